@@ -0,0 +1,388 @@
+// Keeps the dashboard up to date using the server-sent events from
+// /dashboard/events. The tree and inspector are loaded as JSON, and loaded
+// again whenever a patch touches them.
+(function() {
+  "use strict";
+
+  var MAX_PATCHES = 100;
+  var TREE_RELOAD_DELAY = 250;
+
+  var treeElement = document.getElementById("instance-tree");
+  var searchElement = document.getElementById("instance-search");
+  var inspectorElement = document.getElementById("inspector");
+  var diagnosticsElement = document.getElementById("diagnostics");
+  var clientsElement = document.getElementById("clients");
+  var patchesElement = document.getElementById("patches");
+
+  var tree = null;
+  var expanded = {};
+  var selectedId = null;
+  var treeReloadTimeout = null;
+
+  function element(tag, className, text) {
+    var result = document.createElement(tag);
+
+    if (className) {
+      result.className = className;
+    }
+
+    if (text != null) {
+      result.textContent = text;
+    }
+
+    return result;
+  }
+
+  function getJson(url) {
+    // The authentication token, if any, is sent in a cookie.
+    return fetch(url, { credentials: "same-origin" }).then(function(response) {
+      return response.json().then(function(body) {
+        if (!response.ok) {
+          throw new Error(body.details || response.statusText);
+        }
+
+        return body;
+      });
+    });
+  }
+
+  function describe(id) {
+    var instance = tree && tree.instances[id];
+
+    if (!instance) {
+      return "(removed instance)";
+    }
+
+    if (instance.name === instance.className) {
+      return instance.name;
+    }
+
+    return instance.name + " (" + instance.className + ")";
+  }
+
+  // Instance tree
+
+  function loadTree() {
+    getJson("/dashboard/tree").then(function(body) {
+      tree = body;
+
+      if (expanded[tree.rootId] === undefined) {
+        expanded[tree.rootId] = true;
+      }
+
+      renderTree();
+    });
+  }
+
+  function scheduleTreeReload() {
+    if (treeReloadTimeout !== null) {
+      return;
+    }
+
+    treeReloadTimeout = setTimeout(function() {
+      treeReloadTimeout = null;
+      loadTree();
+    }, TREE_RELOAD_DELAY);
+  }
+
+  // Finds the instances that match the search along with their ancestors, so
+  // that matches can be shown where they are in the tree.
+  function searchTree(query) {
+    var visible = {};
+
+    Object.keys(tree.instances).forEach(function(id) {
+      var instance = tree.instances[id];
+      var matches =
+        instance.name.toLowerCase().indexOf(query) !== -1 ||
+        instance.className.toLowerCase().indexOf(query) !== -1;
+
+      while (matches && id && !visible[id]) {
+        visible[id] = true;
+        id = tree.instances[id] && tree.instances[id].parent;
+      }
+    });
+
+    return visible;
+  }
+
+  function renderTree() {
+    if (!tree) {
+      return;
+    }
+
+    var query = searchElement.value.trim().toLowerCase();
+    var visible = query ? searchTree(query) : null;
+
+    treeElement.textContent = "";
+
+    if (visible && !visible[tree.rootId]) {
+      treeElement.appendChild(element("p", "placeholder", "No instances match."));
+      return;
+    }
+
+    var list = element("ul", "tree-list");
+    list.appendChild(renderInstance(tree.rootId, visible));
+    treeElement.appendChild(list);
+  }
+
+  function renderInstance(id, visible) {
+    var instance = tree.instances[id];
+    var item = element("li", "tree-item");
+
+    var children = instance.children.filter(function(childId) {
+      return !visible || visible[childId];
+    });
+
+    // Searching shows every match, so everything on the way is expanded.
+    var isExpanded = visible ? true : expanded[id];
+
+    var row = element("div", "tree-row");
+
+    var toggle = element("span", "tree-toggle", children.length ? (isExpanded ? "▾" : "▸") : "");
+    toggle.addEventListener("click", function(event) {
+      event.stopPropagation();
+      expanded[id] = !expanded[id];
+      renderTree();
+    });
+    row.appendChild(toggle);
+
+    var label = element("span", "tree-label", instance.name);
+    if (instance.className !== instance.name) {
+      label.appendChild(element("span", "tree-class", " " + instance.className));
+    }
+    row.appendChild(label);
+
+    if (id === selectedId) {
+      row.className += " selected";
+    }
+
+    row.addEventListener("click", function() {
+      select(id);
+    });
+
+    item.appendChild(row);
+
+    if (children.length && isExpanded) {
+      var list = element("ul", "tree-list");
+
+      children.forEach(function(childId) {
+        list.appendChild(renderInstance(childId, visible));
+      });
+
+      item.appendChild(list);
+    }
+
+    return item;
+  }
+
+  // Inspector
+
+  function select(id) {
+    selectedId = id;
+
+    // Make sure the selected instance can be seen once the search is cleared.
+    var parent = tree && tree.instances[id] && tree.instances[id].parent;
+    while (parent) {
+      expanded[parent] = true;
+      parent = tree.instances[parent] && tree.instances[parent].parent;
+    }
+
+    renderTree();
+    loadInspector();
+  }
+
+  function loadInspector() {
+    var id = selectedId;
+
+    if (id === null) {
+      return;
+    }
+
+    getJson("/dashboard/instance/" + id).then(
+      function(instance) {
+        if (id === selectedId) {
+          renderInspector(instance);
+        }
+      },
+      function(error) {
+        if (id === selectedId) {
+          inspectorElement.textContent = "";
+          inspectorElement.appendChild(element("p", "placeholder", error.message));
+        }
+      }
+    );
+  }
+
+  function renderInspector(instance) {
+    inspectorElement.textContent = "";
+
+    inspectorElement.appendChild(element("h2", "inspector-name", describe(instance.id)));
+
+    var source = element("div", "inspector-source");
+    source.appendChild(element("span", "stat-name", "Source: "));
+    source.appendChild(element("span", "path", instance.source || "none"));
+
+    if (instance.sourceInstance) {
+      var link = element("a", "inspector-link", " (from " + describe(instance.sourceInstance) + ")");
+      link.href = "#";
+      link.addEventListener("click", function(event) {
+        event.preventDefault();
+        select(instance.sourceInstance);
+      });
+      source.appendChild(link);
+    }
+
+    inspectorElement.appendChild(source);
+
+    inspectorElement.appendChild(
+      element("div", null, "Ignore unknown instances: " + instance.ignoreUnknownInstances)
+    );
+
+    if (instance.relevantPaths.length) {
+      inspectorElement.appendChild(element("div", "stat-name", "Relevant paths:"));
+
+      var paths = element("ul", "path-list");
+      instance.relevantPaths.forEach(function(path) {
+        paths.appendChild(element("li", null, path));
+      });
+      inspectorElement.appendChild(paths);
+    }
+
+    var table = element("table", "properties");
+    instance.properties.forEach(function(property) {
+      var row = element("tr");
+      row.appendChild(element("td", "property-name", property.name));
+      row.appendChild(element("td", "property-type", property.ty));
+
+      var value = element("td", "property-value");
+      value.appendChild(element("pre", null, property.value));
+      row.appendChild(value);
+
+      table.appendChild(row);
+    });
+    inspectorElement.appendChild(table);
+  }
+
+  // Feeds
+
+  function instanceLink(id, text) {
+    var link = element("a", "inspector-link", text);
+    link.href = "#";
+    link.addEventListener("click", function(event) {
+      event.preventDefault();
+      select(id);
+    });
+    return link;
+  }
+
+  function addPatch(patch) {
+    var item = element("li", "patch");
+    item.appendChild(element("span", "patch-time", new Date().toLocaleTimeString() + " "));
+
+    patch.added.forEach(function(added) {
+      var line = element("div", "patch-added", "Added ");
+      line.appendChild(instanceLink(added.id, added.name + " (" + added.className + ")"));
+      item.appendChild(line);
+    });
+
+    if (patch.removed.length) {
+      var count = patch.removed.length;
+      item.appendChild(
+        element("div", "patch-removed", "Removed " + count + (count === 1 ? " instance" : " instances"))
+      );
+    }
+
+    patch.updated.forEach(function(updated) {
+      var changes = updated.changedProperties.slice();
+
+      if (updated.changedClassName) {
+        changes.unshift("ClassName");
+      }
+
+      if (updated.changedName) {
+        changes.unshift("Name");
+      }
+
+      var line = element("div", "patch-updated", "Updated ");
+      line.appendChild(instanceLink(updated.id, updated.name || "(removed instance)"));
+      if (changes.length) {
+        line.appendChild(document.createTextNode(": " + changes.join(", ")));
+      }
+      item.appendChild(line);
+
+      if (updated.id === selectedId) {
+        loadInspector();
+      }
+    });
+
+    if (patch.removed.indexOf(selectedId) !== -1) {
+      loadInspector();
+    }
+
+    patchesElement.insertBefore(item, patchesElement.firstChild);
+
+    while (patchesElement.children.length > MAX_PATCHES) {
+      patchesElement.removeChild(patchesElement.lastChild);
+    }
+  }
+
+  function renderDiagnostics(list) {
+    diagnosticsElement.textContent = "";
+
+    if (!list.diagnostics.length) {
+      diagnosticsElement.appendChild(element("li", "placeholder", "No problems found."));
+      return;
+    }
+
+    list.diagnostics.forEach(function(diagnostic) {
+      var item = element("li", "diagnostic diagnostic-" + diagnostic.severity.toLowerCase());
+      item.appendChild(element("div", "path", diagnostic.path));
+      item.appendChild(element("div", "diagnostic-message", diagnostic.message));
+      diagnosticsElement.appendChild(item);
+    });
+  }
+
+  function renderClients(body) {
+    clientsElement.textContent = "";
+
+    if (!body.clients.length) {
+      clientsElement.appendChild(element("li", "placeholder", "No clients connected."));
+      return;
+    }
+
+    body.clients.forEach(function(client) {
+      var text =
+        (client.address || "unknown address") +
+        (client.placeId != null ? ", place " + client.placeId : "") +
+        ", protocol " + client.protocolVersion +
+        ", connected " + client.connectedSeconds + "s" +
+        (client.subscribed ? ", syncing" : ", idle for " + client.idleSeconds + "s");
+
+      clientsElement.appendChild(element("li", "client", text));
+    });
+  }
+
+  searchElement.addEventListener("input", renderTree);
+
+  var events = new EventSource("/dashboard/events");
+
+  // Patches are only sent once the stream is open, so the tree is loaded
+  // then, including when the stream reconnects.
+  events.addEventListener("open", function() {
+    loadTree();
+    loadInspector();
+  });
+
+  events.addEventListener("patch", function(event) {
+    addPatch(JSON.parse(event.data));
+    scheduleTreeReload();
+  });
+
+  events.addEventListener("diagnostics", function(event) {
+    renderDiagnostics(JSON.parse(event.data));
+  });
+
+  events.addEventListener("clients", function(event) {
+    renderClients(JSON.parse(event.data));
+  });
+})();
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::{fs, path::Path};
 
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use tempfile::{tempdir, TempDir};
@@ -9,10 +9,17 @@ pub fn benchmark_small_place(c: &mut Criterion) {
     bench_build_place(c, "Small Place", "test-projects/benchmark_small_place")
 }
 
-criterion_group!(benches, benchmark_small_place);
+pub fn benchmark_large_project(c: &mut Criterion) {
+    let project = generate_large_project();
+    bench_build_place(c, "Large Project", project.path());
+}
+
+criterion_group!(benches, benchmark_small_place, benchmark_large_project);
 criterion_main!(benches);
 
-fn bench_build_place(c: &mut Criterion, name: &str, path: &str) {
+fn bench_build_place<P: AsRef<Path>>(c: &mut Criterion, name: &str, path: P) {
+    let path = path.as_ref();
+
     let mut group = c.benchmark_group(name);
 
     // 'rojo build' generally takes a fair bit of time to execute.
@@ -36,7 +43,49 @@ fn place_setup<P: AsRef<Path>>(input_path: P) -> (TempDir, BuildCommand) {
     let options = BuildCommand {
         project: input,
         output,
+        watch: false,
+        deterministic: true,
+        trace: None,
     };
 
     (dir, options)
 }
+
+/// Generates a project with a few thousand scripts in it, which is closer to
+/// the size of a real game than the small place.
+fn generate_large_project() -> TempDir {
+    const FOLDERS: usize = 50;
+    const SCRIPTS_PER_FOLDER: usize = 40;
+
+    let dir = tempdir().unwrap();
+
+    fs::write(
+        dir.path().join("default.project.json"),
+        r#"{
+            "name": "benchmark_large_project",
+            "tree": {
+                "$className": "DataModel",
+
+                "ReplicatedStorage": {
+                    "$className": "ReplicatedStorage",
+                    "$path": "src"
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+
+    let source = "local value = 1\n".repeat(200);
+
+    for folder in 0..FOLDERS {
+        let folder_path = dir.path().join("src").join(format!("folder-{}", folder));
+        fs::create_dir_all(&folder_path).unwrap();
+
+        for script in 0..SCRIPTS_PER_FOLDER {
+            let script_path = folder_path.join(format!("module-{}.lua", script));
+            fs::write(script_path, &source).unwrap();
+        }
+    }
+
+    dir
+}
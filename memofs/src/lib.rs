@@ -22,6 +22,9 @@ mod noop_backend;
 mod snapshot;
 mod std_backend;
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
@@ -140,6 +143,11 @@ pub enum VfsEvent {
 /// the public interfaces to this type.
 struct VfsInner {
     backend: Box<dyn VfsBackend>,
+
+    /// The files written or removed through this Vfs, used to tell which
+    /// events were caused by those changes. Written files are stored with a
+    /// hash of their contents, and removed files with `None`.
+    own_changes: HashMap<PathBuf, Option<u64>>,
 }
 
 impl VfsInner {
@@ -153,7 +161,10 @@ impl VfsInner {
     fn write<P: AsRef<Path>, C: AsRef<[u8]>>(&mut self, path: P, contents: C) -> io::Result<()> {
         let path = path.as_ref();
         let contents = contents.as_ref();
-        self.backend.write(path, contents)
+        self.backend.write(path, contents)?;
+        self.own_changes
+            .insert(path.to_path_buf(), Some(hash_contents(contents)));
+        Ok(())
     }
 
     fn read_dir<P: AsRef<Path>>(&mut self, path: P) -> io::Result<ReadDir> {
@@ -166,7 +177,9 @@ impl VfsInner {
     fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
         let path = path.as_ref();
         let _ = self.backend.unwatch(path);
-        self.backend.remove_file(path)
+        self.backend.remove_file(path)?;
+        self.own_changes.insert(path.to_path_buf(), None);
+        Ok(())
     }
 
     fn remove_dir_all<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
@@ -184,6 +197,31 @@ impl VfsInner {
         self.backend.event_receiver()
     }
 
+    fn is_own_change(&mut self, event: &VfsEvent) -> bool {
+        let path = match event {
+            VfsEvent::Create(path) | VfsEvent::Write(path) | VfsEvent::Remove(path) => path,
+        };
+
+        let expected = match self.own_changes.get(path) {
+            Some(expected) => *expected,
+            None => return false,
+        };
+
+        let current = match self.backend.read(path).with_not_found() {
+            Ok(contents) => contents.map(|contents| hash_contents(&contents)),
+            Err(_) => return false,
+        };
+
+        // Once the file has been changed by someone else, later events for it
+        // can't be ours until we change it again.
+        if current == expected {
+            true
+        } else {
+            self.own_changes.remove(path);
+            false
+        }
+    }
+
     fn commit_event(&mut self, event: &VfsEvent) -> io::Result<()> {
         match event {
             VfsEvent::Remove(path) => {
@@ -215,6 +253,7 @@ impl Vfs {
     pub fn new<B: VfsBackend>(backend: B) -> Self {
         let lock = VfsInner {
             backend: Box::new(backend),
+            own_changes: HashMap::new(),
         };
 
         Self {
@@ -303,6 +342,14 @@ impl Vfs {
         self.inner.lock().unwrap().event_receiver()
     }
 
+    /// Tells whether the given event was caused by a write or removal made
+    /// through this `Vfs`, and the file hasn't been changed by anyone else
+    /// since.
+    #[inline]
+    pub fn is_own_change(&self, event: &VfsEvent) -> bool {
+        self.inner.lock().unwrap().is_own_change(event)
+    }
+
     /// Commit an event to this `Vfs`.
     #[inline]
     pub fn commit_event(&self, event: &VfsEvent) -> io::Result<()> {
@@ -396,9 +443,23 @@ impl VfsLock<'_> {
         self.inner.event_receiver()
     }
 
+    /// Tells whether the given event was caused by a write or removal made
+    /// through this `Vfs`, and the file hasn't been changed by anyone else
+    /// since.
+    #[inline]
+    pub fn is_own_change(&mut self, event: &VfsEvent) -> bool {
+        self.inner.is_own_change(event)
+    }
+
     /// Commit an event to this `Vfs`.
     #[inline]
     pub fn commit_event(&mut self, event: &VfsEvent) -> io::Result<()> {
         self.inner.commit_event(event)
     }
 }
+
+fn hash_contents(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
@@ -1,7 +1,8 @@
+use std::collections::HashSet;
 use std::fs;
 use std::io;
-use std::path::Path;
-use std::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
@@ -11,9 +12,21 @@ use notify::{watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher
 use crate::{DirEntry, Metadata, ReadDir, VfsBackend, VfsEvent};
 
 /// `VfsBackend` that uses `std::fs` and the `notify` crate.
+///
+/// Only directories that are read get a watch, which also covers the files
+/// in them, so reading a file doesn't use up another inotify watch on Linux.
+/// Directories that are never read, like ones skipped by ignore globs, aren't
+/// watched at all. Events are filtered down to the paths that have been read
+/// and the direct children of directories that have been read.
 pub struct StdBackend {
     watcher: RecommendedWatcher,
     watcher_receiver: Receiver<VfsEvent>,
+
+    /// Every path that's been read through this backend and hasn't been
+    /// unwatched since, shared with the thread that filters events.
+    read_paths: Arc<Mutex<HashSet<PathBuf>>>,
+
+    watched: WatchedPaths,
 }
 
 impl StdBackend {
@@ -23,21 +36,41 @@ impl StdBackend {
 
         let (tx, rx) = crossbeam_channel::unbounded();
 
+        let read_paths = Arc::new(Mutex::new(HashSet::new()));
+        let thread_read_paths = Arc::clone(&read_paths);
+
         thread::spawn(move || {
+            let is_relevant = |path: &Path| is_relevant(&thread_read_paths.lock().unwrap(), path);
+
             for event in notify_rx {
                 match event {
-                    DebouncedEvent::Create(path) => {
+                    DebouncedEvent::Create(path) if is_relevant(&path) => {
                         tx.send(VfsEvent::Create(path))?;
                     }
-                    DebouncedEvent::Write(path) => {
+                    DebouncedEvent::Write(path) if is_relevant(&path) => {
                         tx.send(VfsEvent::Write(path))?;
                     }
-                    DebouncedEvent::Remove(path) => {
+                    DebouncedEvent::Remove(path) if is_relevant(&path) => {
                         tx.send(VfsEvent::Remove(path))?;
                     }
                     DebouncedEvent::Rename(from, to) => {
-                        tx.send(VfsEvent::Remove(from))?;
-                        tx.send(VfsEvent::Create(to))?;
+                        if is_relevant(&from) {
+                            tx.send(VfsEvent::Remove(from))?;
+                        }
+
+                        if is_relevant(&to) {
+                            tx.send(VfsEvent::Create(to))?;
+                        }
+                    }
+                    DebouncedEvent::Error(err, path) => {
+                        let err = watch_error(err);
+
+                        match path {
+                            Some(path) => {
+                                log::error!("Error watching {}: {}", path.display(), err)
+                            }
+                            None => log::error!("Error watching files: {}", err),
+                        }
                     }
                     _ => {}
                 }
@@ -49,6 +82,8 @@ impl StdBackend {
         Self {
             watcher,
             watcher_receiver: rx,
+            read_paths,
+            watched: WatchedPaths::default(),
         }
     }
 }
@@ -98,14 +133,193 @@ impl VfsBackend for StdBackend {
     }
 
     fn watch(&mut self, path: &Path) -> io::Result<()> {
-        self.watcher
-            .watch(path, RecursiveMode::NonRecursive)
-            .map_err(|inner| io::Error::new(io::ErrorKind::Other, inner))
+        self.read_paths.lock().unwrap().insert(path.to_path_buf());
+
+        if fs_err::metadata(path)?.is_dir() {
+            if let Some(covered_files) = self.watched.add_dir(path) {
+                self.watcher
+                    .watch(path, RecursiveMode::NonRecursive)
+                    .map_err(watch_error)?;
+
+                for file in covered_files {
+                    let _ = self.watcher.unwatch(file);
+                }
+            }
+        } else if self.watched.add_file(path) {
+            self.watcher
+                .watch(path, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+
+        Ok(())
     }
 
     fn unwatch(&mut self, path: &Path) -> io::Result<()> {
-        self.watcher
-            .unwatch(path)
-            .map_err(|inner| io::Error::new(io::ErrorKind::Other, inner))
+        self.read_paths.lock().unwrap().remove(path);
+
+        if self.watched.remove(path) {
+            self.watcher.unwatch(path).map_err(watch_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tells whether an event for the given path should be passed on, which is the
+/// case for paths that have been read and the direct children of directories
+/// that have been read.
+fn is_relevant(read_paths: &HashSet<PathBuf>, path: &Path) -> bool {
+    read_paths.contains(path)
+        || path
+            .parent()
+            .map(|parent| read_paths.contains(parent))
+            .unwrap_or(false)
+}
+
+/// Keeps track of which paths have a watch of their own, separately from the
+/// watcher itself.
+#[derive(Debug, Default)]
+struct WatchedPaths {
+    /// Directories that are watched, which also covers the files directly in
+    /// them.
+    dirs: HashSet<PathBuf>,
+
+    /// Files that are watched on their own because their directory isn't.
+    files: HashSet<PathBuf>,
+}
+
+impl WatchedPaths {
+    /// Records a directory as watched. Returns `None` if it already was, or
+    /// the files that had their own watches and are now covered by the
+    /// directory's.
+    fn add_dir(&mut self, path: &Path) -> Option<Vec<PathBuf>> {
+        if !self.dirs.insert(path.to_path_buf()) {
+            return None;
+        }
+
+        let covered: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|file| file.parent() == Some(path))
+            .cloned()
+            .collect();
+
+        for file in &covered {
+            self.files.remove(file);
+        }
+
+        Some(covered)
+    }
+
+    /// Records a file as watched. Returns whether it needs a watch of its own.
+    fn add_file(&mut self, path: &Path) -> bool {
+        let covered = path
+            .parent()
+            .map(|parent| self.dirs.contains(parent))
+            .unwrap_or(false);
+
+        !covered && self.files.insert(path.to_path_buf())
+    }
+
+    /// Stops tracking a path. Returns whether it had a watch of its own.
+    fn remove(&mut self, path: &Path) -> bool {
+        self.dirs.remove(path) || self.files.remove(path)
+    }
+}
+
+/// The error code Linux uses when adding an inotify watch would go over
+/// `fs.inotify.max_user_watches`.
+#[cfg(target_os = "linux")]
+const ENOSPC: i32 = 28;
+
+/// Converts an error from notify into an `io::Error`, with a clearer message
+/// when the system has run out of file watches.
+fn watch_error(err: notify::Error) -> io::Error {
+    match err {
+        #[cfg(target_os = "linux")]
+        notify::Error::Io(inner) if inner.raw_os_error() == Some(ENOSPC) => io::Error::new(
+            io::ErrorKind::Other,
+            "Ran out of inotify watches while watching files for changes. \
+             Raise the limit with `sysctl fs.inotify.max_user_watches=<number>`.",
+        ),
+        notify::Error::Io(inner) => inner,
+        other => io::Error::new(io::ErrorKind::Other, other),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn directories_cover_their_files() {
+        let mut watched = WatchedPaths::default();
+
+        assert!(watched.add_file(Path::new("/project/default.project.json")));
+        assert!(watched.add_file(Path::new("/project/src/a.lua")));
+
+        // The directory's watch replaces the watch on the file in it, but not
+        // the one in a different directory.
+        assert_eq!(
+            watched.add_dir(Path::new("/project/src")),
+            Some(vec![PathBuf::from("/project/src/a.lua")])
+        );
+        assert_eq!(watched.add_dir(Path::new("/project/src")), None);
+
+        assert!(!watched.add_file(Path::new("/project/src/b.lua")));
+        assert!(watched.add_file(Path::new("/project/src/inner/c.lua")));
+        assert!(!watched.add_file(Path::new("/project/default.project.json")));
+    }
+
+    #[test]
+    fn nested_directories_are_watched_separately() {
+        let mut watched = WatchedPaths::default();
+
+        assert_eq!(watched.add_dir(Path::new("/project/src")), Some(Vec::new()));
+        assert_eq!(
+            watched.add_dir(Path::new("/project/src/inner")),
+            Some(Vec::new())
+        );
+
+        // Removing the inner directory leaves the outer one watched.
+        assert!(watched.remove(Path::new("/project/src/inner")));
+        assert!(!watched.add_file(Path::new("/project/src/a.lua")));
+        assert!(watched.add_file(Path::new("/project/src/inner/b.lua")));
+    }
+
+    #[test]
+    fn unwatching() {
+        let mut watched = WatchedPaths::default();
+
+        watched.add_dir(Path::new("/project/src"));
+        watched.add_file(Path::new("/project/default.project.json"));
+
+        assert!(watched.remove(Path::new("/project/src")));
+        assert!(!watched.remove(Path::new("/project/src")));
+        assert!(watched.remove(Path::new("/project/default.project.json")));
+
+        // Files in a directory that's no longer watched need their own watch.
+        assert!(watched.add_file(Path::new("/project/src/a.lua")));
+    }
+
+    #[test]
+    fn filter_unrelated_events() {
+        let mut read_paths = HashSet::new();
+        read_paths.insert(PathBuf::from("/project/src"));
+        read_paths.insert(PathBuf::from("/project/default.project.json"));
+
+        assert!(is_relevant(&read_paths, Path::new("/project/src")));
+        assert!(is_relevant(&read_paths, Path::new("/project/src/new.lua")));
+        assert!(is_relevant(
+            &read_paths,
+            Path::new("/project/default.project.json")
+        ));
+
+        assert!(!is_relevant(
+            &read_paths,
+            Path::new("/project/src/inner/a.lua")
+        ));
+        assert!(!is_relevant(&read_paths, Path::new("/project/README.md")));
+        assert!(!is_relevant(&read_paths, Path::new("/project/.git/index")));
     }
 }
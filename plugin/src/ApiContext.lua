@@ -10,6 +10,13 @@ local validateApiInfo = Types.ifEnabled(Types.ApiInfoResponse)
 local validateApiRead = Types.ifEnabled(Types.ApiReadResponse)
 local validateApiSubscribe = Types.ifEnabled(Types.ApiSubscribeResponse)
 
+-- How many instances to ask for in each request when reading a whole tree.
+local READ_PAGE_SIZE = 1000
+
+-- Rejection value for paged reads that were interrupted by the server's tree
+-- changing, which need to start over.
+local READ_CONFLICT = newproxy(false)
+
 --[[
 	Returns a promise that will never resolve nor reject.
 ]]
@@ -27,18 +34,39 @@ local function rejectFailedRequests(response)
 	return response
 end
 
+--[[
+	Servers that support more than one protocol version reject requests using
+	one they don't support, with a message explaining what to update.
+]]
+local function rejectUnsupportedProtocolVersion(response)
+	if response.code == 400 then
+		local ok, body = pcall(Http.Response.json, response)
+
+		if ok and type(body) == "table" and body.kind == "UnsupportedProtocolVersion" then
+			return Promise.reject(body.details)
+		end
+	end
+
+	return response
+end
+
 local function rejectWrongProtocolVersion(infoResponseBody)
-	if infoResponseBody.protocolVersion ~= Config.protocolVersion then
+	-- Servers from before protocol versions were negotiated only support the
+	-- one version they report.
+	local minVersion = infoResponseBody.minProtocolVersion or infoResponseBody.protocolVersion
+	local maxVersion = infoResponseBody.maxProtocolVersion or infoResponseBody.protocolVersion
+
+	if Config.protocolVersion < minVersion or Config.protocolVersion > maxVersion then
 		local message = (
 			"Found a Rojo dev server, but it's using a different protocol version, and is incompatible." ..
 			"\nMake sure you have matching versions of both the Rojo plugin and server!" ..
 			"\n\nYour client is version %s, with protocol version %s. It expects server version %s." ..
-			"\nYour server is version %s, with protocol version %s." ..
+			"\nYour server is version %s, with protocol versions %s through %s." ..
 			"\n\nGo to https://github.com/rojo-rbx/rojo for more details."
 		):format(
 			Version.display(Config.version), Config.protocolVersion,
 			Config.expectedServerVersionString,
-			infoResponseBody.serverVersion, infoResponseBody.protocolVersion
+			infoResponseBody.serverVersion, minVersion, maxVersion
 		)
 
 		return Promise.reject(message)
@@ -84,13 +112,25 @@ end
 local ApiContext = {}
 ApiContext.__index = ApiContext
 
-function ApiContext.new(baseUrl)
+function ApiContext.new(baseUrl, authToken)
 	assert(type(baseUrl) == "string")
+	assert(authToken == nil or type(authToken) == "string")
+
+	local headers = {
+		["Rojo-Protocol-Version"] = tostring(Config.protocolVersion),
+		["Rojo-Place-Id"] = tostring(game.PlaceId),
+	}
+
+	if authToken ~= nil then
+		headers.Authorization = "Bearer " .. authToken
+	end
 
 	local self = {
 		__baseUrl = baseUrl,
+		__headers = headers,
 		__sessionId = nil,
 		__messageCursor = -1,
+		__diagnosticsCursor = 0,
 		__connected = true,
 	}
 
@@ -103,6 +143,7 @@ function ApiContext:__fmtDebug(output)
 
 	output:writeLine("Connected: {}", self.__connected)
 	output:writeLine("Base URL: {}", self.__baseUrl)
+	output:writeLine("Authenticated: {}", self.__headers.Authorization ~= nil)
 	output:writeLine("Session ID: {}", self.__sessionId)
 	output:writeLine("Message Cursor: {}", self.__messageCursor)
 
@@ -121,7 +162,8 @@ end
 function ApiContext:connect()
 	local url = ("%s/api/rojo"):format(self.__baseUrl)
 
-	return Http.get(url)
+	return Http.get(url, self.__headers)
+		:andThen(rejectUnsupportedProtocolVersion)
 		:andThen(rejectFailedRequests)
 		:andThen(Http.Response.json)
 		:andThen(rejectWrongProtocolVersion)
@@ -138,10 +180,30 @@ function ApiContext:connect()
 		end)
 end
 
-function ApiContext:read(ids)
+function ApiContext:read(ids, options)
+	options = options or {}
+
 	local url = ("%s/api/read/%s"):format(self.__baseUrl, table.concat(ids, ","))
 
-	return Http.get(url)
+	local query = {}
+	if options.limit ~= nil then
+		table.insert(query, ("limit=%d"):format(options.limit))
+	end
+	if options.continuation ~= nil then
+		table.insert(query, "continuation=" .. options.continuation)
+	end
+	if #query > 0 then
+		url = url .. "?" .. table.concat(query, "&")
+	end
+
+	return Http.get(url, self.__headers)
+		:andThen(function(response)
+			if response.code == 409 and options.continuation ~= nil then
+				return Promise.reject(READ_CONFLICT)
+			end
+
+			return response
+		end)
 		:andThen(rejectFailedRequests)
 		:andThen(Http.Response.json)
 		:andThen(function(body)
@@ -155,6 +217,46 @@ function ApiContext:read(ids)
 		end)
 end
 
+--[[
+	Reads the given instances and all of their descendants a page at a time,
+	starting over if the tree changes partway through.
+]]
+function ApiContext:readAll(ids)
+	local instances = {}
+
+	local function readFrom(continuation)
+		return self:read(ids, {
+			limit = READ_PAGE_SIZE,
+			continuation = continuation,
+		})
+			:andThen(function(body)
+				for id, instance in pairs(body.instances) do
+					instances[id] = instance
+				end
+
+				if body.continuation ~= nil then
+					return readFrom(body.continuation)
+				end
+
+				return {
+					sessionId = body.sessionId,
+					messageCursor = body.messageCursor,
+					instances = instances,
+				}
+			end)
+	end
+
+	return readFrom(nil)
+		:catch(function(err)
+			if err == READ_CONFLICT then
+				Log.trace("Tree changed while reading it, starting over")
+				return self:readAll(ids)
+			end
+
+			return Promise.reject(err)
+		end)
+end
+
 function ApiContext:write(patch)
 	local url = ("%s/api/write"):format(self.__baseUrl)
 
@@ -169,6 +271,10 @@ function ApiContext:write(patch)
 			fixedUpdate.changedProperties = update.changedProperties
 		end
 
+		if update.previousProperties ~= nil and next(update.previousProperties) ~= nil then
+			fixedUpdate.previousProperties = update.previousProperties
+		end
+
 		table.insert(updated, fixedUpdate)
 	end
 
@@ -189,21 +295,50 @@ function ApiContext:write(patch)
 
 	body = Http.jsonEncode(body)
 
-	return Http.post(url, body)
+	return Http.post(url, body, self.__headers)
 		:andThen(rejectFailedRequests)
 		:andThen(Http.Response.json)
 		:andThen(function(body)
 			Log.info("Write response: {:?}", body)
 
+			if body.conflicts ~= nil then
+				for _, conflict in ipairs(body.conflicts) do
+					Log.warn(
+						"Change to property {} of instance {} was rejected because it was changed on the server",
+						conflict.property,
+						conflict.id
+					)
+				end
+			end
+
 			return body
 		end)
 end
 
+--[[
+	Shows the server's current errors and warnings from snapshotting files,
+	which would otherwise only be shown in the terminal running Rojo.
+]]
+local function logDiagnostics(diagnostics)
+	if #diagnostics == 0 then
+		Log.info("Rojo no longer has any problems with your project's files")
+		return
+	end
+
+	for _, diagnostic in ipairs(diagnostics) do
+		Log.warn("Rojo {}: {}\n{}", diagnostic.severity:lower(), diagnostic.path, diagnostic.message)
+	end
+end
+
 function ApiContext:retrieveMessages()
-	local url = ("%s/api/subscribe/%s"):format(self.__baseUrl, self.__messageCursor)
+	local url = ("%s/api/subscribe/%s?diagnostics=%s"):format(
+		self.__baseUrl,
+		self.__messageCursor,
+		self.__diagnosticsCursor
+	)
 
 	local function sendRequest()
-		return Http.get(url)
+		return Http.get(url, self.__headers)
 			:catch(function(err)
 				if err.type == Http.Error.Kind.Timeout then
 					if self.__connected then
@@ -229,6 +364,11 @@ function ApiContext:retrieveMessages()
 
 			self:setMessageCursor(body.messageCursor)
 
+			if body.diagnostics ~= nil and body.diagnostics.cursor ~= self.__diagnosticsCursor then
+				self.__diagnosticsCursor = body.diagnostics.cursor
+				logDiagnostics(body.diagnostics.diagnostics)
+			end
+
 			return body.messages
 		end)
 end
@@ -94,8 +94,8 @@ function App:init()
 		Enum.InitialDockState.Right,
 		false, -- Initially enabled state
 		false, -- Whether to override the widget's previous state
-		360, 190, -- Floating size
-		360, 190 -- Minimum size
+		360, 250, -- Floating size
+		360, 250 -- Minimum size
 	)
 
 	self.dockWidget = self.props.plugin:CreateDockWidgetPluginGui("Rojo-" .. self.displayedVersion, widgetInfo)
@@ -109,12 +109,17 @@ function App:init()
 	end)
 end
 
-function App:startSession(address, port)
+function App:startSession(address, port, authToken)
 	Log.trace("Starting new session")
 
-	local baseUrl = ("http://%s:%s"):format(address, port)
+	-- Addresses can include a scheme to connect to servers using HTTPS.
+	if not address:find("^https?://") then
+		address = "http://" .. address
+	end
+
+	local baseUrl = ("%s:%s"):format(address, port)
 	self.serveSession = ServeSession.new({
-		apiContext = ApiContext.new(baseUrl),
+		apiContext = ApiContext.new(baseUrl, authToken),
 	})
 
 	self.serveSession:onStatusChanged(function(status, details)
@@ -155,8 +160,8 @@ function App:render()
 	if self.state.appStatus == AppStatus.NotStarted then
 		children = {
 			ConnectPanel = e(ConnectPanel, {
-				startSession = function(address, port)
-					self:startSession(address, port)
+				startSession = function(address, port, authToken)
+					self:startSession(address, port, authToken)
 				end,
 				cancel = function()
 					Log.trace("Canceling session configuration")
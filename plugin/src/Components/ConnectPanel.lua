@@ -20,6 +20,7 @@ function ConnectPanel:init()
 	self:setState({
 		address = "",
 		port = "",
+		authToken = "",
 	})
 end
 
@@ -117,13 +118,54 @@ function ConnectPanel:render()
 				}),
 			}),
 
-			Buttons = e(FitList, {
+			AuthToken = e(FitList, {
 				fitAxes = "Y",
 				containerProps = {
 					BackgroundTransparency = 1,
 					LayoutOrder = 2,
 					Size = UDim2.new(1, 0, 0, 0),
 				},
+				layoutProps = {
+					Padding = UDim.new(0, 4),
+				},
+				paddingProps = {
+					PaddingTop = UDim.new(0, 0),
+					PaddingBottom = UDim.new(0, 10),
+					PaddingLeft = UDim.new(0, 24),
+					PaddingRight = UDim.new(0, 24),
+				},
+			}, {
+				Label = e(FitText, {
+					Kind = "TextLabel",
+					LayoutOrder = 1,
+					BackgroundTransparency = 1,
+					TextXAlignment = Enum.TextXAlignment.Left,
+					Font = theme.TitleFont,
+					TextSize = 20,
+					Text = "Token",
+					TextColor3 = theme.Text1,
+				}),
+
+				Input = e(FormTextInput, {
+					layoutOrder = 2,
+					width = UDim.new(0, 308),
+					value = self.state.authToken,
+					placeholderValue = "Only needed with --auth",
+					onValueChange = function(newValue)
+						self:setState({
+							authToken = newValue,
+						})
+					end,
+				}),
+			}),
+
+			Buttons = e(FitList, {
+				fitAxes = "Y",
+				containerProps = {
+					BackgroundTransparency = 1,
+					LayoutOrder = 3,
+					Size = UDim2.new(1, 0, 0, 0),
+				},
 				layoutProps = {
 					FillDirection = Enum.FillDirection.Horizontal,
 					HorizontalAlignment = Enum.HorizontalAlignment.Right,
@@ -151,7 +193,12 @@ function ConnectPanel:render()
 								port = Config.defaultPort
 							end
 
-							startSession(address, port)
+							local authToken = self.state.authToken
+							if authToken:len() == 0 then
+								authToken = nil
+							end
+
+							startSession(address, port, authToken)
 						end
 					end,
 				}),
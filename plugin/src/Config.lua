@@ -7,7 +7,7 @@ return strict("Config", {
 	codename = "Epiphany",
 	version = {0, 6, 0, "-alpha.3"},
 	expectedServerVersionString = "0.6.0 or newer",
-	protocolVersion = 3,
+	protocolVersion = 4,
 	defaultHost = "localhost",
 	defaultPort = 34872,
 })
\ No newline at end of file
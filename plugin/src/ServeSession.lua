@@ -1,10 +1,12 @@
 local Log = require(script.Parent.Parent.Log)
 local Fmt = require(script.Parent.Parent.Fmt)
+local Promise = require(script.Parent.Parent.Promise)
 local t = require(script.Parent.Parent.t)
 
 local DevSettings = require(script.Parent.DevSettings)
 local InstanceMap = require(script.Parent.InstanceMap)
 local Reconciler = require(script.Parent.Reconciler)
+local applyTextPatch = require(script.Parent.applyTextPatch)
 local strict = require(script.Parent.strict)
 
 local Status = strict("Session.Status", {
@@ -36,6 +38,34 @@ local function debugPatch(patch)
 	end)
 end
 
+-- Records the property values that the server has told us about, which are
+-- sent back with changes so that the server can detect conflicting edits.
+local function rememberProperties(knownProperties, patch)
+	for _, id in ipairs(patch.removed) do
+		knownProperties[id] = nil
+	end
+
+	for id, added in pairs(patch.added) do
+		local properties = {}
+
+		for propertyName, value in pairs(added.Properties) do
+			properties[propertyName] = value
+		end
+
+		knownProperties[id] = properties
+	end
+
+	for _, update in ipairs(patch.updated) do
+		local properties = knownProperties[update.id]
+
+		if properties ~= nil and update.changedProperties ~= nil then
+			for propertyName, value in pairs(update.changedProperties) do
+				properties[propertyName] = value
+			end
+		end
+	end
+end
+
 local ServeSession = {}
 ServeSession.__index = ServeSession
 
@@ -62,6 +92,7 @@ function ServeSession.new(options)
 		__apiContext = options.apiContext,
 		__reconciler = reconciler,
 		__instanceMap = instanceMap,
+		__knownProperties = {},
 		__statusChangedCallback = nil,
 	}
 
@@ -125,6 +156,7 @@ function ServeSession:__onInstanceChanged(instance, propertyName)
 	local update = {
 		id = instanceId,
 		changedProperties = {},
+		previousProperties = {},
 	}
 
 	if propertyName == "Name" then
@@ -146,6 +178,12 @@ function ServeSession:__onInstanceChanged(instance, propertyName)
 		end
 
 		update.changedProperties[propertyName] = encoded
+
+		local knownProperties = self.__knownProperties[instanceId]
+		if knownProperties ~= nil and knownProperties[propertyName] ~= nil then
+			update.previousProperties[propertyName] = knownProperties[propertyName]
+			knownProperties[propertyName] = encoded
+		end
 	end
 
 	local patch = {
@@ -158,12 +196,18 @@ function ServeSession:__onInstanceChanged(instance, propertyName)
 end
 
 function ServeSession:__initialSync(rootInstanceId)
-	return self.__apiContext:read({ rootInstanceId })
+	return self.__apiContext:readAll({ rootInstanceId })
 		:andThen(function(readResponseBody)
 			-- Tell the API Context that we're up-to-date with the version of
 			-- the tree defined in this response.
 			self.__apiContext:setMessageCursor(readResponseBody.messageCursor)
 
+			rememberProperties(self.__knownProperties, {
+				removed = {},
+				added = readResponseBody.instances,
+				updated = {},
+			})
+
 			Log.trace("Computing changes that plugin needs to make to catch up to server...")
 
 			-- Calculate the initial patch to apply to the DataModel to catch us
@@ -186,16 +230,102 @@ end
 function ServeSession:__mainSyncLoop()
 	return self.__apiContext:retrieveMessages()
 		:andThen(function(messages)
-			for _, message in ipairs(messages) do
-				self.__reconciler:applyPatch(message)
-			end
-
+			return self:__applyMessages(messages, 1)
+		end)
+		:andThen(function()
 			if self.__status ~= Status.Disconnected then
 				return self:__mainSyncLoop()
 			end
 		end)
 end
 
+--[[
+	Applies messages one at a time, starting from the given index. Text patches
+	in a message are made against the values from the messages before it, so
+	those need to be applied first.
+]]
+function ServeSession:__applyMessages(messages, index)
+	local message = messages[index]
+
+	if message == nil then
+		return Promise.resolve()
+	end
+
+	return self:__resolveTextPatches(message)
+		:andThen(function()
+			rememberProperties(self.__knownProperties, message)
+			self.__reconciler:applyPatch(message)
+
+			return self:__applyMessages(messages, index + 1)
+		end)
+end
+
+--[[
+	Turns properties that the server sent as text patches into full values by
+	applying them to the last values the server sent us.
+
+	If we don't have the value a patch was made against, like when we changed
+	it ourselves, the instance is read from the server again instead.
+]]
+function ServeSession:__resolveTextPatches(message)
+	local unresolvedIds = {}
+	local unresolved = {}
+
+	for _, update in ipairs(message.updated) do
+		if update.patchedProperties ~= nil then
+			local knownProperties = self.__knownProperties[update.id] or {}
+
+			for propertyName, patch in pairs(update.patchedProperties) do
+				local previous = knownProperties[propertyName]
+				local value
+
+				if previous ~= nil and previous.Type == "String" then
+					value = applyTextPatch(previous.Value, patch)
+				end
+
+				if value ~= nil then
+					update.changedProperties[propertyName] = {
+						Type = "String",
+						Value = value,
+					}
+				else
+					if unresolved[update.id] == nil then
+						unresolved[update.id] = {}
+						table.insert(unresolvedIds, update.id)
+					end
+
+					table.insert(unresolved[update.id], {
+						update = update,
+						propertyName = propertyName,
+					})
+				end
+			end
+
+			update.patchedProperties = nil
+		end
+	end
+
+	if #unresolvedIds == 0 then
+		return Promise.resolve()
+	end
+
+	Log.trace("Reading instances with text patches that could not be applied: {:?}", unresolvedIds)
+
+	return self.__apiContext:read(unresolvedIds)
+		:andThen(function(readResponseBody)
+			for id, entries in pairs(unresolved) do
+				local instance = readResponseBody.instances[id]
+
+				-- If the instance is gone, a later message will remove it.
+				if instance ~= nil then
+					for _, entry in ipairs(entries) do
+						entry.update.changedProperties[entry.propertyName] = instance.Properties[entry.propertyName]
+					end
+				end
+			end
+		end)
+end
+
 function ServeSession:__stopInternal(err)
 	self:__setStatus(Status.Disconnected, err)
 	self.__apiContext:disconnect()
@@ -24,12 +24,23 @@ local ApiInstance = t.interface({
 	Children = t.array(RbxId),
 })
 
+local ApiTextPatch = t.interface({
+	baseLength = t.number,
+	baseHash = t.number,
+	edits = t.array(t.interface({
+		start = t.number,
+		length = t.number,
+		text = t.string,
+	})),
+})
+
 local ApiInstanceUpdate = t.interface({
 	id = RbxId,
 	changedName = t.optional(t.string),
 	changedClassName = t.optional(t.string),
 	changedProperties = t.map(t.string, ApiValue),
 	changedMetadata = t.optional(ApiInstanceMetadata),
+	patchedProperties = t.optional(t.map(t.string, ApiTextPatch)),
 })
 
 local ApiSubscribeMessage = t.interface({
@@ -42,6 +53,8 @@ local ApiInfoResponse = t.interface({
 	sessionId = t.string,
 	serverVersion = t.string,
 	protocolVersion = t.number,
+	minProtocolVersion = t.optional(t.number),
+	maxProtocolVersion = t.optional(t.number),
 	expectedPlaceIds = t.optional(t.array(t.number)),
 	rootInstanceId = RbxId,
 })
@@ -50,12 +63,25 @@ local ApiReadResponse = t.interface({
 	sessionId = t.string,
 	messageCursor = t.number,
 	instances = t.map(RbxId, ApiInstance),
+	continuation = t.optional(t.string),
+})
+
+local ApiDiagnostic = t.interface({
+	severity = t.union(t.literal("Error"), t.literal("Warning")),
+	path = t.string,
+	message = t.string,
+})
+
+local ApiDiagnosticList = t.interface({
+	cursor = t.number,
+	diagnostics = t.array(ApiDiagnostic),
 })
 
 local ApiSubscribeResponse = t.interface({
 	sessionId = t.string,
 	messageCursor = t.number,
 	messages = t.array(ApiSubscribeMessage),
+	diagnostics = t.optional(ApiDiagnosticList),
 })
 
 local ApiError = t.interface({
@@ -90,6 +116,7 @@ return strict("Types", {
 	ApiInstanceMetadata = ApiInstanceMetadata,
 	ApiSubscribeMessage = ApiSubscribeMessage,
 	ApiValue = ApiValue,
+	ApiDiagnostic = ApiDiagnostic,
 	RbxId = RbxId,
 
 	-- Deprecated aliases during transition
@@ -0,0 +1,44 @@
+--[[
+	Hashes a string with 32-bit FNV-1a, the same way the server does.
+
+	Multiplying by the FNV prime directly could need more precision than a
+	double has, so it's split into multiplying by 2^24 and by 403.
+]]
+local function fnv1a(value)
+	local hash = 2166136261
+
+	for index = 1, #value do
+		hash = bit32.bxor(hash, value:byte(index))
+		hash = (hash * 403 + (hash % 256) * 16777216) % 4294967296
+	end
+
+	return hash
+end
+
+--[[
+	Applies a patch that the server sent for a string property to the value
+	that property had before.
+
+	Returns nil if the patch was made against a different value, which can
+	happen when the value was changed in Studio after the server last sent it.
+]]
+local function applyTextPatch(value, patch)
+	if #value ~= patch.baseLength or fnv1a(value) ~= patch.baseHash then
+		return nil
+	end
+
+	local pieces = {}
+	local position = 0
+
+	for _, edit in ipairs(patch.edits) do
+		table.insert(pieces, value:sub(position + 1, edit.start))
+		table.insert(pieces, edit.text)
+		position = edit.start + edit.length
+	end
+
+	table.insert(pieces, value:sub(position + 1))
+
+	return table.concat(pieces)
+end
+
+return applyTextPatch
@@ -0,0 +1,50 @@
+return function()
+	local applyTextPatch = require(script.Parent.applyTextPatch)
+
+	it("should apply edits in order", function()
+		local patch = {
+			baseLength = 8,
+			baseHash = 1371335913,
+			edits = {
+				{ start = 0, length = 2, text = "x\n" },
+				{ start = 6, length = 2, text = "y\nz\n" },
+			},
+		}
+
+		expect(applyTextPatch("a\nb\nc\nd\n", patch)).to.equal("x\nb\nc\ny\nz\n")
+	end)
+
+	it("should apply an empty patch", function()
+		local patch = {
+			baseLength = 3,
+			baseHash = 440920331,
+			edits = {},
+		}
+
+		expect(applyTextPatch("abc", patch)).to.equal("abc")
+	end)
+
+	it("should reject values of the wrong length", function()
+		local patch = {
+			baseLength = 4,
+			baseHash = 440920331,
+			edits = {
+				{ start = 0, length = 1, text = "b" },
+			},
+		}
+
+		expect(applyTextPatch("abc", patch)).to.equal(nil)
+	end)
+
+	it("should reject values of the right length with different contents", function()
+		local patch = {
+			baseLength = 3,
+			baseHash = 440920331,
+			edits = {
+				{ start = 0, length = 1, text = "b" },
+			},
+		}
+
+		expect(applyTextPatch("abd", patch)).to.equal(nil)
+	end)
+end
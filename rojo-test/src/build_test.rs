@@ -62,6 +62,11 @@ fn build_rbxmx_ref() {
     run_build_test("rbxmx_ref.rbxmx");
 }
 
+#[test]
+fn build_rbxmx_shared_strings() {
+    run_build_test("rbxmx_shared_strings.rbxmx");
+}
+
 fn run_build_test(test_name: &str) {
     let build_test_path = get_build_tests_path();
     let working_dir = get_working_dir_path();
@@ -1,22 +1,103 @@
 use std::{
-    fs,
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    slice,
     sync::{Arc, Mutex},
 };
 
 use crossbeam_channel::{select, Receiver, RecvError, Sender};
+use futures::sync::oneshot;
 use jod_thread::JoinHandle;
 use memofs::{IoResultExt, Vfs, VfsEvent};
 use rbx_dom_weak::{RbxId, RbxValue};
+use snafu::{ResultExt, Snafu};
 
 use crate::{
+    diagnostics::{capture_warnings, Diagnostic, Diagnostics, Severity},
     error::ErrorDisplay,
     message_queue::MessageQueue,
+    metrics,
     snapshot::{
-        apply_patch_set, compute_patch_set, AppliedPatchSet, InstigatingSource, PatchSet, RojoTree,
+        apply_patch_set, compute_patch_set, validate_patch_set, AppliedPatchSet, InstanceSnapshot,
+        InstigatingSource, PatchError, PatchSet, RojoTree,
     },
-    snapshot_middleware::{snapshot_from_vfs, snapshot_project_node},
+    snapshot_middleware::{
+        compute_project_patch, snapshot_from_vfs, snapshot_project_node, SnapshotError,
+    },
+    trace,
+    write_sandbox::WriteSandbox,
 };
 
+/// A request from a client, like the Rojo Studio plugin, to change the tree.
+pub struct TreeMutation {
+    pub patch_set: PatchSet,
+
+    /// The values of properties that the client saw before it changed them,
+    /// keyed by instance ID. If the value of one of these properties in the
+    /// tree no longer matches, it was changed by someone else in the meantime,
+    /// and the client's change is dropped as a conflict.
+    pub previous_properties: HashMap<RbxId, HashMap<String, Option<RbxValue>>>,
+
+    /// If set, receives the list of conflicts found while applying the patch,
+    /// or the reason that the patch couldn't be applied at all.
+    pub response_sender: Option<oneshot::Sender<Result<Vec<PropertyConflict>, TreeMutationError>>>,
+}
+
+/// The reasons that a `TreeMutation` couldn't be applied.
+#[derive(Debug, Snafu)]
+pub enum TreeMutationError {
+    #[snafu(display("Cannot apply changes from clients while sync is paused"))]
+    SyncPaused,
+
+    #[snafu(display("{}", source))]
+    InvalidPatch { source: PatchError },
+}
+
+/// A request to pause, resume, or redo syncing changes from the filesystem.
+pub struct SyncControl {
+    pub command: SyncCommand,
+
+    /// If set, receives the state of syncing once the command is done.
+    pub response_sender: Option<oneshot::Sender<SyncStatus>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncCommand {
+    /// Stops applying changes from the filesystem to the tree. Changed paths
+    /// are remembered until syncing is resumed.
+    Pause,
+
+    /// Starts applying changes again, snapshotting everything affected by
+    /// changes made while paused at once.
+    Resume,
+
+    /// Snapshots the whole project again and applies the difference to the
+    /// tree, which picks up any changes whose events were missed.
+    Resync,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncStatus {
+    pub paused: bool,
+
+    /// How many changed paths are waiting for syncing to be resumed.
+    pub buffered_paths: usize,
+}
+
+/// A property change from a client that was dropped because the property had
+/// changed since the client last saw it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyConflict {
+    pub id: RbxId,
+    pub property: String,
+
+    /// The value that the client expected the property to have.
+    pub expected_value: Option<RbxValue>,
+
+    /// The value that the property actually had.
+    pub current_value: Option<RbxValue>,
+}
+
 /// Owns the connection between Rojo's VFS and its DOM by holding onto another
 /// thread that processes messages.
 ///
@@ -42,20 +123,31 @@ pub struct ChangeProcessor {
 }
 
 impl ChangeProcessor {
-    /// Spin up the ChangeProcessor, connecting it to the given tree, VFS, and
-    /// outbound message queue.
+    /// Spin up the ChangeProcessor, connecting it to the given tree, VFS,
+    /// outbound message queue, and diagnostics. Changes to the filesystem
+    /// requested by clients go through the given sandbox. Full resyncs
+    /// snapshot the project again starting at `start_path`.
+    #[allow(clippy::too_many_arguments)]
     pub fn start(
         tree: Arc<Mutex<RojoTree>>,
         vfs: Arc<Vfs>,
         message_queue: Arc<MessageQueue<AppliedPatchSet>>,
-        tree_mutation_receiver: Receiver<PatchSet>,
+        diagnostics: Arc<Diagnostics>,
+        tree_mutation_receiver: Receiver<TreeMutation>,
+        sync_control_receiver: Receiver<SyncControl>,
+        write_sandbox: WriteSandbox,
+        start_path: PathBuf,
     ) -> Self {
         let (shutdown_sender, shutdown_receiver) = crossbeam_channel::bounded(1);
         let vfs_receiver = vfs.event_receiver();
-        let task = JobThreadContext {
+        let mut task = JobThreadContext {
             tree,
             vfs,
+            write_sandbox,
             message_queue,
+            diagnostics,
+            start_path,
+            paused_paths: None,
         };
 
         let job_thread = jod_thread::Builder::new()
@@ -77,8 +169,11 @@ impl ChangeProcessor {
                         recv(vfs_receiver) -> event => {
                             task.handle_vfs_event(event?);
                         },
-                        recv(tree_mutation_receiver) -> patch_set => {
-                            task.handle_tree_event(patch_set?);
+                        recv(tree_mutation_receiver) -> mutation => {
+                            task.handle_tree_event(mutation?);
+                        },
+                        recv(sync_control_receiver) -> control => {
+                            task.handle_sync_control(control?);
                         },
                         recv(shutdown_receiver) -> _ => {
                             log::trace!("ChangeProcessor shutdown signal received...");
@@ -115,156 +210,457 @@ struct JobThreadContext {
     /// A handle to the VFS we're managing.
     vfs: Arc<Vfs>,
 
+    /// Checks and backs up the changes that clients make to the filesystem.
+    write_sandbox: WriteSandbox,
+
     /// Whenever changes are applied to the DOM, we should push those changes
     /// into this message queue to inform any connected clients.
     message_queue: Arc<MessageQueue<AppliedPatchSet>>,
+
+    /// Problems found while snapshotting files, which are kept up to date as
+    /// files are snapshotted again.
+    diagnostics: Arc<Diagnostics>,
+
+    /// The path that the session was started from, which is snapshotted again
+    /// when a full resync is requested.
+    start_path: PathBuf,
+
+    /// If syncing is paused, the paths that have changed since it was.
+    paused_paths: Option<BTreeSet<PathBuf>>,
 }
 
 impl JobThreadContext {
-    fn handle_vfs_event(&self, event: VfsEvent) {
+    fn handle_vfs_event(&mut self, event: VfsEvent) {
         log::trace!("Vfs event: {:?}", event);
 
+        metrics::VFS_EVENTS.increment(match &event {
+            VfsEvent::Create(_) => "create",
+            VfsEvent::Write(_) => "write",
+            VfsEvent::Remove(_) => "remove",
+            _ => "other",
+        });
+
         // Update the VFS immediately with the event.
         self.vfs
             .commit_event(&event)
             .expect("Error applying VFS change");
 
+        // Changes that we wrote to the filesystem ourselves on behalf of a
+        // client are already in the tree, so snapshotting them again would
+        // only echo them back to the client.
+        if self.vfs.is_own_change(&event) {
+            log::trace!("Ignoring event caused by our own change: {:?}", event);
+            return;
+        }
+
+        let path = match event {
+            VfsEvent::Create(path) | VfsEvent::Write(path) | VfsEvent::Remove(path) => path,
+            _ => {
+                log::warn!("Unhandled VFS event: {:?}", event);
+                return;
+            }
+        };
+
+        if let Some(paused_paths) = &mut self.paused_paths {
+            log::trace!("Sync is paused, holding onto {}", path.display());
+            paused_paths.insert(path);
+            return;
+        }
+
+        let _span = trace::span_with_path("handle_vfs_event", &path);
+
         // For a given VFS event, we might have many changes to different parts
         // of the tree. Calculate and apply all of these changes.
-        let applied_patches = {
-            let mut tree = self.tree.lock().unwrap();
-            let mut applied_patches = Vec::new();
-
-            match event {
-                VfsEvent::Create(path) | VfsEvent::Write(path) | VfsEvent::Remove(path) => {
-                    // Find the nearest ancestor to this path that has
-                    // associated instances in the tree. This helps make sure
-                    // that we handle additions correctly, especially if we
-                    // receive events for descendants of a large tree being
-                    // created all at once.
-                    let mut current_path = path.as_path();
-                    let affected_ids = loop {
-                        let ids = tree.get_ids_at_path(&current_path);
-
-                        log::trace!("Path {} affects IDs {:?}", current_path.display(), ids);
-
-                        if !ids.is_empty() {
-                            break ids.to_vec();
-                        }
+        let applied_patches = self.update_paths(&[path]);
 
-                        log::trace!("Trying parent path...");
-                        match current_path.parent() {
-                            Some(parent) => current_path = parent,
-                            None => break Vec::new(),
-                        }
-                    };
+        // Notify anyone listening to the message queue about the changes we
+        // just made.
+        self.message_queue.push_messages(&applied_patches);
+    }
 
-                    for id in affected_ids {
-                        if let Some(patch) = compute_and_apply_changes(&mut tree, &self.vfs, id) {
-                            applied_patches.push(patch);
-                        }
+    /// Snapshots every instance affected by changes to the given paths again,
+    /// returning the patches that changed anything.
+    fn update_paths(&self, paths: &[PathBuf]) -> Vec<AppliedPatchSet> {
+        let mut tree = self.tree.lock().unwrap();
+        let mut affected_ids = Vec::new();
+        let mut seen_ids = HashSet::new();
+
+        for path in paths {
+            // Every instance with a relevant path at or under this path needs
+            // to be updated, which handles directories being removed or moved
+            // all at once.
+            let mut ids = tree.get_ids_under_path(path);
+
+            // If there aren't any, find the nearest ancestor to this path that
+            // has associated instances in the tree. This helps make sure that
+            // we handle additions correctly, especially if we receive events
+            // for descendants of a large tree being created all at once.
+            if ids.is_empty() {
+                ids = tree.get_ids_at_nearest_ancestor(path).to_vec();
+            }
+
+            log::trace!("Path {} affects IDs {:?}", path.display(), ids);
+
+            affected_ids.extend(ids.into_iter().filter(|&id| seen_ids.insert(id)));
+        }
+
+        let mut applied_patches = Vec::new();
+
+        for id in remove_descendant_ids(&tree, affected_ids) {
+            let patch = compute_and_apply_changes(&mut tree, &self.vfs, &self.diagnostics, id);
+
+            if let Some(patch) = patch {
+                if !patch.is_empty() {
+                    applied_patches.push(patch);
+                }
+            }
+        }
+
+        applied_patches
+    }
+
+    fn handle_sync_control(&mut self, control: SyncControl) {
+        match control.command {
+            SyncCommand::Pause => {
+                if self.paused_paths.is_none() {
+                    log::info!("Sync paused, changes to files will be applied once it resumes");
+                    self.paused_paths = Some(BTreeSet::new());
+                }
+            }
+            SyncCommand::Resume => {
+                if let Some(paused_paths) = self.paused_paths.take() {
+                    log::info!(
+                        "Sync resumed, applying changes to {} paths",
+                        paused_paths.len()
+                    );
+
+                    let _span = trace::span("resume_sync");
+                    let paths: Vec<_> = paused_paths.into_iter().collect();
+
+                    // Everything that changed while paused is sent to clients
+                    // as one patch, like it all changed at the same time.
+                    let mut combined_patch = AppliedPatchSet::new();
+                    for patch in self.update_paths(&paths) {
+                        combined_patch.append(patch);
+                    }
+
+                    if !combined_patch.is_empty() {
+                        self.message_queue.push_messages(&[combined_patch]);
                     }
                 }
-                _ => log::warn!("Unhandled VFS event: {:?}", event),
             }
+            SyncCommand::Resync => {
+                log::info!("Resyncing the whole project");
 
-            applied_patches
-        };
+                // Paths changed while paused are covered by the resync, but
+                // syncing stays paused if it was.
+                if let Some(paused_paths) = &mut self.paused_paths {
+                    paused_paths.clear();
+                }
 
-        // Notify anyone listening to the message queue about the changes we
-        // just made.
-        self.message_queue.push_messages(&applied_patches);
+                if let Some(patch) = self.resync() {
+                    if !patch.is_empty() {
+                        self.message_queue.push_messages(&[patch]);
+                    }
+                }
+            }
+        }
+
+        if let Some(sender) = control.response_sender {
+            // The client might have gone away already, which is fine.
+            let _ = sender.send(SyncStatus {
+                paused: self.paused_paths.is_some(),
+                buffered_paths: self.paused_paths.as_ref().map_or(0, BTreeSet::len),
+            });
+        }
     }
 
-    fn handle_tree_event(&self, patch_set: PatchSet) {
+    /// Snapshots the project again from the path the session started at and
+    /// applies the difference between it and the tree.
+    fn resync(&self) -> Option<AppliedPatchSet> {
+        let _span = trace::span_with_path("resync", &self.start_path);
+
+        let mut tree = self.tree.lock().unwrap();
+        let root_id = tree.get_root_id();
+        let context = tree
+            .get_metadata(root_id)
+            .expect("metadata missing for root instance")
+            .context
+            .clone();
+
+        let (result, warnings) =
+            capture_warnings(|| snapshot_from_vfs(&context, &self.vfs, &self.start_path));
+
+        // Errors that don't name a file are reported for the start path.
+        let mut sources = vec![self.start_path.clone()];
+        sources.extend(snapshotted_paths(&tree, root_id, &result));
+
+        let snapshot = record_diagnostics(&self.diagnostics, &sources, result, warnings)?
+            .expect("snapshot did not return an instance");
+
+        let patch_set = compute_patch_set(snapshot, &tree, root_id);
+        log_patch_error(root_id, apply_patch_set(&mut tree, patch_set))
+    }
+
+    fn handle_tree_event(&self, mutation: TreeMutation) {
+        let TreeMutation {
+            patch_set,
+            previous_properties,
+            response_sender,
+        } = mutation;
+
         log::trace!("Applying PatchSet from client: {:#?}", patch_set);
 
-        let applied_patch = {
-            let mut tree = self.tree.lock().unwrap();
-
-            for &id in &patch_set.removed_instances {
-                if let Some(instance) = tree.get_instance(id) {
-                    if let Some(instigating_source) = &instance.metadata().instigating_source {
-                        match instigating_source {
-                            InstigatingSource::Path(path) => fs::remove_file(path).unwrap(),
-                            InstigatingSource::ProjectNode(_, _, _) => {
-                                log::warn!(
-                                    "Cannot remove instance {}, it's from a project file",
-                                    id
-                                );
-                            }
+        // Changes from clients would be written to files that are probably in
+        // the middle of changing, like during a rebase.
+        if self.paused_paths.is_some() {
+            log::warn!("Ignoring changes from client while sync is paused");
+
+            if let Some(sender) = response_sender {
+                let _ = sender.send(Err(TreeMutationError::SyncPaused));
+            }
+
+            return;
+        }
+
+        let response = match self
+            .apply_tree_mutation(patch_set, &previous_properties)
+            .context(InvalidPatch)
+        {
+            Ok((applied_patch, conflicts)) => {
+                if !applied_patch.is_empty() {
+                    self.message_queue.push_messages(&[applied_patch]);
+                }
+
+                Ok(conflicts)
+            }
+            Err(err) => {
+                log::error!("Could not apply changes from client: {}", err);
+                Err(err)
+            }
+        };
+
+        if let Some(sender) = response_sender {
+            // The client might have gone away already, which is fine.
+            let _ = sender.send(response);
+        }
+    }
+
+    /// Applies a patch from a client to the tree and filesystem, minus any
+    /// changes that conflict with changes made since the client last saw the
+    /// tree. Nothing is changed if the patch doesn't pass validation.
+    fn apply_tree_mutation(
+        &self,
+        mut patch_set: PatchSet,
+        previous_properties: &HashMap<RbxId, HashMap<String, Option<RbxValue>>>,
+    ) -> Result<(AppliedPatchSet, Vec<PropertyConflict>), PatchError> {
+        let mut tree = self.tree.lock().unwrap();
+
+        let conflicts = remove_conflicts(&tree, &mut patch_set, previous_properties);
+
+        for conflict in &conflicts {
+            log::warn!(
+                "Ignoring change to property {} of instance {} from client: \
+                 it was changed by someone else in the meantime.",
+                conflict.property,
+                conflict.id
+            );
+        }
+
+        validate_patch_set(&tree, &patch_set)?;
+
+        // Changes that can't be made to the filesystem are left out of the
+        // tree too, so that the two don't disagree.
+        patch_set.removed_instances.retain(|&id| {
+            let instance = match tree.get_instance(id) {
+                Some(instance) => instance,
+                None => {
+                    log::warn!("Cannot remove instance {}, it does not exist.", id);
+                    return false;
+                }
+            };
+
+            match &instance.metadata().instigating_source {
+                Some(InstigatingSource::Path(path)) => {
+                    match self.write_sandbox.remove_file(&self.vfs, path) {
+                        Ok(()) => true,
+                        Err(err) => {
+                            log::error!("Cannot remove instance {}: {}", id, err);
+                            false
                         }
-                    } else {
-                        // TODO
-                        log::warn!(
-                            "Cannot remove instance {}, it is not an instigating source.",
-                            id
-                        );
                     }
-                } else {
-                    log::warn!("Cannot remove instance {}, it does not exist.", id);
+                }
+                Some(InstigatingSource::ProjectNode(_, _, _)) => {
+                    log::warn!("Cannot remove instance {}, it's from a project file", id);
+                    false
+                }
+                None => {
+                    // TODO
+                    log::warn!(
+                        "Cannot remove instance {}, it is not an instigating source.",
+                        id
+                    );
+                    false
                 }
             }
+        });
 
-            for update in &patch_set.updated_instances {
-                let id = update.id;
+        for update in &mut patch_set.updated_instances {
+            let id = update.id;
+            let mut failed_write = false;
 
-                if let Some(instance) = tree.get_instance(id) {
-                    if update.changed_name.is_some() {
-                        log::warn!("Cannot rename instances yet.");
-                    }
+            if let Some(instance) = tree.get_instance(id) {
+                if update.changed_name.is_some() {
+                    log::warn!("Cannot rename instances yet.");
+                }
 
-                    if update.changed_class_name.is_some() {
-                        log::warn!("Cannot change ClassName yet.");
-                    }
+                if update.changed_class_name.is_some() {
+                    log::warn!("Cannot change ClassName yet.");
+                }
 
-                    if update.changed_metadata.is_some() {
-                        log::warn!("Cannot change metadata yet.");
-                    }
+                if update.changed_metadata.is_some() {
+                    log::warn!("Cannot change metadata yet.");
+                }
 
-                    for (key, changed_value) in &update.changed_properties {
-                        if key == "Source" {
-                            if let Some(instigating_source) =
-                                &instance.metadata().instigating_source
-                            {
-                                match instigating_source {
-                                    InstigatingSource::Path(path) => {
-                                        if let Some(RbxValue::String { value }) = changed_value {
-                                            fs::write(path, value).unwrap();
-                                        } else {
-                                            log::warn!("Cannot change Source to non-string value.");
-                                        }
-                                    }
-                                    InstigatingSource::ProjectNode(_, _, _) => {
-                                        log::warn!(
-                                            "Cannot remove instance {}, it's from a project file",
-                                            id
+                for (key, changed_value) in &update.changed_properties {
+                    if key == "Source" {
+                        if let Some(instigating_source) = &instance.metadata().instigating_source {
+                            match instigating_source {
+                                InstigatingSource::Path(path) => {
+                                    if let Some(RbxValue::String { value }) = changed_value {
+                                        let result = self.write_sandbox.write(
+                                            &self.vfs,
+                                            path,
+                                            value.as_bytes(),
                                         );
+
+                                        if let Err(err) = result {
+                                            log::error!("Cannot update instance {}: {}", id, err);
+                                            failed_write = true;
+                                        }
+                                    } else {
+                                        log::warn!("Cannot change Source to non-string value.");
                                     }
                                 }
-                            } else {
-                                log::warn!(
-                                    "Cannot update instance {}, it is not an instigating source.",
-                                    id
-                                );
+                                InstigatingSource::ProjectNode(_, _, _) => {
+                                    log::warn!(
+                                        "Cannot remove instance {}, it's from a project file",
+                                        id
+                                    );
+                                }
                             }
                         } else {
-                            log::warn!("Cannot change properties besides BaseScript.Source.");
+                            log::warn!(
+                                "Cannot update instance {}, it is not an instigating source.",
+                                id
+                            );
                         }
+                    } else {
+                        log::warn!("Cannot change properties besides BaseScript.Source.");
                     }
-                } else {
-                    log::warn!("Cannot update instance {}, it does not exist.", id);
                 }
+            } else {
+                log::warn!("Cannot update instance {}, it does not exist.", id);
+            }
+
+            if failed_write {
+                update.changed_properties.remove("Source");
+            }
+        }
+
+        let applied_patch = apply_patch_set(&mut tree, patch_set)?;
+
+        Ok((applied_patch, conflicts))
+    }
+}
+
+/// Filters out any IDs that have an ancestor in the same list. Updating an
+/// instance updates all of its descendants too, so they'd just be wasted work.
+fn remove_descendant_ids(tree: &RojoTree, ids: Vec<RbxId>) -> Vec<RbxId> {
+    let id_set: HashSet<RbxId> = ids.iter().copied().collect();
+
+    ids.into_iter()
+        .filter(|&id| {
+            let mut current = tree.get_instance(id).and_then(|instance| instance.parent());
+
+            while let Some(parent_id) = current {
+                if id_set.contains(&parent_id) {
+                    return false;
+                }
+
+                current = tree
+                    .get_instance(parent_id)
+                    .and_then(|instance| instance.parent());
             }
 
-            apply_patch_set(&mut tree, patch_set)
+            true
+        })
+        .collect()
+}
+
+/// Removes property changes from the given patch whose previous value, as seen
+/// by the client, doesn't match the value in the tree, returning them as
+/// conflicts. Updates left empty by this are removed entirely.
+fn remove_conflicts(
+    tree: &RojoTree,
+    patch_set: &mut PatchSet,
+    previous_properties: &HashMap<RbxId, HashMap<String, Option<RbxValue>>>,
+) -> Vec<PropertyConflict> {
+    let mut conflicts = Vec::new();
+
+    patch_set.updated_instances.retain(|update| {
+        let expected_properties = match previous_properties.get(&update.id) {
+            Some(expected) => expected,
+            None => return true,
+        };
+
+        let instance = match tree.get_instance(update.id) {
+            Some(instance) => instance,
+            None => return true,
         };
 
-        self.message_queue.push_messages(&[applied_patch]);
+        let mut had_conflict = false;
+        let mut has_remaining_changes = update.changed_name.is_some()
+            || update.changed_class_name.is_some()
+            || update.changed_metadata.is_some();
+
+        for key in update.changed_properties.keys() {
+            let current_value = instance.properties().get(key);
+
+            match expected_properties.get(key) {
+                Some(expected_value) if current_value != expected_value.as_ref() => {
+                    had_conflict = true;
+                    conflicts.push(PropertyConflict {
+                        id: update.id,
+                        property: key.clone(),
+                        expected_value: expected_value.clone(),
+                        current_value: current_value.cloned(),
+                    });
+                }
+                _ => has_remaining_changes = true,
+            }
+        }
+
+        !had_conflict || has_remaining_changes
+    });
+
+    for conflict in &conflicts {
+        for update in &mut patch_set.updated_instances {
+            if update.id == conflict.id {
+                update.changed_properties.remove(&conflict.property);
+            }
+        }
     }
+
+    conflicts
 }
 
-fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Option<AppliedPatchSet> {
+fn compute_and_apply_changes(
+    tree: &mut RojoTree,
+    vfs: &Vfs,
+    diagnostics: &Diagnostics,
+    id: RbxId,
+) -> Option<AppliedPatchSet> {
     let metadata = tree
         .get_metadata(id)
         .expect("metadata missing for instance present in tree");
@@ -290,22 +686,59 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
 
             match maybe_meta {
                 Some(_meta) => {
+                    // If our instance is the root of a project file, we can
+                    // usually get away with only snapshotting the parts of
+                    // the project that changed.
+                    if let Some(old_project) = &metadata.source_project {
+                        let (result, warnings) = capture_warnings(|| {
+                            compute_project_patch(
+                                &metadata.context,
+                                tree,
+                                vfs,
+                                id,
+                                old_project,
+                                path,
+                            )
+                        });
+
+                        match result {
+                            Ok(Some(project_patch)) => {
+                                diagnostics.replace(&project_patch.snapshotted_paths, warnings);
+
+                                return log_patch_error(
+                                    id,
+                                    apply_patch_set(tree, project_patch.patch_set),
+                                );
+                            }
+
+                            // The whole project is snapshotted again below,
+                            // which finds these warnings again.
+                            Ok(None) => {}
+
+                            Err(err) => {
+                                return record_diagnostics(
+                                    diagnostics,
+                                    slice::from_ref(path),
+                                    Err(err),
+                                    warnings,
+                                );
+                            }
+                        }
+                    }
+
                     // Our instance was previously created from a path and
                     // that path still exists. We can generate a snapshot
                     // starting at that path and use it as the source for
                     // our patch.
 
-                    let snapshot = match snapshot_from_vfs(&metadata.context, &vfs, &path) {
-                        Ok(maybe_snapshot) => {
-                            maybe_snapshot.expect("snapshot did not return an instance")
-                        }
-                        Err(err) => {
-                            log::error!("Snapshot error: {}", ErrorDisplay(err));
-                            return None;
-                        }
-                    };
+                    let (result, warnings) =
+                        capture_warnings(|| snapshot_from_vfs(&metadata.context, &vfs, &path));
+
+                    let sources = snapshotted_paths(tree, id, &result);
+                    let snapshot = record_diagnostics(diagnostics, &sources, result, warnings)?
+                        .expect("snapshot did not return an instance");
 
-                    let patch_set = compute_patch_set(&snapshot, &tree, id);
+                    let patch_set = compute_patch_set(snapshot, &tree, id);
                     apply_patch_set(tree, patch_set)
                 }
                 None => {
@@ -315,6 +748,8 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
                     // We associate deleting the instigating file for an
                     // instance with deleting that instance.
 
+                    diagnostics.replace(&tree.get_relevant_paths_in_subtree(id), Vec::new());
+
                     let mut patch_set = PatchSet::new();
                     patch_set.removed_instances.push(id);
 
@@ -327,26 +762,89 @@ fn compute_and_apply_changes(tree: &mut RojoTree, vfs: &Vfs, id: RbxId) -> Optio
             // there might be information associated with our instance from
             // the project file, we snapshot the entire project node again.
 
-            let snapshot_result = snapshot_project_node(
-                &metadata.context,
-                &project_path,
-                instance_name,
-                project_node,
-                &vfs,
-            );
+            let (result, warnings) = capture_warnings(|| {
+                snapshot_project_node(
+                    &metadata.context,
+                    &project_path,
+                    instance_name,
+                    project_node,
+                    &vfs,
+                )
+            });
+
+            let sources = snapshotted_paths(tree, id, &result);
+            let snapshot = record_diagnostics(diagnostics, &sources, result, warnings)?
+                .expect("snapshot did not return an instance");
+
+            let patch_set = compute_patch_set(snapshot, &tree, id);
+            apply_patch_set(tree, patch_set)
+        }
+    };
 
-            let snapshot = match snapshot_result {
-                Ok(maybe_snapshot) => maybe_snapshot.expect("snapshot did not return an instance"),
-                Err(err) => {
-                    log::error!("Snapshot error: {}", ErrorDisplay(err));
-                    return None;
-                }
-            };
+    log_patch_error(id, applied_patch_set)
+}
 
-            let patch_set = compute_patch_set(&snapshot, &tree, id);
-            apply_patch_set(tree, patch_set)
+/// Finds the paths that snapshotting the instance with the given ID again
+/// looked at, whose diagnostics are replaced by the ones found this time. That
+/// is every path that the instance and its descendants came from, plus every
+/// path that the new snapshot came from if snapshotting succeeded.
+fn snapshotted_paths(
+    tree: &RojoTree,
+    id: RbxId,
+    result: &Result<Option<InstanceSnapshot>, SnapshotError>,
+) -> Vec<PathBuf> {
+    let mut paths = tree.get_relevant_paths_in_subtree(id);
+
+    if let Ok(Some(snapshot)) = result {
+        paths.extend(snapshot.relevant_paths_in_subtree());
+    }
+
+    paths
+}
+
+/// Replaces the diagnostics for everything under `sources` with the warnings
+/// from snapshotting them again, plus the error if snapshotting failed. Errors
+/// are logged and turned into `None`.
+fn record_diagnostics<T>(
+    diagnostics: &Diagnostics,
+    sources: &[PathBuf],
+    result: Result<T, SnapshotError>,
+    mut warnings: Vec<Diagnostic>,
+) -> Option<T> {
+    let output = match result {
+        Ok(output) => Some(output),
+        Err(err) => {
+            let path = err
+                .path()
+                .or_else(|| sources.first().map(PathBuf::as_path))
+                .unwrap_or_else(|| Path::new(""))
+                .to_path_buf();
+            let message = ErrorDisplay(err).to_string();
+            log::error!("Snapshot error: {}", message);
+
+            warnings.push(Diagnostic {
+                severity: Severity::Error,
+                path,
+                message: message.trim_end().to_owned(),
+            });
+
+            None
         }
     };
 
-    Some(applied_patch_set)
+    diagnostics.replace(sources, warnings);
+    output
+}
+
+fn log_patch_error(
+    id: RbxId,
+    result: Result<AppliedPatchSet, PatchError>,
+) -> Option<AppliedPatchSet> {
+    match result {
+        Ok(applied_patch_set) => Some(applied_patch_set),
+        Err(err) => {
+            log::error!("Could not apply changes to instance {}: {}", id, err);
+            None
+        }
+    }
 }
@@ -1,6 +1,8 @@
 use std::{
+    borrow::Cow,
     fs::File,
     io::{self, BufWriter, Write},
+    path::PathBuf,
 };
 
 use memofs::Vfs;
@@ -9,6 +11,7 @@ use tokio::runtime::Runtime;
 
 use crate::{
     cli::BuildCommand, project::ProjectError, serve_session::ServeSession, snapshot::RojoTree,
+    trace,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -53,6 +56,9 @@ enum Error {
 
     #[snafu(display("{}", source))]
     Project { source: ProjectError },
+
+    #[snafu(display("Could not write trace file {}: {}", path.display(), source))]
+    Trace { source: io::Error, path: PathBuf },
 }
 
 impl From<rbx_binary::EncodeError> for Error {
@@ -70,6 +76,20 @@ pub fn build(options: BuildCommand) -> Result<(), BuildError> {
 }
 
 fn build_inner(options: BuildCommand) -> Result<(), Error> {
+    if let Some(path) = &options.trace {
+        trace::start(path).context(Trace { path })?;
+    }
+
+    let result = build_project(&options);
+
+    if let Some(path) = &options.trace {
+        trace::finish().context(Trace { path })?;
+    }
+
+    result
+}
+
+fn build_project(options: &BuildCommand) -> Result<(), Error> {
     log::trace!("Constructing in-memory filesystem");
 
     let vfs = Vfs::new_default();
@@ -79,7 +99,7 @@ fn build_inner(options: BuildCommand) -> Result<(), Error> {
 
     {
         let tree = session.tree();
-        write_model(&tree, &options)?;
+        write_model(&tree, options)?;
     }
 
     if options.watch {
@@ -91,14 +111,83 @@ fn build_inner(options: BuildCommand) -> Result<(), Error> {
             cursor = new_cursor;
 
             let tree = session.tree();
-            write_model(&tree, &options)?;
+            write_model(&tree, options)?;
         }
     }
 
     Ok(())
 }
 
+/// Writes out an XML model or place generated by rbx_xml.
+///
+/// Both rbx_xml and rbx_binary sort properties and number referents in the
+/// order instances are visited, so their output only depends on the order of
+/// instances in the tree, which Rojo keeps stable. The one exception is the
+/// SharedStrings dictionary in XML files, which rbx_xml writes out in hash map
+/// order. In deterministic mode, we sort it before writing.
+fn write_xml<W: Write>(output: &mut W, buffer: &[u8], options: &BuildCommand) -> Result<(), Error> {
+    let contents = match std::str::from_utf8(buffer) {
+        Ok(xml) if options.deterministic => sort_shared_strings(xml),
+        _ => Cow::Borrowed(buffer),
+    };
+
+    output.write_all(&contents).context(Io)
+}
+
+/// Sorts the entries of the SharedStrings dictionary at the end of an XML
+/// model or place by their MD5 hash.
+fn sort_shared_strings(xml: &str) -> Cow<'_, [u8]> {
+    const START_TAG: &str = "<SharedStrings>";
+    const END_TAG: &str = "</SharedStrings>";
+    const ENTRY_PREFIX: &str = "<SharedString ";
+
+    let start = match xml.rfind(START_TAG) {
+        Some(index) => index + START_TAG.len(),
+        None => return Cow::Borrowed(xml.as_bytes()),
+    };
+
+    let end = match xml[start..].find(END_TAG) {
+        Some(index) => start + index,
+        None => return Cow::Borrowed(xml.as_bytes()),
+    };
+
+    let dictionary = &xml[start..end];
+
+    // Entries are all indented the same way, so the whitespace in front of the
+    // first one can be used to separate all of them.
+    let mut pieces = dictionary.split(ENTRY_PREFIX);
+    let separator = pieces.next().unwrap_or_default();
+    let mut entries: Vec<&str> = pieces.collect();
+
+    let closing_whitespace = match entries.last() {
+        Some(last) => &last[last.trim_end().len()..],
+        None => return Cow::Borrowed(xml.as_bytes()),
+    };
+
+    for entry in &mut entries {
+        *entry = entry.trim_end();
+    }
+
+    entries.sort_unstable();
+
+    let mut output = String::with_capacity(xml.len());
+    output.push_str(&xml[..start]);
+
+    for entry in entries {
+        output.push_str(separator);
+        output.push_str(ENTRY_PREFIX);
+        output.push_str(entry);
+    }
+
+    output.push_str(closing_whitespace);
+    output.push_str(&xml[end..]);
+
+    Cow::Owned(output.into_bytes())
+}
+
 fn write_model(tree: &RojoTree, options: &BuildCommand) -> Result<(), Error> {
+    let _span = trace::span_with_path("write_model", &options.output);
+
     let output_kind = detect_output_kind(&options).ok_or(Error::UnknownOutputKind)?;
     log::debug!("Hoping to generate file of type {:?}", output_kind);
 
@@ -113,8 +202,11 @@ fn write_model(tree: &RojoTree, options: &BuildCommand) -> Result<(), Error> {
             // Model files include the root instance of the tree and all its
             // descendants.
 
-            rbx_xml::to_writer(&mut file, tree.inner(), &[root_id], xml_encode_config())
+            let mut buffer = Vec::new();
+            rbx_xml::to_writer(&mut buffer, tree.inner(), &[root_id], xml_encode_config())
                 .context(XmlModelEncode)?;
+
+            write_xml(&mut file, &buffer, options)?;
         }
         OutputKind::Rbxlx => {
             // Place files don't contain an entry for the DataModel, but our
@@ -123,8 +215,16 @@ fn write_model(tree: &RojoTree, options: &BuildCommand) -> Result<(), Error> {
             let root_instance = tree.get_instance(root_id).unwrap();
             let top_level_ids = root_instance.children();
 
-            rbx_xml::to_writer(&mut file, tree.inner(), top_level_ids, xml_encode_config())
-                .context(XmlModelEncode)?;
+            let mut buffer = Vec::new();
+            rbx_xml::to_writer(
+                &mut buffer,
+                tree.inner(),
+                top_level_ids,
+                xml_encode_config(),
+            )
+            .context(XmlModelEncode)?;
+
+            write_xml(&mut file, &buffer, options)?;
         }
         OutputKind::Rbxm => {
             rbx_binary::encode(tree.inner(), &[root_id], &mut file)?;
@@ -145,3 +245,40 @@ fn write_model(tree: &RojoTree, options: &BuildCommand) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sort_shared_strings_in_place() {
+        let xml = concat!(
+            "<roblox version=\"4\">\n",
+            "  <SharedStrings>\n",
+            "    <SharedString md5=\"b\">Ymx1ZQ==</SharedString>\n",
+            "    <SharedString md5=\"c\">Y3lhbg==</SharedString>\n",
+            "    <SharedString md5=\"a\">YW1iZXI=</SharedString>\n",
+            "  </SharedStrings>\n",
+            "</roblox>",
+        );
+
+        let expected = concat!(
+            "<roblox version=\"4\">\n",
+            "  <SharedStrings>\n",
+            "    <SharedString md5=\"a\">YW1iZXI=</SharedString>\n",
+            "    <SharedString md5=\"b\">Ymx1ZQ==</SharedString>\n",
+            "    <SharedString md5=\"c\">Y3lhbg==</SharedString>\n",
+            "  </SharedStrings>\n",
+            "</roblox>",
+        );
+
+        assert_eq!(sort_shared_strings(xml), expected.as_bytes());
+    }
+
+    #[test]
+    fn sort_shared_strings_without_dictionary() {
+        let xml = "<roblox version=\"4\">\n</roblox>";
+
+        assert_eq!(sort_shared_strings(xml), xml.as_bytes());
+    }
+}
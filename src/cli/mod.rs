@@ -4,6 +4,8 @@ mod build;
 mod doc;
 mod init;
 mod serve;
+#[cfg(unix)]
+mod signals;
 mod upload;
 
 use std::{
@@ -11,6 +13,7 @@ use std::{
     env,
     error::Error,
     fmt,
+    net::IpAddr,
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -117,16 +120,62 @@ impl fmt::Display for InitKindParseError {
 
 /// Expose a Rojo project through a web server that can communicate with the
 /// Rojo Roblox Studio plugin, or be visited by the user in the browser.
+///
+/// On Unix, sending the server `SIGUSR1` pauses syncing changes from the
+/// filesystem and `SIGUSR2` resumes it.
 #[derive(Debug, StructOpt)]
 pub struct ServeCommand {
     /// Path to the project to serve. Defaults to the current directory.
     #[structopt(default_value = "")]
     pub project: PathBuf,
 
+    /// The IP address to listen on. Defaults to 127.0.0.1, which only accepts
+    /// connections from this computer.
+    #[structopt(long)]
+    pub address: Option<IpAddr>,
+
     /// The port to listen on. Defaults to the project's preference, or 34872 if
     /// it has none.
     #[structopt(long)]
     pub port: Option<u16>,
+
+    /// Require clients to authenticate with a token that's generated when the
+    /// server starts and printed along with the server's address. Browsers are
+    /// given the token by the link that's printed, which stores it in a cookie.
+    #[structopt(long)]
+    pub auth: bool,
+
+    /// Also write the authentication token to this file. Implies `--auth`.
+    #[structopt(long)]
+    pub token_file: Option<PathBuf>,
+
+    /// Serve over HTTPS. Needs Rojo built with the `tls` feature. Unless
+    /// `--tls-cert` and `--tls-key` are given, uses a self-signed certificate
+    /// kept in `.rojo/tls` next to the project, which is made again when it
+    /// expires or doesn't cover `--address`. That has to be a specific address
+    /// rather than one like 0.0.0.0.
+    ///
+    /// Studio only accepts certificates that the operating system trusts, so
+    /// add `.rojo/tls/cert.pem` to the system's trusted certificates before
+    /// connecting, then check that the SHA-256 fingerprint printed when the
+    /// server starts matches the certificate you trusted. In the plugin, enter
+    /// the address as `https://<address>`.
+    #[structopt(long)]
+    pub tls: bool,
+
+    /// PEM file containing the certificate chain to serve HTTPS with. Implies
+    /// `--tls`.
+    #[structopt(long, requires = "tls-key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM file containing the private key for `--tls-cert`.
+    #[structopt(long, requires = "tls-cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Write a trace of where time is spent to this file, which can be opened
+    /// in Chrome's trace viewer.
+    #[structopt(long)]
+    pub trace: Option<PathBuf>,
 }
 
 impl ServeCommand {
@@ -149,6 +198,16 @@ pub struct BuildCommand {
     /// Whether to automatically rebuild when any input files change.
     #[structopt(long)]
     pub watch: bool,
+
+    /// Skip the extra work that makes building the same project twice produce
+    /// byte-identical files.
+    #[structopt(long = "no-deterministic", parse(from_flag = std::ops::Not::not))]
+    pub deterministic: bool,
+
+    /// Write a trace of where time is spent to this file, which can be opened
+    /// in Chrome's trace viewer.
+    #[structopt(long)]
+    pub trace: Option<PathBuf>,
 }
 
 impl BuildCommand {
@@ -1,66 +1,186 @@
 use std::{
+    fs,
     io::{self, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
     sync::Arc,
 };
 
 use memofs::Vfs;
-use snafu::Snafu;
+use snafu::{ResultExt, Snafu};
 use termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor};
+use uuid::Uuid;
 
-use crate::{cli::ServeCommand, serve_session::ServeSession, web::LiveServer};
+use crate::{cli::ServeCommand, serve_session::ServeSession, trace, web::LiveServer};
 
+#[cfg(feature = "tls")]
+use crate::web::{TlsConfig, TlsError};
+
+const DEFAULT_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 const DEFAULT_PORT: u16 = 34872;
 
 #[derive(Debug, Snafu)]
 pub struct ServeError(Error);
 
 #[derive(Debug, Snafu)]
-enum Error {}
+enum Error {
+    #[snafu(display("Could not write token file {}: {}", path.display(), source))]
+    TokenFile { source: io::Error, path: PathBuf },
+
+    #[cfg(feature = "tls")]
+    #[snafu(display("{}", source))]
+    Tls { source: TlsError },
+
+    #[cfg(not(feature = "tls"))]
+    #[snafu(display(
+        "This copy of Rojo was built without HTTPS support. \
+         Build it with `--features tls` to use --tls."
+    ))]
+    TlsNotSupported,
+
+    #[snafu(display("Could not write trace file {}: {}", path.display(), source))]
+    Trace { source: io::Error, path: PathBuf },
+}
 
 pub fn serve(options: ServeCommand) -> Result<(), ServeError> {
     Ok(serve_inner(options)?)
 }
 
 fn serve_inner(options: ServeCommand) -> Result<(), Error> {
+    // The server runs until Rojo is interrupted, so the trace is never
+    // finished. Events are written as they happen instead.
+    if let Some(path) = &options.trace {
+        trace::start(path).context(Trace { path })?;
+    }
+
     let vfs = Vfs::new_default();
 
     let session = Arc::new(ServeSession::new(vfs, &options.absolute_project()));
 
+    #[cfg(unix)]
+    super::signals::forward_sync_signals(session.sync_control_sender());
+
+    let ip = options.address.unwrap_or(DEFAULT_ADDRESS);
+
     let port = options
         .port
         .or_else(|| session.project_port())
         .unwrap_or(DEFAULT_PORT);
 
-    let server = LiveServer::new(session);
-
-    let _ = show_start_message(port);
-    server.start(port);
+    let auth_token = if options.auth || options.token_file.is_some() {
+        Some(Uuid::new_v4().to_simple().to_string())
+    } else {
+        None
+    };
+
+    if let (Some(path), Some(token)) = (&options.token_file, &auth_token) {
+        fs::write(path, token).context(TokenFile { path })?;
+    }
+
+    #[cfg(feature = "tls")]
+    let tls = match (&options.tls_cert, &options.tls_key) {
+        (Some(cert), Some(key)) => Some(TlsConfig::from_files(cert, key).context(Tls)?),
+        _ if options.tls => {
+            let dir = session.rojo_folder().join("tls");
+            Some(TlsConfig::self_signed(&dir, ip).context(Tls)?)
+        }
+        _ => None,
+    };
+
+    #[cfg(not(feature = "tls"))]
+    {
+        if options.tls || options.tls_cert.is_some() {
+            return TlsNotSupported.fail();
+        }
+    }
+
+    if !ip.is_loopback() && auth_token.is_none() {
+        log::warn!(
+            "Rojo is listening on {}, which other computers might be able to connect to.",
+            ip
+        );
+        log::warn!("Consider passing --auth so that clients need a token to connect.");
+    }
+
+    #[cfg(feature = "tls")]
+    let fingerprint = tls.as_ref().map(|tls| tls.fingerprint().to_owned());
+    #[cfg(not(feature = "tls"))]
+    let fingerprint: Option<String> = None;
+
+    let server = LiveServer::new(session).auth_token(auth_token.clone());
+
+    #[cfg(feature = "tls")]
+    let server = server.tls(tls);
+
+    let _ = show_start_message(ip, port, auth_token.as_deref(), fingerprint.as_deref());
+    server.start(SocketAddr::new(ip, port));
 
     Ok(())
 }
 
-fn show_start_message(port: u16) -> io::Result<()> {
+fn show_start_message(
+    ip: IpAddr,
+    port: u16,
+    auth_token: Option<&str>,
+    fingerprint: Option<&str>,
+) -> io::Result<()> {
     let writer = BufferWriter::stdout(ColorChoice::Auto);
     let mut buffer = writer.buffer();
 
+    // The UI is served to browsers, which can't reach the unspecified address.
+    let display_host = if ip.is_loopback() || ip.is_unspecified() {
+        "localhost".to_owned()
+    } else {
+        match ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        }
+    };
+
     writeln!(&mut buffer, "Rojo server listening:")?;
 
     write!(&mut buffer, "  Address: ")?;
     buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    writeln!(&mut buffer, "localhost")?;
+    writeln!(&mut buffer, "{}", ip)?;
 
     buffer.set_color(&ColorSpec::new())?;
     write!(&mut buffer, "  Port:    ")?;
     buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
     writeln!(&mut buffer, "{}", port)?;
 
+    if let Some(token) = auth_token {
+        buffer.set_color(&ColorSpec::new())?;
+        write!(&mut buffer, "  Token:   ")?;
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        writeln!(&mut buffer, "{}", token)?;
+    }
+
+    if let Some(fingerprint) = fingerprint {
+        buffer.set_color(&ColorSpec::new())?;
+        writeln!(&mut buffer, "  Certificate SHA-256 fingerprint:")?;
+        buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
+        writeln!(&mut buffer, "    {}", fingerprint)?;
+    }
+
+    let scheme = if fingerprint.is_some() {
+        "https"
+    } else {
+        "http"
+    };
+
     writeln!(&mut buffer)?;
 
     buffer.set_color(&ColorSpec::new())?;
     write!(&mut buffer, "Visit ")?;
 
     buffer.set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))?;
-    write!(&mut buffer, "http://localhost:{}/", port)?;
+    write!(&mut buffer, "{}://{}:{}/", scheme, display_host, port)?;
+
+    // The page turns the token into a cookie, since browsers can't send it in
+    // a header.
+    if let Some(token) = auth_token {
+        write!(&mut buffer, "?token={}", token)?;
+    }
 
     buffer.set_color(&ColorSpec::new())?;
     writeln!(&mut buffer, " in your browser for more information.")?;
@@ -0,0 +1,73 @@
+//! Lets scripts pause and resume syncing of a running `rojo serve` by sending
+//! it signals, like from Git hooks around a large rebase:
+//!
+//! * `SIGUSR1` pauses syncing
+//! * `SIGUSR2` resumes it
+//!
+//! Signal handlers can't do much safely, so they only set a flag, which a
+//! thread checks for and passes on to the change processor.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::Sender;
+
+use crate::change_processor::{SyncCommand, SyncControl};
+
+/// How often to check whether a signal has arrived.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static PAUSE_REQUESTED: AtomicBool = AtomicBool::new(false);
+static RESUME_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(signal: libc::c_int) {
+    match signal {
+        libc::SIGUSR1 => PAUSE_REQUESTED.store(true, Ordering::SeqCst),
+        libc::SIGUSR2 => RESUME_REQUESTED.store(true, Ordering::SeqCst),
+        _ => {}
+    }
+}
+
+/// Starts sending sync commands on the given channel when Rojo receives
+/// `SIGUSR1` or `SIGUSR2`.
+pub fn forward_sync_signals(sender: Sender<SyncControl>) {
+    let handler = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    // Safe because the handler only touches atomics.
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler);
+        libc::signal(libc::SIGUSR2, handler);
+    }
+
+    thread::Builder::new()
+        .name("Signal thread".to_owned())
+        .spawn(move || loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let mut commands = Vec::new();
+
+            if PAUSE_REQUESTED.swap(false, Ordering::SeqCst) {
+                commands.push(SyncCommand::Pause);
+            }
+
+            if RESUME_REQUESTED.swap(false, Ordering::SeqCst) {
+                commands.push(SyncCommand::Resume);
+            }
+
+            for command in commands {
+                let control = SyncControl {
+                    command,
+                    response_sender: None,
+                };
+
+                // The session is gone, so there's nothing left to control.
+                if sender.send(control).is_err() {
+                    return;
+                }
+            }
+        })
+        .expect("Could not start signal thread");
+}
@@ -0,0 +1,210 @@
+//! Keeps track of the problems that Rojo runs into while snapshotting files
+//! during `rojo serve`, so that clients can show them to people who aren't
+//! watching the terminal.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use futures::sync::oneshot;
+
+use crate::message_queue::MessageQueue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem with a file that stopped it from being snapshotted, or that the
+/// user should know about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub message: String,
+}
+
+thread_local! {
+    static CAPTURED_WARNINGS: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+/// Records a warning about the file at the given path, if the current thread
+/// is inside `capture_warnings`. Callers are still expected to log it.
+pub fn report_warning(path: &Path, message: String) {
+    CAPTURED_WARNINGS.with(|captured| {
+        if let Some(warnings) = captured.borrow_mut().as_mut() {
+            warnings.push(Diagnostic {
+                severity: Severity::Warning,
+                path: path.to_path_buf(),
+                message,
+            });
+        }
+    });
+}
+
+/// Runs the given function, collecting every warning reported with
+/// `report_warning` while it runs.
+pub fn capture_warnings<T>(callback: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let outer = CAPTURED_WARNINGS.with(|captured| captured.replace(Some(Vec::new())));
+    let output = callback();
+    let warnings = CAPTURED_WARNINGS.with(|captured| captured.replace(outer));
+
+    (output, warnings.unwrap_or_default())
+}
+
+/// The current diagnostics for every file in a project, which changes
+/// whenever part of the project is snapshotted again.
+pub struct Diagnostics {
+    by_path: Mutex<BTreeMap<PathBuf, Vec<Diagnostic>>>,
+
+    /// Gets an empty message every time the diagnostics change, which lets
+    /// clients wait for changes the same way they do for patches.
+    changes: MessageQueue<()>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Diagnostics {
+            by_path: Mutex::new(BTreeMap::new()),
+            changes: MessageQueue::new(),
+        }
+    }
+
+    /// Replaces the diagnostics for every file at or under any of `sources`
+    /// with the ones found by snapshotting them again. Files that snapshotted
+    /// cleanly this time lose the diagnostics they had before.
+    pub fn replace(&self, sources: &[PathBuf], diagnostics: Vec<Diagnostic>) {
+        let mut new_by_path: BTreeMap<PathBuf, Vec<Diagnostic>> = BTreeMap::new();
+
+        for diagnostic in diagnostics {
+            new_by_path
+                .entry(diagnostic.path.clone())
+                .or_default()
+                .push(diagnostic);
+        }
+
+        let mut by_path = self.by_path.lock().unwrap();
+        let mut changed = false;
+
+        by_path.retain(|path, _| {
+            let keep = new_by_path.contains_key(path)
+                || !sources.iter().any(|source| path.starts_with(source));
+
+            changed |= !keep;
+            keep
+        });
+
+        for (path, diagnostics) in new_by_path {
+            match by_path.get_mut(&path) {
+                Some(existing) if *existing == diagnostics => {}
+                Some(existing) => {
+                    *existing = diagnostics;
+                    changed = true;
+                }
+                None => {
+                    by_path.insert(path, diagnostics);
+                    changed = true;
+                }
+            }
+        }
+
+        // The queue is pushed to while the map is still locked, so that
+        // `current` always returns a cursor that matches its diagnostics.
+        if changed {
+            self.changes.push_messages(&[()]);
+        }
+    }
+
+    /// Returns every current diagnostic, sorted by path, along with a cursor
+    /// that can be passed to `subscribe` to wait for them to change.
+    pub fn current(&self) -> (u32, Vec<Diagnostic>) {
+        let by_path = self.by_path.lock().unwrap();
+        let diagnostics = by_path.values().flatten().cloned().collect();
+
+        (self.changes.cursor(), diagnostics)
+    }
+
+    /// Resolves once the diagnostics have changed since the given cursor.
+    pub fn subscribe(&self, cursor: u32) -> oneshot::Receiver<(u32, Vec<()>)> {
+        self.changes.subscribe(cursor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn diagnostic(severity: Severity, path: &str) -> Diagnostic {
+        Diagnostic {
+            severity,
+            path: PathBuf::from(path),
+            message: format!("Problem with {}", path),
+        }
+    }
+
+    #[test]
+    fn replace_clears_files_under_source() {
+        let diagnostics = Diagnostics::new();
+
+        diagnostics.replace(
+            &[PathBuf::from("/project")],
+            vec![
+                diagnostic(Severity::Warning, "/project/src"),
+                diagnostic(Severity::Warning, "/project/other"),
+            ],
+        );
+        diagnostics.replace(
+            &[PathBuf::from("/project/src/bad.model.json")],
+            vec![diagnostic(Severity::Error, "/project/src/bad.model.json")],
+        );
+
+        let (cursor, current) = diagnostics.current();
+        assert_eq!(cursor, 2);
+        assert_eq!(current.len(), 3);
+
+        // Snapshotting the folder again cleanly clears everything under it,
+        // but leaves its siblings alone.
+        diagnostics.replace(&[PathBuf::from("/project/src")], Vec::new());
+        assert_eq!(
+            diagnostics.current(),
+            (3, vec![diagnostic(Severity::Warning, "/project/other")])
+        );
+
+        // Nothing changed, so clients don't need to hear about it.
+        diagnostics.replace(&[PathBuf::from("/project/src")], Vec::new());
+        assert_eq!(diagnostics.current().0, 3);
+
+        // A different problem with the same file is still a change.
+        diagnostics.replace(
+            &[PathBuf::from("/project/other")],
+            vec![diagnostic(Severity::Error, "/project/other")],
+        );
+        assert_eq!(
+            diagnostics.current(),
+            (4, vec![diagnostic(Severity::Error, "/project/other")])
+        );
+    }
+
+    #[test]
+    fn capture_nested_warnings() {
+        report_warning(Path::new("/ignored"), "Not captured".to_owned());
+
+        let ((), outer) = capture_warnings(|| {
+            report_warning(Path::new("/outer"), "Outer".to_owned());
+
+            let ((), inner) = capture_warnings(|| {
+                report_warning(Path::new("/inner"), "Inner".to_owned());
+            });
+
+            assert_eq!(inner.len(), 1);
+            assert_eq!(inner[0].path, Path::new("/inner"));
+        });
+
+        assert_eq!(outer.len(), 1);
+        assert_eq!(outer[0].path, Path::new("/outer"));
+    }
+}
@@ -9,17 +9,21 @@ mod tree_view;
 
 mod auth_cookie;
 mod change_processor;
+mod diagnostics;
 mod error;
 mod glob;
 mod message_queue;
-mod multimap;
+mod metrics;
 mod path_serializer;
+mod path_trie;
 mod project;
 mod serve_session;
 mod session_id;
 mod snapshot;
 mod snapshot_middleware;
+mod trace;
 mod web;
+mod write_sandbox;
 
 pub use project::*;
 pub use session_id::SessionId;
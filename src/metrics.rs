@@ -0,0 +1,267 @@
+//! Collects metrics about where `rojo serve` spends its time, which are served
+//! from `/metrics` in Prometheus' text format.
+//!
+//! Metrics are global so that code deep in snapshotting can record them
+//! without having a session passed to it. Recording one is cheap, so they're
+//! collected during `rojo build` too, even though nothing reads them.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+
+/// Upper bounds of the buckets that durations are sorted into, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+lazy_static! {
+    pub static ref VFS_EVENTS: Counter = Counter::new(
+        "rojo_vfs_events_total",
+        "Filesystem events received from the VFS.",
+        "kind",
+    );
+    pub static ref SNAPSHOT_DURATION: Histogram = Histogram::new(
+        "rojo_snapshot_duration_seconds",
+        "Time taken by each snapshot middleware to snapshot a path, including any \
+         children it snapshots.",
+        Some("middleware"),
+    );
+    pub static ref PATCH_COMPUTE_DURATION: Histogram = Histogram::new(
+        "rojo_patch_compute_duration_seconds",
+        "Time taken to compute a patch from a snapshot.",
+        None,
+    );
+    pub static ref PATCH_APPLY_DURATION: Histogram = Histogram::new(
+        "rojo_patch_apply_duration_seconds",
+        "Time taken to validate and apply a patch to the tree.",
+        None,
+    );
+    pub static ref REQUEST_DURATION: Histogram = Histogram::new(
+        "rojo_http_request_duration_seconds",
+        "Time taken to respond to API requests, including time that subscribe \
+         requests spend waiting for messages.",
+        Some("route"),
+    );
+}
+
+/// Writes every metric in Prometheus' text format.
+pub fn render(output: &mut String) {
+    VFS_EVENTS.render(output);
+    SNAPSHOT_DURATION.render(output);
+    PATCH_COMPUTE_DURATION.render(output);
+    PATCH_APPLY_DURATION.render(output);
+    REQUEST_DURATION.render(output);
+}
+
+/// Writes a gauge in Prometheus' text format. Gauges aren't stored here, since
+/// the things they measure can be looked at whenever metrics are requested.
+pub fn render_gauge(output: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(output, "# HELP {} {}", name, help).unwrap();
+    writeln!(output, "# TYPE {} gauge", name).unwrap();
+    writeln!(output, "{} {}", name, value).unwrap();
+}
+
+/// A count of things that have happened, split up by the value of a label.
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    label: &'static str,
+    values: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Counter {
+    pub fn new(name: &'static str, help: &'static str, label: &'static str) -> Self {
+        Counter {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn increment(&self, label_value: &'static str) {
+        *self.values.lock().unwrap().entry(label_value).or_default() += 1;
+    }
+
+    fn render(&self, output: &mut String) {
+        writeln!(output, "# HELP {} {}", self.name, self.help).unwrap();
+        writeln!(output, "# TYPE {} counter", self.name).unwrap();
+
+        for (label_value, count) in self.values.lock().unwrap().iter() {
+            writeln!(
+                output,
+                "{}{{{}=\"{}\"}} {}",
+                self.name, self.label, label_value, count
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Durations sorted into buckets, optionally split up by the value of a label.
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    label: Option<&'static str>,
+    values: Mutex<BTreeMap<&'static str, HistogramValues>>,
+}
+
+#[derive(Default)]
+struct HistogramValues {
+    /// How many durations fell in each bucket, not counting smaller buckets.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str, label: Option<&'static str>) -> Self {
+        Histogram {
+            name,
+            help,
+            label,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, label_value: &'static str, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+
+        let mut values = self.values.lock().unwrap();
+        let values = values.entry(label_value).or_default();
+
+        if let Some(index) = DURATION_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            values.buckets[index] += 1;
+        }
+
+        values.sum += seconds;
+        values.count += 1;
+    }
+
+    /// Starts timing something, which is recorded once the returned timer is
+    /// dropped. Histograms without a label should be given an empty one.
+    pub fn start_timer(&self, label_value: &'static str) -> HistogramTimer<'_> {
+        HistogramTimer {
+            histogram: self,
+            label_value,
+            start: Instant::now(),
+        }
+    }
+
+    fn render(&self, output: &mut String) {
+        writeln!(output, "# HELP {} {}", self.name, self.help).unwrap();
+        writeln!(output, "# TYPE {} histogram", self.name).unwrap();
+
+        for (label_value, values) in self.values.lock().unwrap().iter() {
+            let labels = |extra: Option<String>| -> String {
+                let mut labels: Vec<String> = self
+                    .label
+                    .map(|label| format!("{}=\"{}\"", label, label_value))
+                    .into_iter()
+                    .collect();
+                labels.extend(extra);
+
+                if labels.is_empty() {
+                    String::new()
+                } else {
+                    format!("{{{}}}", labels.join(","))
+                }
+            };
+
+            let mut cumulative = 0;
+
+            for (bound, count) in DURATION_BUCKETS.iter().zip(values.buckets.iter()) {
+                cumulative += count;
+                let bucket_labels = labels(Some(format!("le=\"{}\"", bound)));
+                writeln!(
+                    output,
+                    "{}_bucket{} {}",
+                    self.name, bucket_labels, cumulative
+                )
+                .unwrap();
+            }
+
+            let infinity_labels = labels(Some("le=\"+Inf\"".to_owned()));
+            writeln!(
+                output,
+                "{}_bucket{} {}",
+                self.name, infinity_labels, values.count
+            )
+            .unwrap();
+
+            writeln!(output, "{}_sum{} {}", self.name, labels(None), values.sum).unwrap();
+            writeln!(
+                output,
+                "{}_count{} {}",
+                self.name,
+                labels(None),
+                values.count
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub struct HistogramTimer<'a> {
+    histogram: &'a Histogram,
+    label_value: &'static str,
+    start: Instant,
+}
+
+impl HistogramTimer<'_> {
+    /// Stops the timer without recording anything.
+    pub fn discard(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for HistogramTimer<'_> {
+    fn drop(&mut self) {
+        self.histogram
+            .observe(self.label_value, self.start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_histogram() {
+        let histogram = Histogram::new("test_seconds", "A test histogram.", Some("kind"));
+        histogram.observe("fast", Duration::from_micros(100));
+        histogram.observe("fast", Duration::from_millis(20));
+        histogram.observe("slow", Duration::from_secs(60));
+        histogram.start_timer("discarded").discard();
+
+        let mut output = String::new();
+        histogram.render(&mut output);
+
+        assert!(output
+            .starts_with("# HELP test_seconds A test histogram.\n# TYPE test_seconds histogram\n"));
+        assert!(output.contains("test_seconds_bucket{kind=\"fast\",le=\"0.0005\"} 1\n"));
+        assert!(output.contains("test_seconds_bucket{kind=\"fast\",le=\"0.025\"} 2\n"));
+        assert!(output.contains("test_seconds_bucket{kind=\"slow\",le=\"10\"} 0\n"));
+        assert!(output.contains("test_seconds_bucket{kind=\"slow\",le=\"+Inf\"} 1\n"));
+        assert!(output.contains("test_seconds_sum{kind=\"slow\"} 60\n"));
+        assert!(output.contains("test_seconds_count{kind=\"fast\"} 2\n"));
+        assert!(!output.contains("discarded"));
+    }
+
+    #[test]
+    fn render_unlabeled_histogram() {
+        let histogram = Histogram::new("test_seconds", "A test histogram.", None);
+        histogram.observe("", Duration::from_secs(1));
+
+        let mut output = String::new();
+        histogram.render(&mut output);
+
+        assert!(output.contains("test_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(output.contains("test_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    fmt::{self, Debug},
+    path::{Path, PathBuf},
+};
+
+/// A map from paths to sets of values, stored as a tree of path components.
+///
+/// Unlike a hash map keyed by paths, this can efficiently find all of the
+/// values at or below a directory, as well as the values at the nearest
+/// ancestor of a path that has any.
+#[derive(Clone)]
+pub struct PathTrie<V> {
+    root: PathTrieNode<V>,
+}
+
+#[derive(Clone)]
+struct PathTrieNode<V> {
+    values: Vec<V>,
+    children: BTreeMap<OsString, PathTrieNode<V>>,
+}
+
+impl<V> PathTrieNode<V> {
+    fn new() -> Self {
+        PathTrieNode {
+            values: Vec::new(),
+            children: BTreeMap::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.values.is_empty() && self.children.is_empty()
+    }
+
+    fn collect_values<'a>(&'a self, output: &mut Vec<&'a V>) {
+        output.extend(&self.values);
+
+        for child in self.children.values() {
+            child.collect_values(output);
+        }
+    }
+
+    fn collect_entries<'a>(&'a self, path: &mut PathBuf, output: &mut Vec<(PathBuf, &'a [V])>) {
+        if !self.values.is_empty() {
+            output.push((path.clone(), &self.values));
+        }
+
+        for (component, child) in &self.children {
+            path.push(component);
+            child.collect_entries(path, output);
+            path.pop();
+        }
+    }
+}
+
+#[allow(dead_code)] // This is a core library-ish struct, unused stuff is ok
+impl<V: Eq> PathTrie<V> {
+    pub fn new() -> Self {
+        PathTrie {
+            root: PathTrieNode::new(),
+        }
+    }
+
+    /// Returns the values associated with exactly the given path.
+    pub fn get(&self, path: &Path) -> &[V] {
+        match self.find_node(path) {
+            Some(node) => &node.values,
+            None => &[],
+        }
+    }
+
+    /// Returns all of the values associated with the given path or any path
+    /// below it, ordered by path.
+    pub fn get_all_under(&self, path: &Path) -> Vec<&V> {
+        let mut output = Vec::new();
+
+        if let Some(node) = self.find_node(path) {
+            node.collect_values(&mut output);
+        }
+
+        output
+    }
+
+    /// Returns the values associated with the given path or, if there aren't
+    /// any, the values of its nearest ancestor that has some.
+    pub fn get_nearest_ancestor(&self, path: &Path) -> &[V] {
+        let mut node = &self.root;
+        let mut nearest: &[V] = &node.values;
+
+        for component in components(path) {
+            node = match node.children.get(component) {
+                Some(child) => child,
+                None => break,
+            };
+
+            if !node.values.is_empty() {
+                nearest = &node.values;
+            }
+        }
+
+        nearest
+    }
+
+    pub fn insert(&mut self, path: &Path, value: V) {
+        let mut node = &mut self.root;
+
+        for component in components(path) {
+            node = node
+                .children
+                .entry(component.to_os_string())
+                .or_insert_with(PathTrieNode::new);
+        }
+
+        if !node.values.contains(&value) {
+            node.values.push(value);
+        }
+    }
+
+    pub fn remove(&mut self, path: &Path, value: &V) -> Option<V> {
+        let components: Vec<&OsStr> = components(path).collect();
+        remove_from_node(&mut self.root, &components, value)
+    }
+
+    fn find_node(&self, path: &Path) -> Option<&PathTrieNode<V>> {
+        let mut node = &self.root;
+
+        for component in components(path) {
+            node = node.children.get(component)?;
+        }
+
+        Some(node)
+    }
+}
+
+/// Removes a value from the node at the given path below `node`, pruning any
+/// nodes that are left empty.
+fn remove_from_node<V: Eq>(
+    node: &mut PathTrieNode<V>,
+    components: &[&OsStr],
+    value: &V,
+) -> Option<V> {
+    match components.split_first() {
+        None => {
+            let index = node.values.iter().position(|existing| existing == value)?;
+            Some(node.values.swap_remove(index))
+        }
+        Some((first, rest)) => {
+            let child = node.children.get_mut(*first)?;
+            let removed_value = remove_from_node(child, rest, value);
+
+            if child.is_empty() {
+                node.children.remove(*first);
+            }
+
+            removed_value
+        }
+    }
+}
+
+fn components(path: &Path) -> impl Iterator<Item = &OsStr> {
+    path.components().map(|component| component.as_os_str())
+}
+
+impl<V: Debug> Debug for PathTrie<V> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut entries = Vec::new();
+        self.root.collect_entries(&mut PathBuf::new(), &mut entries);
+
+        formatter.debug_map().entries(entries).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn get_under_and_nearest_ancestor() {
+        let mut trie = PathTrie::new();
+        trie.insert(Path::new("/project/src"), 1);
+        trie.insert(Path::new("/project/src/foo.lua"), 2);
+        trie.insert(Path::new("/project/src/bar/baz.lua"), 3);
+        trie.insert(Path::new("/project/other.lua"), 4);
+
+        assert_eq!(trie.get(Path::new("/project/src")), &[1]);
+        assert_eq!(trie.get(Path::new("/project/src/bar")), &[] as &[i32]);
+
+        assert_eq!(
+            trie.get_all_under(Path::new("/project/src")),
+            vec![&1, &3, &2]
+        );
+
+        assert_eq!(
+            trie.get_nearest_ancestor(Path::new("/project/src/bar/new.lua")),
+            &[1]
+        );
+        assert_eq!(
+            trie.get_nearest_ancestor(Path::new("/elsewhere/new.lua")),
+            &[] as &[i32]
+        );
+    }
+
+    #[test]
+    fn remove_prunes_empty_nodes() {
+        let mut trie = PathTrie::new();
+        trie.insert(Path::new("/project/src/foo.lua"), 1);
+        trie.insert(Path::new("/project/src/foo.lua"), 2);
+
+        assert_eq!(trie.remove(Path::new("/project/src/foo.lua"), &1), Some(1));
+        assert_eq!(trie.get(Path::new("/project/src/foo.lua")), &[2]);
+        assert_eq!(trie.remove(Path::new("/project/src/foo.lua"), &1), None);
+
+        assert_eq!(trie.remove(Path::new("/project/src/foo.lua"), &2), Some(2));
+        assert!(trie.root.is_empty());
+    }
+}
@@ -8,7 +8,7 @@ use rbx_dom_weak::UnresolvedRbxValue;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 
-use crate::glob::Glob;
+use crate::{glob::Glob, trace};
 
 static PROJECT_FILENAME: &str = "default.project.json";
 
@@ -109,6 +109,8 @@ impl Project {
         contents: &[u8],
         project_file_location: &Path,
     ) -> Result<Self, serde_json::Error> {
+        let _span = trace::span_with_path("load_project", project_file_location);
+
         let mut project: Self = serde_json::from_slice(&contents)?;
         project.file_location = project_file_location.to_path_buf();
         project.check_compatibility();
@@ -126,6 +128,8 @@ impl Project {
     }
 
     fn load_exact(project_file_location: &Path) -> Result<Self, ProjectError> {
+        let _span = trace::span_with_path("load_project", project_file_location);
+
         let contents = fs::read_to_string(project_file_location).context(Io {
             path: project_file_location,
         })?;
@@ -153,6 +157,15 @@ impl Project {
     pub fn folder_location(&self) -> &Path {
         self.file_location.parent().unwrap()
     }
+
+    /// Returns the files and folders that this project pulls in with `$path`,
+    /// following `$path`s that point to other projects into those projects.
+    pub fn path_roots(&self) -> Vec<PathBuf> {
+        let mut roots = Vec::new();
+        self.tree
+            .collect_path_roots(self.folder_location(), &mut roots);
+        roots
+    }
 }
 
 /// Describes an instance and its descendants in a project.
@@ -201,6 +214,12 @@ pub struct ProjectNode {
     )]
     pub ignore_unknown_instances: Option<bool>,
 
+    /// Names of children that should come first, in the given order. Children
+    /// that aren't listed come after them, with children from `$path` before
+    /// children defined in the project.
+    #[serde(rename = "$order", skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<String>>,
+
     /// Defines that this instance should come from the given file path. This
     /// path can point to any file type supported by Rojo, including Lua files
     /// (`.lua`), Roblox models (`.rbxm`, `.rbxmx`), and localization table
@@ -214,6 +233,21 @@ pub struct ProjectNode {
 }
 
 impl ProjectNode {
+    fn collect_path_roots(&self, project_folder: &Path, roots: &mut Vec<PathBuf>) {
+        if let Some(path) = &self.path {
+            let path = project_folder.join(path);
+
+            match Project::load_fuzzy(&path) {
+                Ok(Some(project)) => roots.extend(project.path_roots()),
+                _ => roots.push(path),
+            }
+        }
+
+        for child in self.children.values() {
+            child.collect_path_roots(project_folder, roots);
+        }
+    }
+
     fn validate_reserved_names(&self) {
         for (name, child) in &self.children {
             if name.starts_with('$') {
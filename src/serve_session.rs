@@ -1,6 +1,7 @@
 use std::{
     collections::HashSet,
-    path::Path,
+    iter,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex, MutexGuard},
     time::Instant,
 };
@@ -10,15 +11,18 @@ use memofs::Vfs;
 use rbx_dom_weak::RbxInstanceProperties;
 
 use crate::{
-    change_processor::ChangeProcessor,
+    change_processor::{ChangeProcessor, SyncControl, TreeMutation},
+    diagnostics::{capture_warnings, Diagnostics},
+    glob::Glob,
     message_queue::MessageQueue,
     project::Project,
     session_id::SessionId,
     snapshot::{
         apply_patch_set, compute_patch_set, AppliedPatchSet, InstanceContext,
-        InstancePropertiesWithMeta, PatchSet, PathIgnoreRule, RojoTree,
+        InstancePropertiesWithMeta, PathIgnoreRule, RojoTree,
     },
     snapshot_middleware::snapshot_from_vfs,
+    write_sandbox::{default_backup_dir, WriteSandbox, ROJO_FOLDER_NAME},
 };
 
 /// Contains all of the state for a Rojo serve session.
@@ -57,6 +61,10 @@ pub struct ServeSession {
     /// their defaults.
     root_project: Option<Project>,
 
+    /// The folder that the project file is in, or that's being served if there
+    /// is no project file. Rojo keeps its own files for the project in here.
+    project_folder: PathBuf,
+
     /// A randomly generated ID for this serve session. It's used to ensure that
     /// a client doesn't begin connecting to a different server part way through
     /// an operation that needs to be atomic.
@@ -81,9 +89,17 @@ pub struct ServeSession {
     /// to be applied.
     message_queue: Arc<MessageQueue<AppliedPatchSet>>,
 
+    /// Errors and warnings from snapshotting the project's files, which
+    /// clients can show to users.
+    diagnostics: Arc<Diagnostics>,
+
     /// A channel to send mutation requests on. These will be handled by the
     /// ChangeProcessor and trigger changes in the tree.
-    tree_mutation_sender: Sender<PatchSet>,
+    tree_mutation_sender: Sender<TreeMutation>,
+
+    /// A channel to pause, resume, or redo syncing from the filesystem on.
+    /// These will be handled by the ChangeProcessor.
+    sync_control_sender: Sender<SyncControl>,
 }
 
 /// Methods that need thread-safety bounds on VfsFetcher are limited to this
@@ -118,6 +134,40 @@ impl ServeSession {
 
         let mut instance_context = InstanceContext::default();
 
+        let project_folder = match &root_project {
+            Some(project) => project.folder_location().to_path_buf(),
+            None if vfs
+                .metadata(start_path)
+                .map(|meta| meta.is_dir())
+                .unwrap_or(false) =>
+            {
+                start_path.to_path_buf()
+            }
+            None => start_path.parent().unwrap_or(start_path).to_path_buf(),
+        };
+
+        // Clients can only change the files that the project pulls in with
+        // $path, or the files being served if there is no project.
+        let write_roots = match &root_project {
+            Some(project) => project.path_roots(),
+            None => vec![start_path.to_path_buf()],
+        };
+
+        let backup_dir = default_backup_dir(&project_folder);
+        log::info!(
+            "Backups of files changed by clients will be kept in {}",
+            backup_dir.display()
+        );
+
+        // When the project pulls in the folder it's in, the backups would
+        // otherwise turn into instances.
+        if write_roots.iter().any(|root| backup_dir.starts_with(root)) {
+            instance_context.add_path_ignore_rules(iter::once(PathIgnoreRule {
+                glob: Glob::new(ROJO_FOLDER_NAME).unwrap(),
+                base_path: project_folder.clone(),
+            }));
+        }
+
         if let Some(project) = &root_project {
             let rules = project.glob_ignore_paths.iter().map(|glob| PathIgnoreRule {
                 glob: glob.clone(),
@@ -128,31 +178,44 @@ impl ServeSession {
         }
 
         log::trace!("Generating snapshot of instances from VFS");
-        let snapshot = snapshot_from_vfs(&instance_context, &vfs, &start_path)
+        let (snapshot, warnings) =
+            capture_warnings(|| snapshot_from_vfs(&instance_context, &vfs, &start_path));
+        let snapshot = snapshot
             .expect("snapshot failed")
             .expect("snapshot did not return an instance");
 
+        let diagnostics = Diagnostics::new();
+        diagnostics.replace(&[start_path.to_path_buf()], warnings);
+
         log::trace!("Computing initial patch set");
-        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        let patch_set = compute_patch_set(snapshot, &tree, root_id);
 
         log::trace!("Applying initial patch set");
-        apply_patch_set(&mut tree, patch_set);
+        apply_patch_set(&mut tree, patch_set).expect("initial patch set was invalid");
 
         let session_id = SessionId::new();
         let message_queue = MessageQueue::new();
 
         let tree = Arc::new(Mutex::new(tree));
         let message_queue = Arc::new(message_queue);
+        let diagnostics = Arc::new(diagnostics);
         let vfs = Arc::new(vfs);
 
         let (tree_mutation_sender, tree_mutation_receiver) = crossbeam_channel::unbounded();
+        let (sync_control_sender, sync_control_receiver) = crossbeam_channel::unbounded();
+
+        let write_sandbox = WriteSandbox::new(write_roots).backup_dir(backup_dir);
 
         log::trace!("Starting ChangeProcessor");
         let change_processor = ChangeProcessor::start(
             Arc::clone(&tree),
             Arc::clone(&vfs),
             Arc::clone(&message_queue),
+            Arc::clone(&diagnostics),
             tree_mutation_receiver,
+            sync_control_receiver,
+            write_sandbox,
+            start_path.to_path_buf(),
         );
 
         Self {
@@ -160,9 +223,12 @@ impl ServeSession {
             start_time,
             session_id,
             root_project,
+            project_folder,
             tree,
             message_queue,
+            diagnostics,
             tree_mutation_sender,
+            sync_control_sender,
             vfs,
         }
     }
@@ -177,10 +243,14 @@ impl ServeSession {
         self.tree.lock().unwrap()
     }
 
-    pub fn tree_mutation_sender(&self) -> Sender<PatchSet> {
+    pub fn tree_mutation_sender(&self) -> Sender<TreeMutation> {
         self.tree_mutation_sender.clone()
     }
 
+    pub fn sync_control_sender(&self) -> Sender<SyncControl> {
+        self.sync_control_sender.clone()
+    }
+
     pub fn vfs(&self) -> &Vfs {
         &self.vfs
     }
@@ -189,6 +259,14 @@ impl ServeSession {
         &self.message_queue
     }
 
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    pub fn diagnostics_handle(&self) -> Arc<Diagnostics> {
+        Arc::clone(&self.diagnostics)
+    }
+
     pub fn session_id(&self) -> SessionId {
         self.session_id
     }
@@ -205,6 +283,13 @@ impl ServeSession {
             .and_then(|project| project.serve_port)
     }
 
+    /// The folder that Rojo keeps its own files for the project in, like
+    /// backups of files changed by clients.
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn rojo_folder(&self) -> PathBuf {
+        self.project_folder.join(ROJO_FOLDER_NAME)
+    }
+
     pub fn start_time(&self) -> Instant {
         self.start_time
     }
@@ -224,14 +309,19 @@ impl ServeSession {
 mod serve_session {
     use super::*;
 
-    use std::{path::PathBuf, time::Duration};
+    use std::{collections::HashMap, path::PathBuf, time::Duration};
 
     use maplit::hashmap;
     use memofs::{InMemoryFs, VfsEvent, VfsSnapshot};
+    use rbx_dom_weak::RbxValue;
     use rojo_insta_ext::RedactionMap;
     use tokio::{runtime::Runtime, timer::Timeout};
 
-    use crate::tree_view::view_tree;
+    use crate::{
+        change_processor::{PropertyConflict, SyncCommand, SyncControl, SyncStatus},
+        snapshot::{PatchSet, PatchUpdate},
+        tree_view::{intern_tree, view_tree},
+    };
 
     #[test]
     fn just_folder() {
@@ -423,4 +513,763 @@ mod serve_session {
             view_tree(&session.tree(), &mut rm)
         );
     }
+
+    #[test]
+    fn change_project_node() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "change_project_node",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Value": {
+                                "$className": "StringValue",
+                                "$properties": {
+                                    "Value": "Before"
+                                }
+                            },
+
+                            "File": {
+                                "$path": "file.txt"
+                            }
+                        }
+                    }
+                "#),
+                "file.txt" => VfsSnapshot::file("initial content"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo");
+
+        let mut rm = RedactionMap::new();
+        insta::assert_yaml_snapshot!(
+            "change_project_node_before",
+            view_tree(&session.tree(), &mut rm)
+        );
+
+        // Changing this file without telling Rojo lets us check that the node
+        // it belongs to isn't snapshotted again.
+        imfs.load_snapshot("/foo/file.txt", VfsSnapshot::file("Changed!"))
+            .unwrap();
+
+        imfs.load_snapshot(
+            "/foo/default.project.json",
+            VfsSnapshot::file(
+                r#"
+                    {
+                        "name": "change_project_node",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Value": {
+                                "$className": "StringValue",
+                                "$properties": {
+                                    "Value": "After"
+                                }
+                            },
+
+                            "File": {
+                                "$path": "file.txt"
+                            },
+
+                            "Added": {
+                                "$className": "Folder"
+                            }
+                        }
+                    }
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let receiver = session.message_queue().subscribe_any();
+
+        imfs.raise_event(VfsEvent::Write(PathBuf::from("/foo/default.project.json")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        let result = rt.block_on(receiver).unwrap();
+
+        // The added instance isn't known to the redaction map yet.
+        intern_tree(&session.tree(), &mut rm);
+
+        insta::assert_yaml_snapshot!("change_project_node_patch", rm.redacted_yaml(result));
+        insta::assert_yaml_snapshot!(
+            "change_project_node_after",
+            view_tree(&session.tree(), &mut rm)
+        );
+    }
+
+    #[test]
+    fn change_project_clears_diagnostics() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "change_project_clears_diagnostics",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Model": {
+                                "$path": "model.model.json"
+                            },
+
+                            "Other": {
+                                "$className": "Folder"
+                            }
+                        }
+                    }
+                "#),
+                "model.model.json" => VfsSnapshot::file(r#"
+                    {
+                        "Name": "WrongName",
+                        "ClassName": "Folder"
+                    }
+                "#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo");
+
+        let (_, diagnostics) = session.diagnostics().current();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, Path::new("/foo/model.model.json"));
+
+        // Removing the node that the model came from only snapshots part of
+        // the project again, which still has to clear the model's warning.
+        imfs.load_snapshot(
+            "/foo/default.project.json",
+            VfsSnapshot::file(
+                r#"
+                    {
+                        "name": "change_project_clears_diagnostics",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Other": {
+                                "$className": "Folder"
+                            }
+                        }
+                    }
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let receiver = session.message_queue().subscribe_any();
+
+        imfs.raise_event(VfsEvent::Write(PathBuf::from("/foo/default.project.json")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        let (_, messages) = rt.block_on(receiver).unwrap();
+        assert_eq!(messages[0].removed.len(), 1);
+
+        assert_eq!(session.diagnostics().current().1, Vec::new());
+    }
+
+    #[test]
+    fn rename_project_clears_nested_diagnostics() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "before",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Src": {
+                                "$path": "src"
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "model.model.json" => VfsSnapshot::file(r#"
+                        {
+                            "Name": "WrongName",
+                            "ClassName": "Folder"
+                        }
+                    "#),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo/default.project.json");
+
+        let (_, diagnostics) = session.diagnostics().current();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, Path::new("/foo/src/model.model.json"));
+
+        // The model is fixed without telling Rojo, so only snapshotting the
+        // whole project again can notice.
+        imfs.load_snapshot(
+            "/foo/src/model.model.json",
+            VfsSnapshot::file(r#"{ "ClassName": "Folder" }"#),
+        )
+        .unwrap();
+
+        // Renaming the project means it has to be snapshotted in full.
+        imfs.load_snapshot(
+            "/foo/default.project.json",
+            VfsSnapshot::file(
+                r#"
+                    {
+                        "name": "after",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Src": {
+                                "$path": "src"
+                            }
+                        }
+                    }
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let receiver = session.message_queue().subscribe_any();
+
+        imfs.raise_event(VfsEvent::Write(PathBuf::from("/foo/default.project.json")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(receiver).unwrap();
+
+        assert_eq!(session.diagnostics().current().1, Vec::new());
+    }
+
+    #[test]
+    fn replace_folder_in_project() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "replace_folder_in_project",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "A": {
+                                "$path": "src/a.txt"
+                            },
+
+                            "B": {
+                                "$path": "src/b.txt"
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "a.txt" => VfsSnapshot::file("a before"),
+                    "b.txt" => VfsSnapshot::file("b before"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo");
+
+        let mut rm = RedactionMap::new();
+        insta::assert_yaml_snapshot!(
+            "replace_folder_in_project_before",
+            view_tree(&session.tree(), &mut rm)
+        );
+
+        // No instance comes from the folder itself, only from files inside it,
+        // which should all be updated by the one event.
+        imfs.load_snapshot(
+            "/foo/src",
+            VfsSnapshot::dir(hashmap! {
+                "a.txt" => VfsSnapshot::file("a after"),
+                "b.txt" => VfsSnapshot::file("b after"),
+            }),
+        )
+        .unwrap();
+
+        let receiver = session.message_queue().subscribe_any();
+
+        imfs.raise_event(VfsEvent::Create(PathBuf::from("/foo/src")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        let result = rt.block_on(receiver).unwrap();
+
+        insta::assert_yaml_snapshot!("replace_folder_in_project_patch", rm.redacted_yaml(result));
+        insta::assert_yaml_snapshot!(
+            "replace_folder_in_project_after",
+            view_tree(&session.tree(), &mut rm)
+        );
+    }
+
+    #[test]
+    fn add_file_to_project_root_path() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "add_file_to_project_root_path",
+                        "tree": {
+                            "$path": "src"
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "a.txt" => VfsSnapshot::file("a"),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo");
+
+        let mut rm = RedactionMap::new();
+        insta::assert_yaml_snapshot!(
+            "add_file_to_project_root_path_before",
+            view_tree(&session.tree(), &mut rm)
+        );
+
+        // The project file itself is unchanged, so the root has to be
+        // snapshotted again from the folder it points to.
+        imfs.load_snapshot("/foo/src/b.txt", VfsSnapshot::file("b"))
+            .unwrap();
+
+        let receiver = session.message_queue().subscribe_any();
+
+        imfs.raise_event(VfsEvent::Create(PathBuf::from("/foo/src/b.txt")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        let result = rt.block_on(receiver).unwrap();
+
+        intern_tree(&session.tree(), &mut rm);
+
+        insta::assert_yaml_snapshot!(
+            "add_file_to_project_root_path_patch",
+            rm.redacted_yaml(result)
+        );
+        insta::assert_yaml_snapshot!(
+            "add_file_to_project_root_path_after",
+            view_tree(&session.tree(), &mut rm)
+        );
+    }
+
+    #[test]
+    fn change_project_root_path_only() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "change_project_root_path_only",
+                        "tree": {
+                            "$path": "src",
+
+                            "Other": {
+                                "$path": "other.txt"
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "a.txt" => VfsSnapshot::file("a"),
+                }),
+                "other.txt" => VfsSnapshot::file("other before"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo");
+
+        // Changing this file without telling Rojo lets us check that the
+        // project's own nodes aren't snapshotted again.
+        imfs.load_snapshot("/foo/other.txt", VfsSnapshot::file("other after"))
+            .unwrap();
+        imfs.load_snapshot("/foo/src/b.txt", VfsSnapshot::file("b"))
+            .unwrap();
+
+        let receiver = session.message_queue().subscribe_any();
+
+        imfs.raise_event(VfsEvent::Create(PathBuf::from("/foo/src/b.txt")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        let (_, messages) = rt.block_on(receiver).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].added.len(), 1);
+        assert!(messages[0].removed.is_empty());
+        assert!(messages[0].updated.is_empty());
+
+        let tree = session.tree();
+        let root = tree.get_instance(tree.get_root_id()).unwrap();
+        let children: Vec<_> = root
+            .children()
+            .iter()
+            .map(|&id| tree.get_instance(id).unwrap())
+            .collect();
+
+        let names: Vec<_> = children.iter().map(|child| child.name()).collect();
+        assert_eq!(names, vec!["a", "b", "Other"]);
+
+        assert_eq!(
+            children[2].properties().get("Value"),
+            Some(&RbxValue::String {
+                value: "other before".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn conflicting_write() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.txt", VfsSnapshot::file("Hello!"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let session = ServeSession::new(vfs, "/foo.txt");
+        let root_id = session.tree().get_root_id();
+
+        let (response_sender, response_receiver) = futures::sync::oneshot::channel();
+
+        let update = PatchUpdate {
+            id: root_id,
+            changed_name: None,
+            changed_class_name: None,
+            changed_properties: hashmap! {
+                "Value".to_owned() => Some(RbxValue::String {
+                    value: "From Studio".to_owned(),
+                }),
+            },
+            changed_metadata: None,
+        };
+
+        session
+            .tree_mutation_sender()
+            .send(TreeMutation {
+                patch_set: PatchSet {
+                    updated_instances: vec![update],
+                    ..PatchSet::new()
+                },
+                previous_properties: hashmap! {
+                    root_id => hashmap! {
+                        "Value".to_owned() => Some(RbxValue::String {
+                            value: "Stale".to_owned(),
+                        }),
+                    },
+                },
+                response_sender: Some(response_sender),
+            })
+            .unwrap();
+
+        let receiver = Timeout::new(response_receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        let conflicts = rt.block_on(receiver).unwrap().unwrap();
+
+        let current_value = RbxValue::String {
+            value: "Hello!".to_owned(),
+        };
+
+        assert_eq!(
+            conflicts,
+            vec![PropertyConflict {
+                id: root_id,
+                property: "Value".to_owned(),
+                expected_value: Some(RbxValue::String {
+                    value: "Stale".to_owned(),
+                }),
+                current_value: Some(current_value.clone()),
+            }]
+        );
+
+        let tree = session.tree();
+        let root = tree.get_instance(root_id).unwrap();
+        assert_eq!(root.properties().get("Value"), Some(&current_value));
+    }
+
+    #[test]
+    fn own_write_is_not_echoed() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo.lua", VfsSnapshot::file("-- before"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo.lua");
+        let root_id = session.tree().get_root_id();
+
+        let (response_sender, response_receiver) = futures::sync::oneshot::channel();
+
+        let update = PatchUpdate {
+            id: root_id,
+            changed_name: None,
+            changed_class_name: None,
+            changed_properties: hashmap! {
+                "Source".to_owned() => Some(RbxValue::String {
+                    value: "-- after".to_owned(),
+                }),
+            },
+            changed_metadata: None,
+        };
+
+        session
+            .tree_mutation_sender()
+            .send(TreeMutation {
+                patch_set: PatchSet {
+                    updated_instances: vec![update],
+                    ..PatchSet::new()
+                },
+                previous_properties: HashMap::new(),
+                response_sender: Some(response_sender),
+            })
+            .unwrap();
+
+        let mut rt = Runtime::new().unwrap();
+
+        let receiver = Timeout::new(response_receiver, Duration::from_millis(200));
+        rt.block_on(receiver).unwrap().unwrap();
+
+        let cursor = session.message_queue().cursor();
+        assert_eq!(cursor, 1);
+
+        // The write to the file raises an event, which shouldn't produce
+        // another message.
+        imfs.raise_event(VfsEvent::Write(PathBuf::from("/foo.lua")));
+
+        let receiver = Timeout::new(
+            session.message_queue().subscribe(cursor),
+            Duration::from_millis(200),
+        );
+        assert!(rt.block_on(receiver).is_err());
+
+        let contents = Vfs::new(imfs).read("/foo.lua").unwrap();
+        assert_eq!(contents.as_slice(), b"-- after");
+    }
+
+    fn send_sync_command(session: &ServeSession, command: SyncCommand) -> SyncStatus {
+        let (response_sender, response_receiver) = futures::sync::oneshot::channel();
+
+        session
+            .sync_control_sender()
+            .send(SyncControl {
+                command,
+                response_sender: Some(response_sender),
+            })
+            .unwrap();
+
+        let receiver = Timeout::new(response_receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(receiver).unwrap()
+    }
+
+    #[test]
+    fn pause_and_resume_sync() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/root",
+            VfsSnapshot::dir(hashmap! {
+                "a.txt" => VfsSnapshot::file("A"),
+                "b.txt" => VfsSnapshot::file("B"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/root");
+
+        let status = send_sync_command(&session, SyncCommand::Pause);
+        assert_eq!(
+            status,
+            SyncStatus {
+                paused: true,
+                buffered_paths: 0,
+            }
+        );
+
+        for (name, contents) in &[("a.txt", "A2"), ("b.txt", "B2"), ("a.txt", "A3")] {
+            let path = PathBuf::from("/root").join(name);
+            imfs.load_snapshot(&path, VfsSnapshot::file(*contents))
+                .unwrap();
+            imfs.raise_event(VfsEvent::Write(path));
+        }
+
+        // Events and commands arrive on different channels, so wait for the
+        // events to be buffered before resuming.
+        let mut status = send_sync_command(&session, SyncCommand::Pause);
+        for _ in 0..20 {
+            if status.buffered_paths == 2 {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(10));
+            status = send_sync_command(&session, SyncCommand::Pause);
+        }
+        assert_eq!(status.buffered_paths, 2);
+        assert_eq!(session.message_queue().cursor(), 0);
+
+        let status = send_sync_command(&session, SyncCommand::Resume);
+        assert_eq!(
+            status,
+            SyncStatus {
+                paused: false,
+                buffered_paths: 0,
+            }
+        );
+
+        // Both files are synced in one message.
+        let receiver = Timeout::new(
+            session.message_queue().subscribe(0),
+            Duration::from_millis(200),
+        );
+        let mut rt = Runtime::new().unwrap();
+        let (cursor, messages) = rt.block_on(receiver).unwrap();
+        assert_eq!(cursor, 1);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].updated.len(), 2);
+
+        let tree = session.tree();
+        let root = tree.get_instance(tree.get_root_id()).unwrap();
+        let values: Vec<_> = root
+            .children()
+            .iter()
+            .map(|&id| tree.get_instance(id).unwrap().properties().get("Value"))
+            .collect();
+        assert!(values.contains(&Some(&RbxValue::String {
+            value: "A3".to_owned()
+        })));
+        assert!(values.contains(&Some(&RbxValue::String {
+            value: "B2".to_owned()
+        })));
+    }
+
+    #[test]
+    fn resync_without_events() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/root",
+            VfsSnapshot::dir(hashmap! {
+                "a.txt" => VfsSnapshot::file("A"),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/root");
+
+        // No event is raised for the new file, like when an event is missed.
+        imfs.load_snapshot("/root/b.txt", VfsSnapshot::file("B"))
+            .unwrap();
+
+        let status = send_sync_command(&session, SyncCommand::Resync);
+        assert!(!status.paused);
+
+        let receiver = Timeout::new(
+            session.message_queue().subscribe(0),
+            Duration::from_millis(200),
+        );
+        let mut rt = Runtime::new().unwrap();
+        let (_, messages) = rt.block_on(receiver).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].added.len(), 1);
+
+        let tree = session.tree();
+        let root = tree.get_instance(tree.get_root_id()).unwrap();
+        assert_eq!(root.children().len(), 2);
+    }
+
+    #[test]
+    fn resync_clears_diagnostics() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "default.project.json" => VfsSnapshot::file(r#"
+                    {
+                        "name": "resync_clears_diagnostics",
+                        "tree": {
+                            "$className": "Folder",
+
+                            "Src": {
+                                "$path": "src"
+                            }
+                        }
+                    }
+                "#),
+                "src" => VfsSnapshot::dir(hashmap! {
+                    "model.model.json" => VfsSnapshot::file(r#"{ "ClassName": "Folder" }"#),
+                }),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs.clone());
+
+        let session = ServeSession::new(vfs, "/foo/default.project.json");
+        assert_eq!(session.diagnostics().current().1, Vec::new());
+
+        let receiver = session.diagnostics().subscribe(0);
+
+        imfs.load_snapshot("/foo/src/model.model.json", VfsSnapshot::file("{ not json"))
+            .unwrap();
+        imfs.raise_event(VfsEvent::Write(PathBuf::from("/foo/src/model.model.json")));
+
+        let receiver = Timeout::new(receiver, Duration::from_millis(200));
+
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(receiver).unwrap();
+
+        let (_, diagnostics) = session.diagnostics().current();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].path, Path::new("/foo/src/model.model.json"));
+
+        // The file is fixed without telling Rojo, like when an event is
+        // missed, which a resync recovers from.
+        imfs.load_snapshot(
+            "/foo/src/model.model.json",
+            VfsSnapshot::file(r#"{ "ClassName": "Folder" }"#),
+        )
+        .unwrap();
+
+        send_sync_command(&session, SyncCommand::Resync);
+
+        assert_eq!(session.diagnostics().current().1, Vec::new());
+    }
 }
@@ -1,16 +1,15 @@
 //! Defines the structure of an instance snapshot.
 
-use std::{borrow::Cow, collections::HashMap};
+use std::{borrow::Cow, collections::HashMap, mem, path::PathBuf};
 
 use rbx_dom_weak::{RbxId, RbxTree, RbxValue};
 use serde::{Deserialize, Serialize};
 
-use super::InstanceMetadata;
+use super::{InstanceMetadata, RojoTree};
 
 /// A lightweight description of what an instance should look like.
 ///
 // Possible future improvements:
-// - Use refcounted/interned strings
 // - Replace use of RbxValue with a sum of RbxValue + borrowed value
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InstanceSnapshot {
@@ -47,16 +46,16 @@ impl InstanceSnapshot {
         }
     }
 
-    pub fn name(self, name: impl Into<String>) -> Self {
+    pub fn name(self, name: impl Into<Cow<'static, str>>) -> Self {
         Self {
-            name: Cow::Owned(name.into()),
+            name: name.into(),
             ..self
         }
     }
 
-    pub fn class_name(self, class_name: impl Into<String>) -> Self {
+    pub fn class_name(self, class_name: impl Into<Cow<'static, str>>) -> Self {
         Self {
-            class_name: Cow::Owned(class_name.into()),
+            class_name: class_name.into(),
             ..self
         }
     }
@@ -82,24 +81,68 @@ impl InstanceSnapshot {
         }
     }
 
-    pub fn from_tree(tree: &RbxTree, id: RbxId) -> Self {
+    /// Returns the relevant paths of this snapshot and all of its children.
+    pub fn relevant_paths_in_subtree(&self) -> Vec<PathBuf> {
+        let mut paths = self.metadata.relevant_paths.clone();
+
+        for child in &self.children {
+            paths.extend(child.relevant_paths_in_subtree());
+        }
+
+        paths
+    }
+
+    /// Creates a snapshot that matches the given instance and its descendants
+    /// in a `RojoTree`, including their metadata, so that diffing it against
+    /// the tree finds no changes.
+    pub fn from_tree(tree: &RojoTree, id: RbxId) -> Self {
         let instance = tree
             .get_instance(id)
             .expect("instance did not exist in tree");
 
         let children = instance
-            .get_children_ids()
+            .children()
             .iter()
-            .cloned()
-            .map(|id| Self::from_tree(tree, id))
+            .map(|&id| Self::from_tree(tree, id))
+            .collect();
+
+        Self {
+            snapshot_id: None,
+            metadata: instance.metadata().clone(),
+            name: Cow::Owned(instance.name().to_owned()),
+            class_name: Cow::Owned(instance.class_name().to_owned()),
+            properties: instance.properties().clone(),
+            children,
+        }
+    }
+
+    /// Creates a snapshot of the given instance and its descendants, moving
+    /// their names and properties out of the tree instead of copying them.
+    ///
+    /// The instances are left in the tree with empty names, class names, and
+    /// properties, so this is meant for trees that are thrown away afterwards,
+    /// like ones just decoded from a model file.
+    pub fn take_from_tree(tree: &mut RbxTree, id: RbxId) -> Self {
+        let instance = tree
+            .get_instance_mut(id)
+            .expect("instance did not exist in tree");
+
+        let name = mem::take(&mut instance.name);
+        let class_name = mem::take(&mut instance.class_name);
+        let properties = mem::take(&mut instance.properties);
+        let children_ids = instance.get_children_ids().to_vec();
+
+        let children = children_ids
+            .into_iter()
+            .map(|id| Self::take_from_tree(tree, id))
             .collect();
 
         Self {
             snapshot_id: Some(id),
             metadata: InstanceMetadata::default(),
-            name: Cow::Owned(instance.name.clone()),
-            class_name: Cow::Owned(instance.class_name.clone()),
-            properties: instance.properties.clone(),
+            name: Cow::Owned(name),
+            class_name: Cow::Owned(class_name),
+            properties,
             children,
         }
     }
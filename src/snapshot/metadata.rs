@@ -6,7 +6,11 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
-use crate::{glob::Glob, path_serializer, project::ProjectNode};
+use crate::{
+    glob::Glob,
+    path_serializer,
+    project::{Project, ProjectNode},
+};
 
 /// Rojo-specific metadata that can be associated with an instance or a snapshot
 /// of an instance.
@@ -52,6 +56,13 @@ pub struct InstanceMetadata {
     /// that instance's instigating source is snapshotted directly, the same
     /// context will be passed into it.
     pub context: InstanceContext,
+
+    /// For the root instance of a project file, the project that it was
+    /// created from. When the project file changes, this is compared against
+    /// the new project so that only the nodes that changed are snapshotted
+    /// again.
+    #[serde(skip)]
+    pub source_project: Option<Arc<Project>>,
 }
 
 impl InstanceMetadata {
@@ -61,6 +72,7 @@ impl InstanceMetadata {
             instigating_source: None,
             relevant_paths: Vec::new(),
             context: InstanceContext::default(),
+            source_project: None,
         }
     }
 
@@ -27,10 +27,12 @@
 //!    See `src/snapshot/patch_compute.rs` for implementation.
 //!
 //! 3. Patch sets are applied to the tree with `apply_patch_set`, which
-//!    mutates the relevant instances. `apply_patch_set` returns a new
-//!    object, `AppliedPatchSet`. Applied patch sets describe the transform
-//!    that was applied, and are suitable for cases where another tree needs
-//!    to be synchronized with Rojo's, like the Rojo Studio plugin.
+//!    first checks that the whole patch can be applied with
+//!    `validate_patch_set`, then mutates the relevant instances.
+//!    `apply_patch_set` returns a new object, `AppliedPatchSet`. Applied
+//!    patch sets describe the transform that was applied, and are suitable
+//!    for cases where another tree needs to be synchronized with Rojo's, like
+//!    the Rojo Studio plugin.
 //!
 //!    See `src/snapshot/patch_apply.rs` for implementation.
 //!
@@ -53,6 +55,8 @@ mod metadata;
 mod patch;
 mod patch_apply;
 mod patch_compute;
+mod patch_validate;
+mod shared_values;
 mod tree;
 
 pub use instance_snapshot::InstanceSnapshot;
@@ -60,6 +64,8 @@ pub use metadata::*;
 pub use patch::*;
 pub use patch_apply::apply_patch_set;
 pub use patch_compute::compute_patch_set;
+pub use patch_validate::{validate_patch_set, PatchError};
+pub use shared_values::SharedValues;
 pub use tree::*;
 
 #[cfg(test)]
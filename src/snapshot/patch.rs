@@ -1,6 +1,6 @@
 //! Defines the data structures used for describing instance patches.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use rbx_dom_weak::{RbxId, RbxValue};
 use serde::{Deserialize, Serialize};
@@ -17,6 +17,9 @@ pub struct PatchSet {
     pub removed_instances: Vec<RbxId>,
     pub added_instances: Vec<PatchAdd>,
     pub updated_instances: Vec<PatchUpdate>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reordered_children: Vec<PatchReorder>,
 }
 
 impl<'a> PatchSet {
@@ -25,8 +28,18 @@ impl<'a> PatchSet {
             removed_instances: Vec::new(),
             added_instances: Vec::new(),
             updated_instances: Vec::new(),
+            reordered_children: Vec::new(),
         }
     }
+
+    /// Moves all of the changes from another `PatchSet` into this one. The two
+    /// patches should describe separate parts of the tree.
+    pub fn append(&mut self, other: PatchSet) {
+        self.removed_instances.extend(other.removed_instances);
+        self.added_instances.extend(other.added_instances);
+        self.updated_instances.extend(other.updated_instances);
+        self.reordered_children.extend(other.reordered_children);
+    }
 }
 
 /// A patch containing an instance that was added to the tree.
@@ -51,6 +64,17 @@ pub struct PatchUpdate {
     pub changed_metadata: Option<InstanceMetadata>,
 }
 
+/// A patch indicating that the children of an instance need to be put into a
+/// new order.
+///
+/// In a `PatchSet`, `children` can contain the snapshot IDs of instances added
+/// by the same patch. Once applied, every entry refers to an instance ID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchReorder {
+    pub parent_id: RbxId,
+    pub children: Vec<RbxId>,
+}
+
 /// Applied patch sets have the same rough shape as PatchSet, but are
 /// descriptive of the operation that happened instead of prescribing what
 /// mutations to apply to the tree.
@@ -58,14 +82,14 @@ pub struct PatchUpdate {
 /// Applied patch sets are generated by applying a patch to a tree, and are
 /// suitable for sending over the network to a synchronized tree like the Rojo
 /// Studio plugin.
-///
-// TODO: Introduce machinery to detect conflicts, like keeping previous +
-// current values in all fields.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppliedPatchSet {
     pub removed: Vec<RbxId>,
     pub added: Vec<RbxId>,
     pub updated: Vec<AppliedPatchUpdate>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reordered: Vec<PatchReorder>,
 }
 
 impl AppliedPatchSet {
@@ -74,19 +98,52 @@ impl AppliedPatchSet {
             removed: Vec::new(),
             added: Vec::new(),
             updated: Vec::new(),
+            reordered: Vec::new(),
         }
     }
+
+    /// Tells whether applying the patch didn't change anything.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+            && self.added.is_empty()
+            && self.updated.is_empty()
+            && self.reordered.is_empty()
+    }
+
+    /// Adds the changes from another patch that was applied after this one.
+    pub fn append(&mut self, other: AppliedPatchSet) {
+        self.removed.extend(other.removed);
+        self.added.extend(other.added);
+        self.updated.extend(other.updated);
+        self.reordered.extend(other.reordered);
+    }
 }
 
+/// Describes a change that was made to an instance, along with the values
+/// that were replaced, which can be used to detect conflicting changes.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppliedPatchUpdate {
     pub id: RbxId,
 
-    // TODO: Store previous values in order to detect application conflicts
     pub changed_name: Option<String>,
     pub changed_class_name: Option<String>,
-    pub changed_properties: HashMap<String, Option<RbxValue>>,
+
+    /// The new values of the properties that changed. Large strings are shared
+    /// with other applied patches that have the same value through the tree's
+    /// `SharedValues`.
+    pub changed_properties: HashMap<String, Option<Arc<RbxValue>>>,
     pub changed_metadata: Option<InstanceMetadata>,
+
+    /// The name of the instance before it was changed, if it was changed.
+    pub previous_name: Option<String>,
+
+    /// The class name of the instance before it was changed, if it was
+    /// changed.
+    pub previous_class_name: Option<String>,
+
+    /// The values of all properties in `changed_properties` before they were
+    /// changed. A value of `None` means that the property did not exist.
+    pub previous_properties: HashMap<String, Option<Arc<RbxValue>>>,
 }
 
 impl AppliedPatchUpdate {
@@ -97,6 +154,9 @@ impl AppliedPatchUpdate {
             changed_class_name: None,
             changed_properties: HashMap::new(),
             changed_metadata: None,
+            previous_name: None,
+            previous_class_name: None,
+            previous_properties: HashMap::new(),
         }
     }
 }
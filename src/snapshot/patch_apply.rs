@@ -4,15 +4,29 @@ use std::collections::HashMap;
 
 use rbx_dom_weak::{RbxId, RbxInstanceProperties, RbxValue};
 
+use crate::{metrics, trace};
+
 use super::{
-    patch::{AppliedPatchSet, AppliedPatchUpdate, PatchSet, PatchUpdate},
+    patch::{AppliedPatchSet, AppliedPatchUpdate, PatchReorder, PatchSet, PatchUpdate},
+    patch_validate::{validate_patch_set, PatchError},
     InstancePropertiesWithMeta, InstanceSnapshot, RojoTree,
 };
 
 /// Consumes the input `PatchSet`, applying all of its prescribed changes to the
 /// tree and returns an `AppliedPatchSet`, which can be used to keep another
 /// tree in sync with Rojo's.
-pub fn apply_patch_set(tree: &mut RojoTree, patch_set: PatchSet) -> AppliedPatchSet {
+///
+/// The patch is validated against the tree before anything is changed, so if
+/// an error is returned, the tree is left untouched.
+pub fn apply_patch_set(
+    tree: &mut RojoTree,
+    patch_set: PatchSet,
+) -> Result<AppliedPatchSet, PatchError> {
+    let _timer = metrics::PATCH_APPLY_DURATION.start_timer("");
+    let _span = trace::span("apply_patch_set");
+
+    validate_patch_set(tree, &patch_set)?;
+
     let mut context = PatchApplyContext::default();
 
     for removed_id in patch_set.removed_instances {
@@ -29,7 +43,13 @@ pub fn apply_patch_set(tree: &mut RojoTree, patch_set: PatchSet) -> AppliedPatch
         apply_update_child(&mut context, tree, update_patch);
     }
 
-    finalize_patch_application(context, tree)
+    // Reorders can refer to instances added by this patch, so they also need to
+    // be applied after additions.
+    for reorder_patch in patch_set.reordered_children {
+        apply_reorder_children(&mut context, tree, reorder_patch);
+    }
+
+    Ok(finalize_patch_application(context, tree))
 }
 
 /// All of the ephemeral state needing during application of a patch.
@@ -109,14 +129,10 @@ fn finalize_patch_application(context: PatchApplyContext, tree: &mut RojoTree) -
 }
 
 fn apply_remove_instance(context: &mut PatchApplyContext, tree: &mut RojoTree, removed_id: RbxId) {
-    match tree.remove_instance(removed_id) {
-        Some(_) => context.applied_patch_set.removed.push(removed_id),
-        None => {
-            log::warn!(
-                "Patch misapplication: Tried to remove instance {} but it did not exist.",
-                removed_id
-            );
-        }
+    // Validation makes sure that the instance existed, so if it's gone now, it
+    // was removed along with an ancestor earlier in this patch.
+    if tree.remove_instance(removed_id).is_some() {
+        context.applied_patch_set.removed.push(removed_id);
     }
 }
 
@@ -175,17 +191,32 @@ fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patc
     };
 
     if let Some(name) = patch.changed_name {
-        *instance.name_mut() = name.clone();
+        let previous_name = std::mem::replace(instance.name_mut(), name.clone());
+        applied_patch.previous_name = Some(previous_name);
         applied_patch.changed_name = Some(name);
     }
 
     if let Some(class_name) = patch.changed_class_name {
-        *instance.class_name_mut() = class_name.clone();
+        let previous_class_name = std::mem::replace(instance.class_name_mut(), class_name.clone());
+        applied_patch.previous_class_name = Some(previous_class_name);
         applied_patch.changed_class_name = Some(class_name);
     }
 
     for (key, property_entry) in patch.changed_properties {
-        match property_entry {
+        // Values in the applied patch are shared through the tree, so a value
+        // that an earlier patch changed this property to is reused here.
+        let previous_value = tree.get_shared_property(patch.id, &key);
+        applied_patch
+            .previous_properties
+            .insert(key.clone(), previous_value);
+
+        let property_entry = property_entry.map(|value| tree.share_value(value));
+
+        let mut instance = tree
+            .get_instance_mut(patch.id)
+            .expect("instance was removed while being updated");
+
+        match property_entry.as_deref() {
             // Ref values need to be potentially rewritten from snapshot IDs to
             // instance IDs if they referred to an instance that was created as
             // part of this patch.
@@ -196,9 +227,9 @@ fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patc
                 // for more info.
                 let new_id = context
                     .snapshot_id_to_instance_id
-                    .get(&id)
+                    .get(id)
                     .copied()
-                    .unwrap_or(id);
+                    .unwrap_or(*id);
 
                 instance.properties_mut().insert(
                     key.clone(),
@@ -207,7 +238,7 @@ fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patc
                     },
                 );
             }
-            Some(ref value) => {
+            Some(value) => {
                 instance.properties_mut().insert(key.clone(), value.clone());
             }
             None => {
@@ -221,11 +252,43 @@ fn apply_update_child(context: &mut PatchApplyContext, tree: &mut RojoTree, patc
     context.applied_patch_set.updated.push(applied_patch)
 }
 
+fn apply_reorder_children(
+    context: &mut PatchApplyContext,
+    tree: &mut RojoTree,
+    patch: PatchReorder,
+) {
+    // Children added by this patch are referred to by their snapshot ID.
+    let children: Vec<RbxId> = patch
+        .children
+        .iter()
+        .map(|id| {
+            context
+                .snapshot_id_to_instance_id
+                .get(id)
+                .copied()
+                .unwrap_or(*id)
+        })
+        .collect();
+
+    if tree.reorder_children(patch.parent_id, &children) {
+        context.applied_patch_set.reordered.push(PatchReorder {
+            parent_id: patch.parent_id,
+            children,
+        });
+    } else {
+        log::warn!(
+            "Patch misapplication: Tried to reorder the children of instance {}, \
+             but not all of the given instances were its children.",
+            patch.parent_id
+        );
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use std::{borrow::Cow, collections::HashMap};
+    use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
     use maplit::hashmap;
     use rbx_dom_weak::RbxValue;
@@ -266,7 +329,7 @@ mod test {
             ..Default::default()
         };
 
-        apply_patch_set(&mut tree, patch_set);
+        apply_patch_set(&mut tree, patch_set).unwrap();
 
         let root_instance = tree.get_instance(root_id).unwrap();
         let child_id = root_instance.children()[0];
@@ -319,7 +382,7 @@ mod test {
             ..Default::default()
         };
 
-        apply_patch_set(&mut tree, patch_set);
+        apply_patch_set(&mut tree, patch_set).unwrap();
 
         let expected_properties = hashmap! {
             "Foo".to_owned() => RbxValue::Int32 { value: 8 },
@@ -332,4 +395,59 @@ mod test {
         assert_eq!(root_instance.class_name(), "NewClassName");
         assert_eq!(root_instance.properties(), &expected_properties);
     }
+
+    #[test]
+    fn updates_share_values() {
+        let _ = env_logger::try_init();
+
+        let source = |contents: &str| RbxValue::String {
+            value: contents.repeat(1000),
+        };
+
+        let mut tree = RojoTree::new(InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Script".to_owned(),
+                class_name: "ModuleScript".to_owned(),
+                properties: hashmap! {
+                    "Source".to_owned() => source("a"),
+                },
+            },
+            metadata: Default::default(),
+        });
+
+        let root_id = tree.get_root_id();
+
+        let mut apply_source = |contents: &str| {
+            let patch_set = PatchSet {
+                updated_instances: vec![PatchUpdate {
+                    id: root_id,
+                    changed_name: None,
+                    changed_class_name: None,
+                    changed_properties: hashmap! {
+                        "Source".to_owned() => Some(source(contents)),
+                    },
+                    changed_metadata: None,
+                }],
+                ..Default::default()
+            };
+
+            let mut applied = apply_patch_set(&mut tree, patch_set).unwrap();
+            applied.updated.remove(0)
+        };
+
+        let first = apply_source("b");
+        let second = apply_source("c");
+
+        // The value the first patch changed Source to is the same allocation
+        // as the value the second patch replaced.
+        let changed = first.changed_properties["Source"].as_ref().unwrap();
+        let previous = second.previous_properties["Source"].as_ref().unwrap();
+        assert_eq!(**changed, source("b"));
+        assert!(Arc::ptr_eq(changed, previous));
+
+        assert_eq!(
+            tree.get_instance(root_id).unwrap().properties()["Source"],
+            source("c")
+        );
+    }
 }
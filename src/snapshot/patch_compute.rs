@@ -1,16 +1,29 @@
 //! Defines the algorithm for computing a roughly-minimal patch set given an
 //! existing instance tree and an instance snapshot.
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
 
 use rbx_dom_weak::{RbxId, RbxValue};
 
+use crate::{metrics, trace};
+
 use super::{
-    patch::{PatchAdd, PatchSet, PatchUpdate},
-    InstanceSnapshot, InstanceWithMeta, RojoTree,
+    patch::{PatchAdd, PatchReorder, PatchSet, PatchUpdate},
+    InstanceMetadata, InstanceSnapshot, InstanceWithMeta, InstigatingSource, RojoTree,
 };
 
-pub fn compute_patch_set(snapshot: &InstanceSnapshot, tree: &RojoTree, id: RbxId) -> PatchSet {
+/// Consumes the given snapshot, computing the changes needed to make the
+/// instance with the given ID and its descendants match it.
+///
+/// The snapshot is consumed so that the parts of it that end up in the patch,
+/// like new instances and changed properties, can be moved instead of copied.
+pub fn compute_patch_set(snapshot: InstanceSnapshot, tree: &RojoTree, id: RbxId) -> PatchSet {
+    let _timer = metrics::PATCH_COMPUTE_DURATION.start_timer("");
+    let _span = trace::span("compute_patch_set");
+
     let mut patch_set = PatchSet::new();
     let mut context = ComputePatchContext::default();
 
@@ -67,7 +80,7 @@ fn rewrite_refs_in_snapshot(context: &ComputePatchContext, snapshot: &mut Instan
 
 fn compute_patch_set_internal(
     context: &mut ComputePatchContext,
-    snapshot: &InstanceSnapshot,
+    snapshot: InstanceSnapshot,
     tree: &RojoTree,
     id: RbxId,
     patch_set: &mut PatchSet,
@@ -80,57 +93,47 @@ fn compute_patch_set_internal(
         .get_instance(id)
         .expect("Instance did not exist in tree");
 
-    compute_property_patches(snapshot, &instance, patch_set);
-    compute_children_patches(context, snapshot, tree, id, patch_set);
+    let children = compute_property_patches(snapshot, &instance, patch_set);
+    compute_children_patches(context, children, tree, id, patch_set);
 }
 
+/// Computes the changes to the instance itself, handing back the snapshot's
+/// children so that they can be diffed next.
 fn compute_property_patches(
-    snapshot: &InstanceSnapshot,
+    snapshot: InstanceSnapshot,
     instance: &InstanceWithMeta,
     patch_set: &mut PatchSet,
-) {
-    let mut visited_properties = HashSet::new();
+) -> Vec<InstanceSnapshot> {
     let mut changed_properties = HashMap::new();
 
     let changed_name = if snapshot.name == instance.name() {
         None
     } else {
-        Some(snapshot.name.clone().into_owned())
+        Some(snapshot.name.into_owned())
     };
 
     let changed_class_name = if snapshot.class_name == instance.class_name() {
         None
     } else {
-        Some(snapshot.class_name.clone().into_owned())
+        Some(snapshot.class_name.into_owned())
     };
 
     let changed_metadata = if &snapshot.metadata == instance.metadata() {
         None
     } else {
-        Some(snapshot.metadata.clone())
+        Some(snapshot.metadata)
     };
 
-    for (name, snapshot_value) in &snapshot.properties {
-        visited_properties.insert(name.as_str());
-
-        match instance.properties().get(name) {
-            Some(instance_value) => {
-                if snapshot_value != instance_value {
-                    changed_properties.insert(name.clone(), Some(snapshot_value.clone()));
-                }
-            }
-            None => {
-                changed_properties.insert(name.clone(), Some(snapshot_value.clone()));
-            }
+    for name in instance.properties().keys() {
+        if !snapshot.properties.contains_key(name) {
+            changed_properties.insert(name.clone(), None);
         }
     }
 
-    for name in instance.properties().keys() {
-        if visited_properties.contains(name.as_str()) {
-            continue;
+    for (name, snapshot_value) in snapshot.properties {
+        if instance.properties().get(&name) != Some(&snapshot_value) {
+            changed_properties.insert(name, Some(snapshot_value));
         }
-
-        changed_properties.insert(name.clone(), None);
     }
 
     if changed_properties.is_empty()
@@ -138,7 +141,7 @@ fn compute_property_patches(
         && changed_class_name.is_none()
         && changed_metadata.is_none()
     {
-        return;
+        return snapshot.children;
     }
 
     patch_set.updated_instances.push(PatchUpdate {
@@ -148,11 +151,13 @@ fn compute_property_patches(
         changed_properties,
         changed_metadata,
     });
+
+    snapshot.children
 }
 
 fn compute_children_patches(
     context: &mut ComputePatchContext,
-    snapshot: &InstanceSnapshot,
+    snapshot_children: Vec<InstanceSnapshot>,
     tree: &RojoTree,
     id: RbxId,
     patch_set: &mut PatchSet,
@@ -164,45 +169,37 @@ fn compute_children_patches(
     let instance_children = instance.children();
 
     let mut paired_instances = vec![false; instance_children.len()];
+    let matches = match_children(
+        &snapshot_children,
+        tree,
+        instance_children,
+        &mut paired_instances,
+    );
+
+    // The position of each snapshot child once the patch is applied, used to
+    // figure out whether the children need to be reordered.
+    let mut child_order = Vec::with_capacity(snapshot_children.len());
+
+    for (snapshot_child, matching_index) in snapshot_children.into_iter().zip(matches) {
+        match matching_index {
+            Some(index) => {
+                let instance_child_id = instance_children[index];
+                child_order.push(ChildPosition::Existing(instance_child_id));
 
-    for snapshot_child in snapshot.children.iter() {
-        let matching_instance =
-            instance_children
-                .iter()
-                .enumerate()
-                .find(|(instance_index, instance_child_id)| {
-                    if paired_instances[*instance_index] {
-                        return false;
-                    }
-
-                    let instance_child = tree
-                        .get_instance(**instance_child_id)
-                        .expect("Instance did not exist in tree");
-
-                    if snapshot_child.name == instance_child.name()
-                        && snapshot_child.class_name == instance_child.class_name()
-                    {
-                        paired_instances[*instance_index] = true;
-                        return true;
-                    }
-
-                    false
-                });
-
-        match matching_instance {
-            Some((_, instance_child_id)) => {
                 compute_patch_set_internal(
                     context,
                     snapshot_child,
                     tree,
-                    *instance_child_id,
+                    instance_child_id,
                     patch_set,
                 );
             }
             None => {
+                child_order.push(ChildPosition::Added(patch_set.added_instances.len()));
+
                 patch_set.added_instances.push(PatchAdd {
                     parent_id: id,
-                    instance: snapshot_child.clone(),
+                    instance: snapshot_child,
                 });
             }
         }
@@ -215,6 +212,181 @@ fn compute_children_patches(
 
         patch_set.removed_instances.push(*instance_child_id);
     }
+
+    compute_reorder_patch(
+        id,
+        instance_children,
+        &paired_instances,
+        child_order,
+        patch_set,
+    );
+}
+
+/// Identifies the thing that an instance was created from, which stays the
+/// same even when the instance's name changes.
+#[derive(PartialEq, Eq, Hash)]
+enum SourceKey<'a> {
+    Path(&'a Path),
+    ProjectNode(&'a Path, &'a str),
+}
+
+impl<'a> SourceKey<'a> {
+    fn from_metadata(metadata: &'a InstanceMetadata) -> Option<Self> {
+        match &metadata.instigating_source {
+            Some(InstigatingSource::Path(path)) => Some(SourceKey::Path(path)),
+            Some(InstigatingSource::ProjectNode(folder, name, _)) => {
+                Some(SourceKey::ProjectNode(folder, name))
+            }
+            None => None,
+        }
+    }
+}
+
+/// Pairs up the children of a snapshot with the existing children of the
+/// instance it describes, returning the index into `instance_children` for
+/// each snapshot child that has a match.
+///
+/// Children are first matched by where they came from, so that an instance
+/// keeps its ID when it's renamed and duplicate-named siblings don't trade
+/// IDs. Anything left over is matched by name and class name, in order.
+fn match_children(
+    snapshot_children: &[InstanceSnapshot],
+    tree: &RojoTree,
+    instance_children: &[RbxId],
+    paired_instances: &mut [bool],
+) -> Vec<Option<usize>> {
+    let mut by_source: HashMap<SourceKey, Vec<usize>> = HashMap::new();
+    let mut by_name: HashMap<(&str, &str), VecDeque<usize>> = HashMap::new();
+    let mut class_names = Vec::with_capacity(instance_children.len());
+
+    for (index, child_id) in instance_children.iter().enumerate() {
+        let child = tree
+            .get_instance(*child_id)
+            .expect("Instance did not exist in tree");
+
+        if let Some(key) = SourceKey::from_metadata(child.metadata()) {
+            by_source.entry(key).or_default().push(index);
+        }
+
+        by_name
+            .entry((child.name(), child.class_name()))
+            .or_default()
+            .push_back(index);
+
+        class_names.push(child.class_name());
+    }
+
+    let mut matches = vec![None; snapshot_children.len()];
+
+    for (snapshot_index, snapshot_child) in snapshot_children.iter().enumerate() {
+        let candidates =
+            SourceKey::from_metadata(&snapshot_child.metadata).and_then(|key| by_source.get(&key));
+
+        if let Some(candidates) = candidates {
+            let found = candidates.iter().copied().find(|&index| {
+                !paired_instances[index] && class_names[index] == snapshot_child.class_name
+            });
+
+            if let Some(index) = found {
+                paired_instances[index] = true;
+                matches[snapshot_index] = Some(index);
+            }
+        }
+    }
+
+    for (snapshot_index, snapshot_child) in snapshot_children.iter().enumerate() {
+        if matches[snapshot_index].is_some() {
+            continue;
+        }
+
+        let key = (
+            snapshot_child.name.as_ref(),
+            snapshot_child.class_name.as_ref(),
+        );
+
+        if let Some(candidates) = by_name.get_mut(&key) {
+            while let Some(index) = candidates.pop_front() {
+                if !paired_instances[index] {
+                    paired_instances[index] = true;
+                    matches[snapshot_index] = Some(index);
+                    break;
+                }
+            }
+        }
+    }
+
+    matches
+}
+
+/// Where a child from a snapshot ends up when its patch is applied.
+enum ChildPosition {
+    /// The child was paired with an existing instance with this ID.
+    Existing(RbxId),
+
+    /// The child will be added by the `PatchAdd` at this index in the patch
+    /// set's `added_instances`.
+    Added(usize),
+}
+
+impl ChildPosition {
+    fn is_added(&self) -> bool {
+        match self {
+            ChildPosition::Existing(_) => false,
+            ChildPosition::Added(_) => true,
+        }
+    }
+}
+
+/// Emits a `PatchReorder` if applying the patch would leave the children of
+/// the given instance in a different order than the snapshot describes.
+///
+/// Applying a patch keeps existing children in place and appends new children
+/// to the end, so no reorder is needed as long as the paired children are
+/// already in order and every added child comes after them.
+fn compute_reorder_patch(
+    id: RbxId,
+    instance_children: &[RbxId],
+    paired_instances: &[bool],
+    child_order: Vec<ChildPosition>,
+    patch_set: &mut PatchSet,
+) {
+    let kept_children = instance_children
+        .iter()
+        .zip(paired_instances)
+        .filter(|(_, paired)| **paired)
+        .map(|(child_id, _)| child_id);
+
+    let desired_existing = child_order.iter().filter_map(|position| match position {
+        ChildPosition::Existing(child_id) => Some(child_id),
+        ChildPosition::Added(_) => None,
+    });
+
+    let additions_trail = child_order
+        .iter()
+        .skip_while(|position| !position.is_added())
+        .all(ChildPosition::is_added);
+
+    if additions_trail && kept_children.eq(desired_existing) {
+        return;
+    }
+
+    // Added instances don't have an instance ID yet, so we refer to them by
+    // snapshot ID instead, assigning one if the snapshot didn't have one.
+    let children = child_order
+        .into_iter()
+        .map(|position| match position {
+            ChildPosition::Existing(child_id) => child_id,
+            ChildPosition::Added(index) => *patch_set.added_instances[index]
+                .instance
+                .snapshot_id
+                .get_or_insert_with(RbxId::new),
+        })
+        .collect();
+
+    patch_set.reordered_children.push(PatchReorder {
+        parent_id: id,
+        children,
+    });
 }
 
 #[cfg(test)]
@@ -262,7 +434,7 @@ mod test {
             children: Vec::new(),
         };
 
-        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        let patch_set = compute_patch_set(snapshot, &tree, root_id);
 
         let expected_patch_set = PatchSet {
             updated_instances: vec![PatchUpdate {
@@ -278,6 +450,7 @@ mod test {
             }],
             added_instances: Vec::new(),
             removed_instances: Vec::new(),
+            reordered_children: Vec::new(),
         };
 
         assert_eq!(patch_set, expected_patch_set);
@@ -323,7 +496,7 @@ mod test {
             class_name: Cow::Borrowed("foo"),
         };
 
-        let patch_set = compute_patch_set(&snapshot, &tree, root_id);
+        let patch_set = compute_patch_set(snapshot, &tree, root_id);
 
         let expected_patch_set = PatchSet {
             added_instances: vec![PatchAdd {
@@ -343,6 +516,7 @@ mod test {
             }],
             updated_instances: Vec::new(),
             removed_instances: Vec::new(),
+            reordered_children: Vec::new(),
         };
 
         assert_eq!(patch_set, expected_patch_set);
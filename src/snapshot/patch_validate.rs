@@ -0,0 +1,132 @@
+//! Defines the checks that a patch has to pass before it's applied, which make
+//! sure that it can be applied all at once without leaving the tree
+//! half-changed.
+
+use std::collections::{HashMap, HashSet};
+
+use rbx_dom_weak::RbxId;
+use snafu::Snafu;
+
+use super::{patch::PatchSet, RojoTree};
+
+/// A reason that a `PatchSet` can't be applied to a tree.
+#[derive(Debug, Clone, PartialEq, Snafu)]
+pub enum PatchError {
+    #[snafu(display("Cannot remove instance {}, it does not exist", id))]
+    RemoveMissingInstance { id: RbxId },
+
+    #[snafu(display("Cannot remove the root instance"))]
+    RemoveRootInstance,
+
+    #[snafu(display(
+        "Cannot add an instance to {}, it does not exist or is removed by this patch",
+        parent_id
+    ))]
+    AddToMissingParent { parent_id: RbxId },
+
+    #[snafu(display(
+        "Cannot update instance {}, it does not exist or is removed by this patch",
+        id
+    ))]
+    UpdateMissingInstance { id: RbxId },
+
+    #[snafu(display(
+        "Cannot reorder the children of instance {}, it does not exist or is removed by this patch",
+        parent_id
+    ))]
+    ReorderMissingParent { parent_id: RbxId },
+
+    #[snafu(display(
+        "Cannot reorder instance {}, it is not a child of instance {}",
+        child_id,
+        parent_id
+    ))]
+    ReorderNotAChild { parent_id: RbxId, child_id: RbxId },
+}
+
+/// Checks that every change in the given `PatchSet` refers to instances that
+/// will exist when it's applied to the given tree.
+pub fn validate_patch_set(tree: &RojoTree, patch_set: &PatchSet) -> Result<(), PatchError> {
+    let root_id = tree.get_root_id();
+    let mut removed = HashSet::new();
+
+    for &id in &patch_set.removed_instances {
+        if id == root_id {
+            return Err(PatchError::RemoveRootInstance);
+        }
+
+        if tree.get_instance(id).is_none() {
+            return Err(PatchError::RemoveMissingInstance { id });
+        }
+
+        removed.insert(id);
+    }
+
+    // Instances added by the patch are referred to by their snapshot ID until
+    // they're given a real ID, which only reorders need to do.
+    let mut added_parents = HashMap::new();
+
+    for add_patch in &patch_set.added_instances {
+        if !exists_after_removal(tree, &removed, add_patch.parent_id) {
+            return Err(PatchError::AddToMissingParent {
+                parent_id: add_patch.parent_id,
+            });
+        }
+
+        if let Some(snapshot_id) = add_patch.instance.snapshot_id {
+            added_parents.insert(snapshot_id, add_patch.parent_id);
+        }
+    }
+
+    for update_patch in &patch_set.updated_instances {
+        if !exists_after_removal(tree, &removed, update_patch.id) {
+            return Err(PatchError::UpdateMissingInstance {
+                id: update_patch.id,
+            });
+        }
+    }
+
+    for reorder_patch in &patch_set.reordered_children {
+        let parent_id = reorder_patch.parent_id;
+
+        if !exists_after_removal(tree, &removed, parent_id) {
+            return Err(PatchError::ReorderMissingParent { parent_id });
+        }
+
+        for &child_id in &reorder_patch.children {
+            let is_existing_child = !removed.contains(&child_id)
+                && tree.get_instance(child_id).and_then(|child| child.parent()) == Some(parent_id);
+
+            let is_added_child = added_parents.get(&child_id) == Some(&parent_id);
+
+            if !is_existing_child && !is_added_child {
+                return Err(PatchError::ReorderNotAChild {
+                    parent_id,
+                    child_id,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Tells whether the given instance is in the tree and won't be removed along
+/// with itself or one of its ancestors.
+fn exists_after_removal(tree: &RojoTree, removed: &HashSet<RbxId>, id: RbxId) -> bool {
+    let mut current_id = id;
+
+    loop {
+        if removed.contains(&current_id) {
+            return false;
+        }
+
+        match tree.get_instance(current_id) {
+            Some(instance) => match instance.parent() {
+                Some(parent_id) => current_id = parent_id,
+                None => return true,
+            },
+            None => return false,
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Defines the storage that applied patches share property values through.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Weak},
+};
+
+use rbx_dom_weak::RbxValue;
+
+/// Strings shorter than this aren't worth looking up.
+const MIN_SHARED_LEN: usize = 256;
+
+/// How many entries the map can hold before entries for values that have
+/// been dropped are first cleared out.
+const MIN_CLEANUP_LEN: usize = 64;
+
+/// Hands out reference-counted property values, reusing one that's still
+/// alive when a large string with the same contents is shared again.
+///
+/// Applied patches keep both the values they changed properties to and the
+/// values they replaced, and are kept in the message queue for the whole
+/// session. When a script is edited twice, the value the first patch changed
+/// it to is the one the second patch replaced, so both share one string.
+///
+/// Only entries for values that are still alive are useful, so the map only
+/// holds weak references to them.
+#[derive(Debug, Default)]
+pub struct SharedValues {
+    values: HashMap<u64, Weak<RbxValue>>,
+    next_cleanup_len: usize,
+}
+
+impl SharedValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shares the given value, dropping it in favor of an equal one that's
+    /// already shared if there is one.
+    pub fn share(&mut self, value: RbxValue) -> Arc<RbxValue> {
+        let key = match shared_key(&value) {
+            Some(key) => key,
+            None => return Arc::new(value),
+        };
+
+        match self.find(key, &value) {
+            Some(shared) => shared,
+            None => self.insert(key, Arc::new(value)),
+        }
+    }
+
+    /// Shares a copy of the given value, unless an equal one is already
+    /// shared, which is returned without making a copy.
+    pub fn share_ref(&mut self, value: &RbxValue) -> Arc<RbxValue> {
+        let key = match shared_key(value) {
+            Some(key) => key,
+            None => return Arc::new(value.clone()),
+        };
+
+        match self.find(key, value) {
+            Some(shared) => shared,
+            None => self.insert(key, Arc::new(value.clone())),
+        }
+    }
+
+    fn find(&self, key: u64, value: &RbxValue) -> Option<Arc<RbxValue>> {
+        self.values
+            .get(&key)?
+            .upgrade()
+            .filter(|shared| **shared == *value)
+    }
+
+    fn insert(&mut self, key: u64, shared: Arc<RbxValue>) -> Arc<RbxValue> {
+        if self.values.len() >= self.next_cleanup_len {
+            self.values.retain(|_, value| value.upgrade().is_some());
+            self.next_cleanup_len = (self.values.len() * 2).max(MIN_CLEANUP_LEN);
+        }
+
+        // A different value with the same hash is replaced, which only means
+        // that the old one won't be shared from now on.
+        self.values.insert(key, Arc::downgrade(&shared));
+        shared
+    }
+}
+
+/// Hashes the values that are worth sharing, which are large strings like
+/// the source of a script.
+fn shared_key(value: &RbxValue) -> Option<u64> {
+    match value {
+        RbxValue::String { value } if value.len() >= MIN_SHARED_LEN => {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            Some(hasher.finish())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn large_string(contents: &str) -> RbxValue {
+        RbxValue::String {
+            value: contents.repeat(MIN_SHARED_LEN),
+        }
+    }
+
+    #[test]
+    fn shares_large_strings() {
+        let mut shared_values = SharedValues::new();
+
+        let first = shared_values.share(large_string("a"));
+        let second = shared_values.share_ref(&large_string("a"));
+        let third = shared_values.share(large_string("a"));
+        assert!(Arc::ptr_eq(&first, &second));
+        assert!(Arc::ptr_eq(&first, &third));
+
+        let other = shared_values.share(large_string("b"));
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
+
+    #[test]
+    fn skips_small_values() {
+        let mut shared_values = SharedValues::new();
+        let small = RbxValue::String {
+            value: "a".to_owned(),
+        };
+
+        let first = shared_values.share(small.clone());
+        let second = shared_values.share(small);
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(shared_values.values.is_empty());
+    }
+
+    #[test]
+    fn forgets_dropped_values() {
+        let mut shared_values = SharedValues::new();
+
+        for i in 0..MIN_CLEANUP_LEN * 4 {
+            shared_values.share(large_string(&i.to_string()));
+        }
+
+        // None of the values were kept, so the map was cleared out each time
+        // it reached its limit instead of growing.
+        assert!(shared_values.values.len() <= MIN_CLEANUP_LEN);
+    }
+}
@@ -1,11 +1,14 @@
 use insta::assert_yaml_snapshot;
 use maplit::hashmap;
-use rbx_dom_weak::{RbxInstanceProperties, RbxValue};
+use rbx_dom_weak::{RbxId, RbxInstanceProperties, RbxValue};
 
 use rojo_insta_ext::RedactionMap;
 
 use crate::{
-    snapshot::{apply_patch_set, InstancePropertiesWithMeta, PatchSet, PatchUpdate, RojoTree},
+    snapshot::{
+        apply_patch_set, InstancePropertiesWithMeta, PatchError, PatchReorder, PatchSet,
+        PatchUpdate, RojoTree,
+    },
     tree_view::{intern_tree, view_tree},
 };
 
@@ -27,7 +30,7 @@ fn set_name_and_class_name() {
         ..Default::default()
     };
 
-    let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+    let applied_patch_set = apply_patch_set(&mut tree, patch_set).unwrap();
 
     let tree_view = view_tree(&tree, &mut redactions);
     assert_yaml_snapshot!(tree_view);
@@ -58,7 +61,7 @@ fn add_property() {
         ..Default::default()
     };
 
-    let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+    let applied_patch_set = apply_patch_set(&mut tree, patch_set).unwrap();
 
     let tree_view = view_tree(&tree, &mut redactions);
     assert_yaml_snapshot!(tree_view);
@@ -102,7 +105,7 @@ fn remove_property() {
         ..Default::default()
     };
 
-    let applied_patch_set = apply_patch_set(&mut tree, patch_set);
+    let applied_patch_set = apply_patch_set(&mut tree, patch_set).unwrap();
 
     let tree_view = view_tree(&tree, &mut redactions);
     assert_yaml_snapshot!("remove_property_after_patch", tree_view);
@@ -111,6 +114,144 @@ fn remove_property() {
     assert_yaml_snapshot!("remove_property_appied_patch", applied_patch_value);
 }
 
+#[test]
+fn reorder_children() {
+    let mut redactions = RedactionMap::new();
+
+    let mut tree = empty_tree();
+    let root_id = tree.get_root_id();
+
+    let child_ids: Vec<_> = ["A", "B", "C"]
+        .iter()
+        .map(|name| {
+            tree.insert_instance(
+                InstancePropertiesWithMeta {
+                    properties: RbxInstanceProperties {
+                        name: name.to_string(),
+                        class_name: "Folder".to_owned(),
+                        properties: Default::default(),
+                    },
+                    metadata: Default::default(),
+                },
+                root_id,
+            )
+        })
+        .collect();
+
+    intern_tree(&tree, &mut redactions);
+
+    let patch_set = PatchSet {
+        reordered_children: vec![PatchReorder {
+            parent_id: root_id,
+            children: vec![child_ids[2], child_ids[0], child_ids[1]],
+        }],
+        ..Default::default()
+    };
+
+    let applied_patch_set = apply_patch_set(&mut tree, patch_set).unwrap();
+
+    let tree_view = view_tree(&tree, &mut redactions);
+    assert_yaml_snapshot!(tree_view);
+
+    let applied_patch_value = redactions.redacted_yaml(applied_patch_set);
+    assert_yaml_snapshot!(applied_patch_value);
+}
+
+#[test]
+fn invalid_patch_is_not_applied() {
+    let mut redactions = RedactionMap::new();
+
+    let mut tree = empty_tree();
+    let root_id = tree.get_root_id();
+    let child_id = tree.insert_instance(
+        InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: "Child".to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: Default::default(),
+            },
+            metadata: Default::default(),
+        },
+        root_id,
+    );
+
+    intern_tree(&tree, &mut redactions);
+    let tree_before = view_tree(&tree, &mut redactions);
+
+    // The rename of the root is valid, but the child is updated after it is
+    // removed, so none of the patch should be applied.
+    let patch_set = PatchSet {
+        removed_instances: vec![child_id],
+        updated_instances: vec![
+            PatchUpdate {
+                id: root_id,
+                changed_name: Some("Renamed".to_owned()),
+                changed_class_name: None,
+                changed_properties: Default::default(),
+                changed_metadata: None,
+            },
+            PatchUpdate {
+                id: child_id,
+                changed_name: Some("Renamed".to_owned()),
+                changed_class_name: None,
+                changed_properties: Default::default(),
+                changed_metadata: None,
+            },
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        apply_patch_set(&mut tree, patch_set).unwrap_err(),
+        PatchError::UpdateMissingInstance { id: child_id }
+    );
+
+    assert_eq!(view_tree(&tree, &mut redactions), tree_before);
+}
+
+#[test]
+fn invalid_patch_errors() {
+    let mut tree = empty_tree();
+    let root_id = tree.get_root_id();
+    let missing_id = RbxId::new();
+
+    let patch_set = PatchSet {
+        removed_instances: vec![root_id],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        apply_patch_set(&mut tree, patch_set).unwrap_err(),
+        PatchError::RemoveRootInstance
+    );
+
+    let patch_set = PatchSet {
+        removed_instances: vec![missing_id],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        apply_patch_set(&mut tree, patch_set).unwrap_err(),
+        PatchError::RemoveMissingInstance { id: missing_id }
+    );
+
+    let patch_set = PatchSet {
+        reordered_children: vec![PatchReorder {
+            parent_id: root_id,
+            children: vec![missing_id],
+        }],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        apply_patch_set(&mut tree, patch_set).unwrap_err(),
+        PatchError::ReorderNotAChild {
+            parent_id: root_id,
+            child_id: missing_id,
+        }
+    );
+}
+
 fn empty_tree() -> RojoTree {
     RojoTree::new(InstancePropertiesWithMeta {
         properties: RbxInstanceProperties {
@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, path::Path};
 
 use insta::assert_yaml_snapshot;
 use maplit::hashmap;
@@ -6,7 +6,9 @@ use rbx_dom_weak::{RbxInstanceProperties, RbxValue};
 
 use rojo_insta_ext::RedactionMap;
 
-use crate::snapshot::{compute_patch_set, InstancePropertiesWithMeta, InstanceSnapshot, RojoTree};
+use crate::snapshot::{
+    compute_patch_set, InstanceMetadata, InstancePropertiesWithMeta, InstanceSnapshot, RojoTree,
+};
 
 #[test]
 fn set_name_and_class_name() {
@@ -24,7 +26,7 @@ fn set_name_and_class_name() {
         children: Vec::new(),
     };
 
-    let patch_set = compute_patch_set(&snapshot, &tree, tree.get_root_id());
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
     let patch_value = redactions.redacted_yaml(patch_set);
 
     assert_yaml_snapshot!(patch_value);
@@ -50,7 +52,7 @@ fn set_property() {
         children: Vec::new(),
     };
 
-    let patch_set = compute_patch_set(&snapshot, &tree, tree.get_root_id());
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
     let patch_value = redactions.redacted_yaml(patch_set);
 
     assert_yaml_snapshot!(patch_value);
@@ -83,7 +85,7 @@ fn remove_property() {
         children: Vec::new(),
     };
 
-    let patch_set = compute_patch_set(&snapshot, &tree, tree.get_root_id());
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
     let patch_value = redactions.redacted_yaml(patch_set);
 
     assert_yaml_snapshot!(patch_value);
@@ -112,7 +114,7 @@ fn add_child() {
         }],
     };
 
-    let patch_set = compute_patch_set(&snapshot, &tree, tree.get_root_id());
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
     let patch_value = redactions.redacted_yaml(patch_set);
 
     assert_yaml_snapshot!(patch_value);
@@ -151,7 +153,159 @@ fn remove_child() {
         children: Vec::new(),
     };
 
-    let patch_set = compute_patch_set(&snapshot, &tree, tree.get_root_id());
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
+    let patch_value = redactions.redacted_yaml(patch_set);
+
+    assert_yaml_snapshot!(patch_value);
+}
+
+#[test]
+fn reorder_children() {
+    let mut redactions = RedactionMap::new();
+
+    let mut tree = empty_tree();
+    redactions.intern(tree.get_root_id());
+
+    for name in &["A", "B"] {
+        let root_id = tree.get_root_id();
+        let new_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: name.to_string(),
+                    class_name: "Folder".to_owned(),
+                    properties: Default::default(),
+                },
+                metadata: Default::default(),
+            },
+            root_id,
+        );
+
+        redactions.intern(new_id);
+    }
+
+    let child = |name| InstanceSnapshot {
+        snapshot_id: None,
+        metadata: Default::default(),
+        name: Cow::Borrowed(name),
+        class_name: Cow::Borrowed("Folder"),
+        properties: Default::default(),
+        children: Vec::new(),
+    };
+
+    let snapshot = InstanceSnapshot {
+        snapshot_id: None,
+        metadata: Default::default(),
+        name: Cow::Borrowed("ROOT"),
+        class_name: Cow::Borrowed("ROOT"),
+        properties: Default::default(),
+        children: vec![child("B"), child("A")],
+    };
+
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
+    let patch_value = redactions.redacted_yaml(patch_set);
+
+    assert_yaml_snapshot!(patch_value);
+}
+
+#[test]
+fn rename_child_with_same_source() {
+    let mut redactions = RedactionMap::new();
+
+    let mut tree = empty_tree();
+    redactions.intern(tree.get_root_id());
+
+    {
+        let root_id = tree.get_root_id();
+        let child_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "OldName".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: Default::default(),
+                },
+                metadata: InstanceMetadata::new().instigating_source(Path::new("/child")),
+            },
+            root_id,
+        );
+
+        redactions.intern(child_id);
+    }
+
+    let snapshot = InstanceSnapshot {
+        snapshot_id: None,
+        metadata: Default::default(),
+        name: Cow::Borrowed("ROOT"),
+        class_name: Cow::Borrowed("ROOT"),
+        properties: Default::default(),
+        children: vec![InstanceSnapshot {
+            snapshot_id: None,
+            metadata: InstanceMetadata::new().instigating_source(Path::new("/child")),
+            name: Cow::Borrowed("NewName"),
+            class_name: Cow::Borrowed("Folder"),
+            properties: Default::default(),
+            children: Vec::new(),
+        }],
+    };
+
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
+    let patch_value = redactions.redacted_yaml(patch_set);
+
+    assert_yaml_snapshot!(patch_value);
+}
+
+#[test]
+fn duplicate_names_match_by_source() {
+    let mut redactions = RedactionMap::new();
+
+    let mut tree = empty_tree();
+    redactions.intern(tree.get_root_id());
+
+    for path in &["/first", "/second"] {
+        let root_id = tree.get_root_id();
+        let child_id = tree.insert_instance(
+            InstancePropertiesWithMeta {
+                properties: RbxInstanceProperties {
+                    name: "Duplicate".to_owned(),
+                    class_name: "Folder".to_owned(),
+                    properties: Default::default(),
+                },
+                metadata: InstanceMetadata::new().instigating_source(Path::new(path)),
+            },
+            root_id,
+        );
+
+        redactions.intern(child_id);
+    }
+
+    let child = |path: &str, properties| InstanceSnapshot {
+        snapshot_id: None,
+        metadata: InstanceMetadata::new().instigating_source(Path::new(path)),
+        name: Cow::Borrowed("Duplicate"),
+        class_name: Cow::Borrowed("Folder"),
+        properties,
+        children: Vec::new(),
+    };
+
+    // Only the instance from /second changes, so the patch should only touch
+    // that instance, even though its position among its siblings changed.
+    let snapshot = InstanceSnapshot {
+        snapshot_id: None,
+        metadata: Default::default(),
+        name: Cow::Borrowed("ROOT"),
+        class_name: Cow::Borrowed("ROOT"),
+        properties: Default::default(),
+        children: vec![
+            child(
+                "/second",
+                hashmap! {
+                    "Changed".to_owned() => RbxValue::Bool { value: true },
+                },
+            ),
+            child("/first", Default::default()),
+        ],
+    };
+
+    let patch_set = compute_patch_set(snapshot, &tree, tree.get_root_id());
     let patch_value = redactions.redacted_yaml(patch_set);
 
     assert_yaml_snapshot!(patch_value);
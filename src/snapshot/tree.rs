@@ -1,13 +1,14 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use rbx_dom_weak::{Descendants, RbxId, RbxInstance, RbxInstanceProperties, RbxTree, RbxValue};
 
-use crate::multimap::MultiMap;
+use crate::path_trie::PathTrie;
 
-use super::InstanceMetadata;
+use super::{InstanceMetadata, SharedValues};
 
 /// An expanded variant of rbx_dom_weak's `RbxTree` that tracks additional
 /// metadata per instance that's Rojo-specific.
@@ -22,14 +23,20 @@ pub struct RojoTree {
     /// set of actual instances.
     metadata_map: HashMap<RbxId, InstanceMetadata>,
 
-    /// A multimap from source paths to all of the root instances that were
-    /// constructed from that path.
+    /// A map from source paths to all of the root instances that were
+    /// constructed from that path, organized as a trie so that instances can
+    /// be looked up by directory.
     ///
     /// Descendants of those instances should not be contained in the set, the
     /// value portion of the map is also a set in order to support the same path
     /// appearing multiple times in the same Rojo project. This is sometimes
     /// called "path aliasing" in various Rojo documentation.
-    path_to_ids: MultiMap<PathBuf, RbxId>,
+    path_to_ids: PathTrie<RbxId>,
+
+    /// Shares large property values handed out of the tree in applied
+    /// patches. The tree itself keeps its own copies, since rbx_dom_weak
+    /// stores plain `RbxValue`s.
+    shared_values: SharedValues,
 }
 
 impl RojoTree {
@@ -37,7 +44,8 @@ impl RojoTree {
         let mut tree = RojoTree {
             inner: RbxTree::new(root.properties),
             metadata_map: HashMap::new(),
-            path_to_ids: MultiMap::new(),
+            path_to_ids: PathTrie::new(),
+            shared_values: SharedValues::new(),
         };
 
         tree.insert_metadata(tree.inner.get_root_id(), root.metadata);
@@ -85,7 +93,7 @@ impl RojoTree {
     pub fn remove_instance(&mut self, id: RbxId) -> Option<RojoTree> {
         if let Some(inner) = self.inner.remove_instance(id) {
             let mut metadata_map = HashMap::new();
-            let mut path_to_ids = MultiMap::new();
+            let mut path_to_ids = PathTrie::new();
 
             self.move_metadata(id, &mut metadata_map, &mut path_to_ids);
             for instance in inner.descendants(id) {
@@ -96,12 +104,39 @@ impl RojoTree {
                 inner,
                 metadata_map,
                 path_to_ids,
+                shared_values: SharedValues::new(),
             })
         } else {
             None
         }
     }
 
+    /// Puts the given children of an instance into the given order. Children
+    /// that aren't listed are moved ahead of the ones that are.
+    ///
+    /// Returns `false` if any of the IDs was not a child of the given parent,
+    /// in which case the tree is left untouched.
+    pub fn reorder_children(&mut self, parent_id: RbxId, order: &[RbxId]) -> bool {
+        let all_children = order.iter().all(|&child_id| {
+            self.inner
+                .get_instance(child_id)
+                .and_then(|child| child.get_parent_id())
+                == Some(parent_id)
+        });
+
+        if !all_children {
+            return false;
+        }
+
+        // Setting the parent of an instance moves it to the end of its parent's
+        // list of children, so doing that in order sorts the children.
+        for &child_id in order {
+            self.inner.set_parent(child_id, parent_id);
+        }
+
+        true
+    }
+
     /// Replaces the metadata associated with the given instance ID.
     pub fn update_metadata(&mut self, id: RbxId, metadata: InstanceMetadata) {
         use std::collections::hash_map::Entry;
@@ -115,11 +150,11 @@ impl RojoTree {
                 // to this instance correctly.
                 if existing_metadata.relevant_paths != metadata.relevant_paths {
                     for existing_path in &existing_metadata.relevant_paths {
-                        self.path_to_ids.remove(existing_path, id);
+                        self.path_to_ids.remove(existing_path, &id);
                     }
 
                     for new_path in &metadata.relevant_paths {
-                        self.path_to_ids.insert(new_path.clone(), id);
+                        self.path_to_ids.insert(new_path, id);
                     }
                 }
 
@@ -138,17 +173,66 @@ impl RojoTree {
         }
     }
 
+    /// Returns the relevant paths of the given instance and all of its
+    /// descendants.
+    pub fn get_relevant_paths_in_subtree(&self, id: RbxId) -> Vec<PathBuf> {
+        let mut paths = match self.get_metadata(id) {
+            Some(metadata) => metadata.relevant_paths.clone(),
+            None => Vec::new(),
+        };
+
+        for descendant in self.descendants(id) {
+            paths.extend_from_slice(&descendant.metadata().relevant_paths);
+        }
+
+        paths
+    }
+
     pub fn get_ids_at_path(&self, path: &Path) -> &[RbxId] {
         self.path_to_ids.get(path)
     }
 
+    /// Returns the IDs of all instances that have a relevant path at or below
+    /// the given path, like everything from a directory.
+    pub fn get_ids_under_path(&self, path: &Path) -> Vec<RbxId> {
+        // Instances usually have more than one relevant path in a directory,
+        // so we need to make sure that each one is only returned once.
+        let mut seen = HashSet::new();
+
+        self.path_to_ids
+            .get_all_under(path)
+            .into_iter()
+            .copied()
+            .filter(|id| seen.insert(*id))
+            .collect()
+    }
+
+    /// Returns the IDs of the instances associated with the given path or, if
+    /// there aren't any, its nearest ancestor that has some.
+    pub fn get_ids_at_nearest_ancestor(&self, path: &Path) -> &[RbxId] {
+        self.path_to_ids.get_nearest_ancestor(path)
+    }
+
+    /// Returns a shared copy of a property of the given instance, which
+    /// doesn't need to be copied again if an equal value is already shared.
+    pub fn get_shared_property(&mut self, id: RbxId, key: &str) -> Option<Arc<RbxValue>> {
+        let value = self.inner.get_instance(id)?.properties.get(key)?;
+        Some(self.shared_values.share_ref(value))
+    }
+
+    /// Shares a value that's about to be put into the tree, so that the
+    /// copies handed out of the tree later can use the same allocation.
+    pub fn share_value(&mut self, value: RbxValue) -> Arc<RbxValue> {
+        self.shared_values.share(value)
+    }
+
     pub fn get_metadata(&self, id: RbxId) -> Option<&InstanceMetadata> {
         self.metadata_map.get(&id)
     }
 
     fn insert_metadata(&mut self, id: RbxId, metadata: InstanceMetadata) {
         for path in &metadata.relevant_paths {
-            self.path_to_ids.insert(path.clone(), id);
+            self.path_to_ids.insert(path, id);
         }
 
         self.metadata_map.insert(id, metadata);
@@ -160,13 +244,13 @@ impl RojoTree {
         &mut self,
         id: RbxId,
         metadata_map: &mut HashMap<RbxId, InstanceMetadata>,
-        path_to_ids: &mut MultiMap<PathBuf, RbxId>,
+        path_to_ids: &mut PathTrie<RbxId>,
     ) {
         let metadata = self.metadata_map.remove(&id).unwrap();
 
         for path in &metadata.relevant_paths {
-            self.path_to_ids.remove(path, id);
-            path_to_ids.insert(path.clone(), id);
+            self.path_to_ids.remove(path, &id);
+            path_to_ids.insert(path, id);
         }
 
         metadata_map.insert(id, metadata);
@@ -0,0 +1,215 @@
+//! Detects siblings that Rojo can't tell apart.
+//!
+//! Instances are paired up with their previous versions by name and class
+//! name, so two siblings that share both are ambiguous. Files whose names only
+//! differ by case are also flagged, since they can't be checked out on
+//! case-insensitive filesystems, like the defaults on Windows and macOS.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diagnostics::report_warning,
+    snapshot::{InstanceSnapshot, InstigatingSource},
+};
+
+/// A group of sibling instances that share the same name and class name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiblingCollision {
+    pub name: String,
+    pub class_name: String,
+
+    /// A description of where each of the colliding instances came from.
+    pub sources: Vec<String>,
+}
+
+/// Finds all groups of children that have the same name and class name.
+pub fn find_sibling_collisions(children: &[InstanceSnapshot]) -> Vec<SiblingCollision> {
+    let mut group_indices = HashMap::new();
+    let mut groups: Vec<Vec<&InstanceSnapshot>> = Vec::new();
+
+    for child in children {
+        let key = (child.name.as_ref(), child.class_name.as_ref());
+
+        let index = *group_indices.entry(key).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+
+        groups[index].push(child);
+    }
+
+    groups
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|group| SiblingCollision {
+            name: group[0].name.to_string(),
+            class_name: group[0].class_name.to_string(),
+            sources: group.iter().map(|child| describe_source(child)).collect(),
+        })
+        .collect()
+}
+
+/// Finds all groups of paths whose file names are the same when compared
+/// case-insensitively.
+pub fn find_case_collisions<'a, I>(paths: I) -> Vec<Vec<PathBuf>>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let mut group_indices = HashMap::new();
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+
+    for path in paths {
+        let file_name = match path.file_name() {
+            Some(file_name) => file_name.to_string_lossy().to_lowercase(),
+            None => continue,
+        };
+
+        let index = *group_indices.entry(file_name).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+
+        groups[index].push(path.to_path_buf());
+    }
+
+    groups.into_iter().filter(|group| group.len() > 1).collect()
+}
+
+/// Logs a warning for every collision among the children of an instance, and
+/// reports it as a diagnostic for the given path.
+pub fn warn_sibling_collisions(
+    path: &Path,
+    parent: impl fmt::Display,
+    children: &[InstanceSnapshot],
+) {
+    for collision in find_sibling_collisions(children) {
+        log::warn!(
+            "{} has multiple children of class {} named \"{}\":",
+            parent,
+            collision.class_name,
+            collision.name
+        );
+
+        for source in &collision.sources {
+            log::warn!("  - {}", source);
+        }
+
+        log::warn!("Rojo can't tell these apart. Rename one, or set its name with a meta file.");
+
+        report_warning(
+            path,
+            format!(
+                "{} has multiple children of class {} named \"{}\", which Rojo can't tell apart: {}",
+                parent,
+                collision.class_name,
+                collision.name,
+                collision.sources.join(", ")
+            ),
+        );
+    }
+}
+
+/// Logs a warning for every group of the given paths that would collide on a
+/// case-insensitive filesystem, and reports it as a diagnostic for the folder
+/// they're in.
+pub fn warn_case_collisions<'a, I>(folder: &Path, paths: I)
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    for group in find_case_collisions(paths) {
+        log::warn!("These files have names that only differ by case:");
+
+        for path in &group {
+            log::warn!("  - {}", path.display());
+        }
+
+        log::warn!("They can't coexist on case-insensitive filesystems, like the defaults on Windows and macOS.");
+
+        let names: Vec<_> = group
+            .iter()
+            .filter_map(|path| path.file_name())
+            .map(|name| name.to_string_lossy())
+            .collect();
+
+        report_warning(
+            folder,
+            format!(
+                "These files have names that only differ by case, so they can't coexist on \
+                 case-insensitive filesystems: {}",
+                names.join(", ")
+            ),
+        );
+    }
+}
+
+fn describe_source(snapshot: &InstanceSnapshot) -> String {
+    match &snapshot.metadata.instigating_source {
+        Some(InstigatingSource::Path(path)) => path.display().to_string(),
+        Some(InstigatingSource::ProjectNode(project_folder, name, node)) => match &node.path {
+            Some(path) => project_folder.join(path).display().to_string(),
+            None => format!("project node \"{}\" in {}", name, project_folder.display()),
+        },
+        None => format!("instance \"{}\" from a model file", snapshot.name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::snapshot::InstanceMetadata;
+
+    fn child(name: &'static str, class_name: &'static str, path: &str) -> InstanceSnapshot {
+        InstanceSnapshot::new()
+            .name(name)
+            .class_name(class_name)
+            .metadata(InstanceMetadata::new().instigating_source(Path::new(path)))
+    }
+
+    #[test]
+    fn same_name_and_class() {
+        let children = vec![
+            child("foo", "ModuleScript", "/src/foo.lua"),
+            child("foo", "ModuleScript", "/src/foo"),
+            child("foo", "Folder", "/src/foo.txt"),
+            child("bar", "ModuleScript", "/src/bar.lua"),
+        ];
+
+        assert_eq!(
+            find_sibling_collisions(&children),
+            vec![SiblingCollision {
+                name: "foo".to_owned(),
+                class_name: "ModuleScript".to_owned(),
+                sources: vec!["/src/foo.lua".to_owned(), "/src/foo".to_owned()],
+            }]
+        );
+    }
+
+    #[test]
+    fn names_only_differing_by_case() {
+        let children = vec![
+            child("Foo", "ModuleScript", "/src/Foo.lua"),
+            child("foo", "ModuleScript", "/src/foo.lua"),
+        ];
+
+        assert!(find_sibling_collisions(&children).is_empty());
+
+        let paths = vec![
+            Path::new("/src/Foo.lua"),
+            Path::new("/src/foo.lua"),
+            Path::new("/src/foo.server.lua"),
+        ];
+
+        assert_eq!(
+            find_case_collisions(paths),
+            vec![vec![
+                PathBuf::from("/src/Foo.lua"),
+                PathBuf::from("/src/foo.lua"),
+            ]]
+        );
+    }
+}
@@ -10,6 +10,7 @@ use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
 use super::{
     meta_file::AdjacentMetadata,
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     util::match_file_name,
 };
 
@@ -33,7 +34,7 @@ impl SnapshotMiddleware for SnapshotCsv {
         let table_contents = convert_localization_csv(&vfs.read(path)?);
 
         let mut snapshot = InstanceSnapshot::new()
-            .name(instance_name)
+            .name(unescape_file_name(instance_name).into_owned())
             .class_name("LocalizationTable")
             .properties(hashmap! {
                 "Contents".to_owned() => RbxValue::String {
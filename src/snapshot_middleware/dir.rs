@@ -5,9 +5,11 @@ use memofs::{DirEntry, IoResultExt, Vfs};
 use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
 
 use super::{
+    collision::{warn_case_collisions, warn_sibling_collisions},
     error::SnapshotError,
     meta_file::DirectoryMetadata,
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     snapshot_from_vfs,
 };
 
@@ -28,20 +30,32 @@ impl SnapshotMiddleware for SnapshotDir {
                 .all(|rule| rule.passes(child.path()))
         };
 
-        let mut snapshot_children = Vec::new();
+        let mut entries = Vec::new();
 
         for entry in vfs.read_dir(path)? {
             let entry = entry?;
 
-            if !passes_filter_rules(&entry) {
-                continue;
+            if passes_filter_rules(&entry) {
+                entries.push(entry);
             }
+        }
+
+        // The order that entries are read in depends on the platform and VFS
+        // backend, so we sort them to keep the order of children stable.
+        entries.sort_by(|a, b| a.path().cmp(b.path()));
+
+        warn_case_collisions(path, entries.iter().map(DirEntry::path));
+
+        let mut snapshot_children = Vec::new();
 
+        for entry in entries {
             if let Some(child_snapshot) = snapshot_from_vfs(context, vfs, entry.path())? {
                 snapshot_children.push(child_snapshot);
             }
         }
 
+        warn_sibling_collisions(path, path.display(), &snapshot_children);
+
         let instance_name = path
             .file_name()
             .expect("Could not extract file name")
@@ -63,7 +77,7 @@ impl SnapshotMiddleware for SnapshotDir {
         ];
 
         let mut snapshot = InstanceSnapshot::new()
-            .name(instance_name)
+            .name(unescape_file_name(&instance_name).into_owned())
             .class_name("Folder")
             .children(snapshot_children)
             .metadata(
@@ -125,4 +139,34 @@ mod test {
 
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
+
+    #[test]
+    fn folder_with_order() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot(
+            "/foo",
+            VfsSnapshot::dir(hashmap! {
+                "A" => VfsSnapshot::empty_dir(),
+                "B" => VfsSnapshot::empty_dir(),
+                "C" => VfsSnapshot::empty_dir(),
+                "init.meta.json" => VfsSnapshot::file(r#"{ "order": ["C", "A"] }"#),
+            }),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot =
+            SnapshotDir::from_vfs(&InstanceContext::default(), &vfs, Path::new("/foo"))
+                .unwrap()
+                .unwrap();
+
+        let child_names: Vec<_> = instance_snapshot
+            .children
+            .iter()
+            .map(|child| child.name.as_ref())
+            .collect();
+
+        assert_eq!(child_names, vec!["C", "A", "B"]);
+    }
 }
@@ -1,4 +1,8 @@
-use std::{error::Error, fmt, io, path::PathBuf};
+use std::{
+    error::Error,
+    fmt, io,
+    path::{Path, PathBuf},
+};
 
 use snafu::Snafu;
 
@@ -16,6 +20,11 @@ impl SnapshotError {
         }
     }
 
+    /// The path of the file that caused the error, if it's known.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     pub(crate) fn wrap(source: impl Into<SnapshotErrorDetail>, path: impl Into<PathBuf>) -> Self {
         Self {
             detail: source.into(),
@@ -5,11 +5,15 @@ use rbx_dom_weak::UnresolvedRbxValue;
 use rbx_reflection::try_resolve_value;
 use serde::Deserialize;
 
-use crate::snapshot::{InstanceContext, InstanceSnapshot};
+use crate::{
+    diagnostics::report_warning,
+    snapshot::{InstanceContext, InstanceSnapshot},
+};
 
 use super::{
     error::SnapshotError,
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     util::match_file_name,
 };
 
@@ -24,7 +28,7 @@ impl SnapshotMiddleware for SnapshotJsonModel {
         }
 
         let instance_name = match match_file_name(path, ".model.json") {
-            Some(name) => name,
+            Some(name) => unescape_file_name(name),
             None => return Ok(None),
         };
 
@@ -33,7 +37,7 @@ impl SnapshotMiddleware for SnapshotJsonModel {
             .map_err(|source| SnapshotError::malformed_model_json(source, path))?;
 
         if let Some(json_name) = &instance.name {
-            if json_name != instance_name {
+            if *json_name != instance_name {
                 log::warn!(
                     "Name from JSON model did not match its file name: {}",
                     path.display()
@@ -47,10 +51,19 @@ impl SnapshotMiddleware for SnapshotJsonModel {
                     instance_name
                 );
                 log::warn!("'Name' for the top-level instance in a JSON model is now optional and will be ignored.");
+
+                report_warning(
+                    path,
+                    format!(
+                        "This model's 'Name' is \"{}\", but it's named \"{}\" after its file. \
+                         'Name' for the top-level instance in a JSON model is ignored.",
+                        json_name, instance_name
+                    ),
+                );
             }
         }
 
-        let mut snapshot = instance.core.into_snapshot(instance_name.to_owned());
+        let mut snapshot = instance.core.into_snapshot(instance_name.into_owned());
 
         snapshot.metadata = snapshot
             .metadata
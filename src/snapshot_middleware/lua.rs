@@ -10,6 +10,7 @@ use super::{
     dir::SnapshotDir,
     meta_file::AdjacentMetadata,
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     util::match_trailing,
 };
 
@@ -73,7 +74,7 @@ fn snapshot_lua_file(context: &InstanceContext, vfs: &Vfs, path: &Path) -> Snaps
     let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
 
     let mut snapshot = InstanceSnapshot::new()
-        .name(instance_name)
+        .name(unescape_file_name(instance_name).into_owned())
         .class_name(class_name)
         .properties(hashmap! {
             "Source".to_owned() => RbxValue::String {
@@ -239,6 +240,52 @@ mod test {
         insta::assert_yaml_snapshot!(instance_snapshot);
     }
 
+    #[test]
+    fn module_with_escaped_name() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/What%3F.lua", VfsSnapshot::file("Hello there!"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot =
+            SnapshotLua::from_vfs(&InstanceContext::default(), &vfs, Path::new("/What%3F.lua"))
+                .unwrap()
+                .unwrap();
+
+        insta::assert_yaml_snapshot!(instance_snapshot);
+    }
+
+    #[test]
+    fn module_with_meta_name() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo-lower.lua", VfsSnapshot::file("Hello there!"))
+            .unwrap();
+        imfs.load_snapshot(
+            "/foo-lower.meta.json",
+            VfsSnapshot::file(
+                r#"
+                    {
+                        "name": "foo"
+                    }
+                "#,
+            ),
+        )
+        .unwrap();
+
+        let vfs = Vfs::new(imfs);
+
+        let instance_snapshot = SnapshotLua::from_vfs(
+            &InstanceContext::default(),
+            &vfs,
+            Path::new("/foo-lower.lua"),
+        )
+        .unwrap()
+        .unwrap();
+
+        insta::assert_yaml_snapshot!(instance_snapshot);
+    }
+
     #[test]
     fn script_with_meta() {
         let mut imfs = InMemoryFs::new();
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::snapshot::InstanceSnapshot;
 
-use super::error::SnapshotError;
+use super::{error::SnapshotError, util::apply_child_order};
 
 /// Represents metadata in a sibling file with the same basename.
 ///
@@ -15,6 +15,11 @@ use super::error::SnapshotError;
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AdjacentMetadata {
+    /// Overrides the name of the instance, which is otherwise derived from
+    /// the file name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ignore_unknown_instances: Option<bool>,
 
@@ -28,6 +33,12 @@ impl AdjacentMetadata {
             .map_err(|source| SnapshotError::malformed_meta_json(source, path))
     }
 
+    pub fn apply_name(&mut self, snapshot: &mut InstanceSnapshot) {
+        if let Some(name) = self.name.take() {
+            snapshot.name = Cow::Owned(name);
+        }
+    }
+
     pub fn apply_ignore_unknown_instances(&mut self, snapshot: &mut InstanceSnapshot) {
         if let Some(ignore) = self.ignore_unknown_instances.take() {
             snapshot.metadata.ignore_unknown_instances = ignore;
@@ -49,6 +60,7 @@ impl AdjacentMetadata {
     }
 
     pub fn apply_all(&mut self, snapshot: &mut InstanceSnapshot) {
+        self.apply_name(snapshot);
         self.apply_ignore_unknown_instances(snapshot);
         self.apply_properties(snapshot);
     }
@@ -64,6 +76,11 @@ impl AdjacentMetadata {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DirectoryMetadata {
+    /// Overrides the name of the instance, which is otherwise derived from
+    /// the directory name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ignore_unknown_instances: Option<bool>,
 
@@ -72,6 +89,11 @@ pub struct DirectoryMetadata {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub class_name: Option<String>,
+
+    /// Names of children that should come first, in the given order. Any
+    /// children not listed here come after them in their default order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<String>>,
 }
 
 impl DirectoryMetadata {
@@ -81,9 +103,17 @@ impl DirectoryMetadata {
     }
 
     pub fn apply_all(&mut self, snapshot: &mut InstanceSnapshot) {
+        self.apply_name(snapshot);
         self.apply_ignore_unknown_instances(snapshot);
         self.apply_class_name(snapshot);
         self.apply_properties(snapshot);
+        self.apply_order(snapshot);
+    }
+
+    fn apply_name(&mut self, snapshot: &mut InstanceSnapshot) {
+        if let Some(name) = self.name.take() {
+            snapshot.name = Cow::Owned(name);
+        }
     }
 
     fn apply_class_name(&mut self, snapshot: &mut InstanceSnapshot) {
@@ -103,6 +133,18 @@ impl DirectoryMetadata {
         }
     }
 
+    fn apply_order(&mut self, snapshot: &mut InstanceSnapshot) {
+        if let Some(order) = self.order.take() {
+            for name in apply_child_order(&mut snapshot.children, &order) {
+                log::warn!(
+                    "The order in init.meta.json for {} lists a child named {}, which does not exist.",
+                    snapshot.name,
+                    name
+                );
+            }
+        }
+    }
+
     fn apply_properties(&mut self, snapshot: &mut InstanceSnapshot) {
         let class_name = &snapshot.class_name;
 
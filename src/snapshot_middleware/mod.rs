@@ -3,6 +3,7 @@
 
 #![allow(dead_code)]
 
+mod collision;
 mod csv;
 mod dir;
 mod error;
@@ -10,6 +11,7 @@ mod json_model;
 mod lua;
 mod meta_file;
 mod middleware;
+mod name_escape;
 mod project;
 mod rbxlx;
 mod rbxm;
@@ -29,9 +31,9 @@ use self::{
     project::SnapshotProject, rbxlx::SnapshotRbxlx, rbxm::SnapshotRbxm, rbxmx::SnapshotRbxmx,
     txt::SnapshotTxt,
 };
-use crate::snapshot::InstanceContext;
+use crate::{metrics, snapshot::InstanceContext, trace};
 
-pub use self::project::snapshot_project_node;
+pub use self::project::{compute_project_patch, snapshot_project_node};
 
 macro_rules! middlewares {
     ( $($middleware: ident,)* ) => {
@@ -44,10 +46,18 @@ macro_rules! middlewares {
             $(
                 log::trace!("trying middleware {} on {}", stringify!($middleware), path.display());
 
+                // Only the middleware that handles the path is timed, which
+                // includes it failing with an error.
+                let timer = metrics::SNAPSHOT_DURATION.start_timer(stringify!($middleware));
+                let span = trace::span_with_path(stringify!($middleware), path);
+
                 if let Some(snapshot) = $middleware::from_vfs(context, vfs, path)? {
                     log::trace!("middleware {} success on {}", stringify!($middleware), path.display());
                     return Ok(Some(snapshot));
                 }
+
+                timer.discard();
+                span.discard();
             )*
 
             log::trace!("no middleware returned Ok(Some)");
@@ -0,0 +1,206 @@
+//! Defines the reversible escaping scheme Rojo uses to turn instance names into
+//! file names and back.
+//!
+//! Roblox allows almost any string as an instance name, but most filesystems
+//! don't. Characters that are illegal or awkward on disk are encoded as `%XX`,
+//! where `XX` is the character's byte value in uppercase hex. For example, an
+//! instance named `What?` is stored as `What%3F.lua`.
+//!
+//! Escaping only covers names that a filesystem can't represent at all. Names
+//! that only collide on case-insensitive filesystems, like `Foo` and `foo`,
+//! should be given a different file name and use the `name` field of a meta
+//! file instead.
+
+use std::borrow::Cow;
+
+/// Device names that Windows reserves regardless of extension or case.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Turns an instance name into a string that is safe to use as the stem of a
+/// file name on all platforms Rojo supports. `unescape_file_name` reverses it.
+pub fn escape_file_name(name: &str) -> Cow<'_, str> {
+    let needs_escape = name.chars().any(must_escape)
+        || name.ends_with('.')
+        || name.ends_with(' ')
+        || is_reserved(name);
+
+    if !needs_escape {
+        return Cow::Borrowed(name);
+    }
+
+    let mut output = String::with_capacity(name.len() + 8);
+    let last_index = name.char_indices().last().map(|(index, _)| index);
+
+    for (index, character) in name.char_indices() {
+        let is_first = index == 0;
+        let is_last = Some(index) == last_index;
+
+        let escape = must_escape(character)
+            || (is_last && (character == '.' || character == ' '))
+            || (is_first && is_reserved(name));
+
+        if escape {
+            push_escaped(&mut output, character);
+        } else {
+            output.push(character);
+        }
+    }
+
+    Cow::Owned(output)
+}
+
+/// Turns the stem of a file name back into the instance name it represents.
+///
+/// Any `%XX` sequence that encodes an ASCII character is decoded, but only if
+/// the whole name is exactly what `escape_file_name` would produce for the
+/// result. Other file names are used as-is, so files from before escaping
+/// existed that happen to contain `%`, like `Hello%20World.lua`, keep their
+/// names.
+pub fn unescape_file_name(file_name: &str) -> Cow<'_, str> {
+    if !file_name.contains('%') {
+        return Cow::Borrowed(file_name);
+    }
+
+    let decoded = decode_file_name(file_name);
+
+    if escape_file_name(&decoded) == file_name {
+        Cow::Owned(decoded)
+    } else {
+        Cow::Borrowed(file_name)
+    }
+}
+
+fn decode_file_name(file_name: &str) -> String {
+    let bytes = file_name.as_bytes();
+    let mut output = String::with_capacity(file_name.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            if let Some(decoded) = decode_escape(bytes.get(index + 1..index + 3)) {
+                output.push(decoded);
+                index += 3;
+                continue;
+            }
+        }
+
+        // `index` always sits on a character boundary here, since escapes are
+        // pure ASCII and everything else is copied a full character at a time.
+        let character = file_name[index..].chars().next().unwrap();
+        output.push(character);
+        index += character.len_utf8();
+    }
+
+    output
+}
+
+fn must_escape(character: char) -> bool {
+    match character {
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '%' => true,
+        _ => character.is_ascii_control(),
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name);
+
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+fn push_escaped(output: &mut String, character: char) {
+    let mut buffer = [0; 4];
+
+    for byte in character.encode_utf8(&mut buffer).bytes() {
+        output.push_str(&format!("%{:02X}", byte));
+    }
+}
+
+fn decode_escape(hex: Option<&[u8]>) -> Option<char> {
+    let hex = std::str::from_utf8(hex?).ok()?;
+    let value = u8::from_str_radix(hex, 16).ok()?;
+
+    if value.is_ascii() {
+        Some(value as char)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(name: &str) -> String {
+        let escaped = escape_file_name(name);
+        unescape_file_name(&escaped).into_owned()
+    }
+
+    #[test]
+    fn plain_names_are_unchanged() {
+        assert_eq!(escape_file_name("Hello World"), "Hello World");
+        assert_eq!(escape_file_name("init.client"), "init.client");
+        assert_eq!(unescape_file_name("Hello World"), "Hello World");
+    }
+
+    #[test]
+    fn illegal_characters() {
+        assert_eq!(escape_file_name("What?"), "What%3F");
+        assert_eq!(escape_file_name("a/b\\c"), "a%2Fb%5Cc");
+        assert_eq!(escape_file_name("Key: <Value>"), "Key%3A %3CValue%3E");
+        assert_eq!(escape_file_name("100%"), "100%25");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces() {
+        assert_eq!(escape_file_name("Wait..."), "Wait..%2E");
+        assert_eq!(escape_file_name("Padded "), "Padded%20");
+    }
+
+    #[test]
+    fn reserved_names() {
+        assert_eq!(escape_file_name("CON"), "%43ON");
+        assert_eq!(escape_file_name("nul.thing"), "%6Eul.thing");
+        assert_eq!(escape_file_name("Console"), "Console");
+    }
+
+    #[test]
+    fn stray_percent_signs_are_kept() {
+        assert_eq!(unescape_file_name("50%"), "50%");
+        assert_eq!(unescape_file_name("50%zz"), "50%zz");
+        assert_eq!(unescape_file_name("%E2%82%AC"), "%E2%82%AC");
+    }
+
+    #[test]
+    fn unneeded_escapes_are_kept() {
+        assert_eq!(unescape_file_name("Hello%20World"), "Hello%20World");
+        assert_eq!(unescape_file_name("%41"), "%41");
+        assert_eq!(unescape_file_name("What%3f"), "What%3f");
+        assert_eq!(unescape_file_name("What%3F"), "What?");
+    }
+
+    #[test]
+    fn round_trips() {
+        let names = [
+            "Plain",
+            "What?",
+            "a/b",
+            "Trailing.",
+            "Trailing ",
+            "100%",
+            "%41",
+            "PRN",
+            "lpt1.bak",
+            "Tab\tHere",
+            "Ünïcödé: ✓",
+        ];
+
+        for name in &names {
+            assert_eq!(&round_trip(name), name);
+        }
+    }
+}
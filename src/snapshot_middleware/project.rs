@@ -1,19 +1,28 @@
-use std::{borrow::Cow, collections::HashMap, path::Path};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use memofs::{IoResultExt, Vfs};
+use rbx_dom_weak::RbxId;
 use rbx_reflection::try_resolve_value;
 
 use crate::{
     project::{Project, ProjectNode},
     snapshot::{
-        InstanceContext, InstanceMetadata, InstanceSnapshot, InstigatingSource, PathIgnoreRule,
+        compute_patch_set, InstanceContext, InstanceMetadata, InstanceSnapshot, InstigatingSource,
+        PatchAdd, PatchReorder, PatchSet, PatchUpdate, PathIgnoreRule, RojoTree,
     },
 };
 
 use super::{
+    collision::warn_sibling_collisions,
     error::SnapshotError,
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
     snapshot_from_vfs,
+    util::apply_child_order,
 };
 
 /// Handles snapshots for:
@@ -45,14 +54,7 @@ impl SnapshotMiddleware for SnapshotProject {
         let project = Project::load_from_slice(&vfs.read(path)?, path)
             .map_err(|err| SnapshotError::malformed_project(err, path))?;
 
-        let mut context = context.clone();
-
-        let rules = project.glob_ignore_paths.iter().map(|glob| PathIgnoreRule {
-            glob: glob.clone(),
-            base_path: project.folder_location().to_path_buf(),
-        });
-
-        context.add_path_ignore_rules(rules);
+        let context = project_context(context, &project);
 
         // Snapshotting a project should always return an instance, so this
         // unwrap is safe.
@@ -65,28 +67,464 @@ impl SnapshotMiddleware for SnapshotProject {
         )?
         .unwrap();
 
-        // Setting the instigating source to the project file path is a little
-        // coarse.
-        //
-        // Ideally, we'd only snapshot the project file if the project file
-        // actually changed. Because Rojo only has the concept of one
-        // relevant path -> snapshot path mapping per instance, we pick the more
-        // conservative approach of snapshotting the project file if any
-        // relevant paths changed.
-        snapshot.metadata.instigating_source = Some(path.to_path_buf().into());
-
-        // Mark this snapshot (the root node of the project file) as being
-        // related to the project file.
-        //
-        // We SHOULD NOT mark the project file as a relevant path for any
-        // nodes that aren't roots. They'll be updated as part of the project
-        // file being updated.
-        snapshot.metadata.relevant_paths.push(path.to_path_buf());
+        mark_project_root(&mut snapshot, path, project);
 
         Ok(Some(snapshot))
     }
 }
 
+/// Attaches the project file to the snapshot of its root node.
+fn mark_project_root(snapshot: &mut InstanceSnapshot, path: &Path, project: Project) {
+    // Setting the instigating source to the project file path means that
+    // any change to a relevant path of the root sends us back here.
+    //
+    // Because Rojo only has the concept of one relevant path -> snapshot
+    // path mapping per instance, we can't tell which part of the project
+    // changed from the path alone. Instead, we keep the project around in
+    // the metadata, and `compute_project_patch` compares it against the
+    // new one to only snapshot the nodes that changed.
+    snapshot.metadata.instigating_source = Some(path.to_path_buf().into());
+
+    // Mark this snapshot (the root node of the project file) as being
+    // related to the project file.
+    //
+    // We SHOULD NOT mark the project file as a relevant path for any
+    // nodes that aren't roots. They'll be updated as part of the project
+    // file being updated.
+    snapshot.metadata.relevant_paths.push(path.to_path_buf());
+
+    // Keep the project around so that we can tell which parts of it
+    // changed when the project file is edited.
+    snapshot.metadata.source_project = Some(Arc::new(project));
+}
+
+/// The changes that `compute_project_patch` found, along with every path that
+/// was snapshotted again to find them.
+pub struct ProjectPatch {
+    pub patch_set: PatchSet,
+    pub snapshotted_paths: Vec<PathBuf>,
+}
+
+impl ProjectPatch {
+    fn new(project_path: &Path) -> Self {
+        ProjectPatch {
+            patch_set: PatchSet::new(),
+            snapshotted_paths: vec![project_path.to_path_buf()],
+        }
+    }
+
+    /// Notes that the given snapshot, and every snapshot under it, was just
+    /// made again.
+    fn add_snapshotted(&mut self, snapshot: &InstanceSnapshot) {
+        self.snapshotted_paths
+            .extend(snapshot.relevant_paths_in_subtree());
+    }
+
+    /// Notes that the instance with the given ID, and every instance under it,
+    /// is about to be removed.
+    fn add_removed(&mut self, tree: &RojoTree, id: RbxId) {
+        self.patch_set.removed_instances.push(id);
+        self.add_replaced(tree, id);
+    }
+
+    /// Notes that the instance with the given ID, and every instance under it,
+    /// is about to be replaced or removed, so the paths they came from no
+    /// longer need to have anything reported about them.
+    fn add_replaced(&mut self, tree: &RojoTree, id: RbxId) {
+        self.snapshotted_paths
+            .extend(tree.get_relevant_paths_in_subtree(id));
+    }
+}
+
+/// Computes the changes needed to bring the instances created from a project
+/// file up to date with the file's current contents. Only the project nodes
+/// that actually changed are snapshotted again, which avoids reading every
+/// file referred to by a large project when one property is changed.
+///
+/// If the project didn't change at all, the change must have come from the
+/// root node's `$path` instead, so only that is snapshotted again.
+///
+/// Returns `Ok(None)` if the project changed in a way that needs the whole
+/// project to be snapshotted again, like changing its name, ignore globs, or
+/// root node.
+pub fn compute_project_patch(
+    context: &InstanceContext,
+    tree: &RojoTree,
+    vfs: &Vfs,
+    root_id: RbxId,
+    old_project: &Project,
+    path: &Path,
+) -> Result<Option<ProjectPatch>, SnapshotError> {
+    let new_project = Project::load_from_slice(&vfs.read(path)?, path)
+        .map_err(|err| SnapshotError::malformed_project(err, path))?;
+
+    if new_project == *old_project {
+        let context = project_context(context, &new_project);
+        let mut snapshot = snapshot_root_path(&context, tree, vfs, root_id, &new_project)?;
+        mark_project_root(&mut snapshot, path, new_project);
+
+        // The project's children were copied from the tree rather than being
+        // snapshotted again, so only the root's own paths count.
+        let mut patch = ProjectPatch::new(path);
+        patch
+            .snapshotted_paths
+            .extend_from_slice(&snapshot.metadata.relevant_paths);
+        patch.patch_set = compute_patch_set(snapshot, tree, root_id);
+
+        return Ok(Some(patch));
+    }
+
+    if new_project.name != old_project.name
+        || new_project.glob_ignore_paths != old_project.glob_ignore_paths
+        || needs_snapshot(&old_project.tree, &new_project.tree)
+    {
+        return Ok(None);
+    }
+
+    let context = project_context(context, &new_project);
+    let mut patch = ProjectPatch::new(path);
+
+    diff_project_children(
+        &context,
+        tree,
+        vfs,
+        new_project.folder_location(),
+        root_id,
+        &old_project.tree,
+        &new_project.tree,
+        &mut patch,
+    )?;
+
+    let mut metadata = tree
+        .get_metadata(root_id)
+        .expect("metadata missing for instance present in tree")
+        .clone();
+    metadata.source_project = Some(Arc::new(new_project));
+
+    patch
+        .patch_set
+        .updated_instances
+        .push(metadata_update(root_id, metadata));
+
+    Ok(Some(patch))
+}
+
+/// Snapshots the root node of a project again after a change under its `$path`.
+/// The children defined by the project haven't changed, so they're copied from
+/// the tree instead of being snapshotted again.
+fn snapshot_root_path(
+    context: &InstanceContext,
+    tree: &RojoTree,
+    vfs: &Vfs,
+    root_id: RbxId,
+    project: &Project,
+) -> Result<InstanceSnapshot, SnapshotError> {
+    let root_node = ProjectNode {
+        children: BTreeMap::new(),
+        order: None,
+        ..project.tree.clone()
+    };
+
+    // Snapshotting a project node should always return an instance.
+    let mut snapshot = snapshot_project_node(
+        context,
+        project.folder_location(),
+        &project.name,
+        &root_node,
+        vfs,
+    )?
+    .unwrap();
+
+    for name in project.tree.children.keys() {
+        if let Some(child_id) = find_project_child(tree, root_id, name) {
+            snapshot
+                .children
+                .push(InstanceSnapshot::from_tree(tree, child_id));
+        }
+    }
+
+    if let Some(order) = &project.tree.order {
+        apply_child_order(&mut snapshot.children, order);
+    }
+
+    Ok(snapshot)
+}
+
+/// Adds the ignore globs defined by a project to the given context, skipping
+/// any that it already has.
+fn project_context(context: &InstanceContext, project: &Project) -> InstanceContext {
+    let mut context = context.clone();
+
+    let rules: Vec<_> = project
+        .glob_ignore_paths
+        .iter()
+        .map(|glob| PathIgnoreRule {
+            glob: glob.clone(),
+            base_path: project.folder_location().to_path_buf(),
+        })
+        .filter(|rule| !context.path_ignore_rules.contains(rule))
+        .collect();
+
+    context.add_path_ignore_rules(rules);
+    context
+}
+
+/// Tells whether the instance described by a project node needs to be
+/// snapshotted again, rather than just having its children patched.
+fn needs_snapshot(old_node: &ProjectNode, new_node: &ProjectNode) -> bool {
+    // Children added to an instance are moved into name order by
+    // `order_added_children`, but a specific order is easier to get right by
+    // snapshotting the node again.
+    let children_changed = !old_node.children.keys().eq(new_node.children.keys());
+
+    old_node.class_name != new_node.class_name
+        || old_node.properties != new_node.properties
+        || old_node.ignore_unknown_instances != new_node.ignore_unknown_instances
+        || old_node.order != new_node.order
+        || old_node.path != new_node.path
+        || (children_changed && new_node.order.is_some())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn diff_project_children(
+    context: &InstanceContext,
+    tree: &RojoTree,
+    vfs: &Vfs,
+    project_folder: &Path,
+    parent_id: RbxId,
+    old_node: &ProjectNode,
+    new_node: &ProjectNode,
+    patch: &mut ProjectPatch,
+) -> Result<(), SnapshotError> {
+    let mut added = Vec::new();
+
+    for (name, old_child) in &old_node.children {
+        let child_id = find_project_child(tree, parent_id, name);
+
+        match (new_node.children.get(name), child_id) {
+            (None, Some(child_id)) => patch.add_removed(tree, child_id),
+            (Some(new_child), Some(child_id)) if new_child != old_child => {
+                diff_project_node(
+                    context,
+                    tree,
+                    vfs,
+                    project_folder,
+                    child_id,
+                    name,
+                    old_child,
+                    new_child,
+                    patch,
+                )?;
+            }
+            (Some(new_child), None) => {
+                added.extend(add_project_child(
+                    context,
+                    vfs,
+                    project_folder,
+                    parent_id,
+                    name,
+                    new_child,
+                    patch,
+                )?);
+            }
+            _ => {}
+        }
+    }
+
+    for (name, new_child) in &new_node.children {
+        if !old_node.children.contains_key(name) {
+            added.extend(add_project_child(
+                context,
+                vfs,
+                project_folder,
+                parent_id,
+                name,
+                new_child,
+                patch,
+            )?);
+        }
+    }
+
+    if !added.is_empty() {
+        order_added_children(tree, parent_id, new_node, &added, &mut patch.patch_set);
+    }
+
+    Ok(())
+}
+
+/// Applying a patch puts added children after all of the existing ones, but
+/// snapshotting a project puts the children it defines in name order after the
+/// children from `$path`. Emits a reorder if any added child belongs earlier,
+/// so that `rojo serve` and `rojo build` agree.
+///
+/// `added` holds the name and snapshot ID of each child added by the patch.
+fn order_added_children(
+    tree: &RojoTree,
+    parent_id: RbxId,
+    node: &ProjectNode,
+    added: &[(String, RbxId)],
+    patch_set: &mut PatchSet,
+) {
+    let parent = match tree.get_instance(parent_id) {
+        Some(parent) => parent,
+        None => return,
+    };
+
+    let removed: HashSet<RbxId> = patch_set.removed_instances.iter().copied().collect();
+    let kept: Vec<RbxId> = parent
+        .children()
+        .iter()
+        .copied()
+        .filter(|id| !removed.contains(id))
+        .collect();
+
+    let mut project_children = HashMap::new();
+    let mut desired = Vec::new();
+
+    for &child_id in &kept {
+        let child = tree.get_instance(child_id).unwrap();
+
+        match &child.metadata().instigating_source {
+            Some(InstigatingSource::ProjectNode(_, name, _))
+                if node.children.contains_key(name) =>
+            {
+                project_children.insert(name.as_str(), child_id);
+            }
+            _ => desired.push(child_id),
+        }
+    }
+
+    for name in node.children.keys() {
+        let existing = project_children.get(name.as_str()).copied();
+        let added_id = added
+            .iter()
+            .find(|(added_name, _)| added_name == name)
+            .map(|(_, id)| *id);
+
+        desired.extend(existing.or(added_id));
+    }
+
+    let appended = kept.into_iter().chain(added.iter().map(|(_, id)| *id));
+
+    if !appended.eq(desired.iter().copied()) {
+        patch_set.reordered_children.push(PatchReorder {
+            parent_id,
+            children: desired,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn diff_project_node(
+    context: &InstanceContext,
+    tree: &RojoTree,
+    vfs: &Vfs,
+    project_folder: &Path,
+    id: RbxId,
+    name: &str,
+    old_node: &ProjectNode,
+    new_node: &ProjectNode,
+    patch: &mut ProjectPatch,
+) -> Result<(), SnapshotError> {
+    if needs_snapshot(old_node, new_node) {
+        // Snapshotting a project node should always return an instance.
+        let snapshot =
+            snapshot_project_node(context, project_folder, name, new_node, vfs)?.unwrap();
+        patch.add_replaced(tree, id);
+        patch.add_snapshotted(&snapshot);
+        patch
+            .patch_set
+            .append(compute_patch_set(snapshot, tree, id));
+
+        return Ok(());
+    }
+
+    diff_project_children(
+        context,
+        tree,
+        vfs,
+        project_folder,
+        id,
+        old_node,
+        new_node,
+        patch,
+    )?;
+
+    // The node stored as the instance's instigating source has to be kept up
+    // to date for the next time we compare against it.
+    let mut metadata = tree
+        .get_metadata(id)
+        .expect("metadata missing for instance present in tree")
+        .clone();
+    metadata.instigating_source = Some(InstigatingSource::ProjectNode(
+        project_folder.to_path_buf(),
+        name.to_owned(),
+        new_node.clone(),
+    ));
+
+    patch
+        .patch_set
+        .updated_instances
+        .push(metadata_update(id, metadata));
+
+    Ok(())
+}
+
+fn add_project_child(
+    context: &InstanceContext,
+    vfs: &Vfs,
+    project_folder: &Path,
+    parent_id: RbxId,
+    name: &str,
+    node: &ProjectNode,
+    patch: &mut ProjectPatch,
+) -> Result<Option<(String, RbxId)>, SnapshotError> {
+    match snapshot_project_node(context, project_folder, name, node, vfs)? {
+        Some(mut snapshot) => {
+            // Added instances are referred to by snapshot ID in case they need
+            // to be reordered.
+            let snapshot_id = *snapshot.snapshot_id.get_or_insert_with(RbxId::new);
+
+            patch.add_snapshotted(&snapshot);
+            patch.patch_set.added_instances.push(PatchAdd {
+                parent_id,
+                instance: snapshot,
+            });
+
+            Ok(Some((name.to_owned(), snapshot_id)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Finds the child of an instance that was created from the project node with
+/// the given name.
+fn find_project_child(tree: &RojoTree, parent_id: RbxId, name: &str) -> Option<RbxId> {
+    let parent = tree.get_instance(parent_id)?;
+
+    parent.children().iter().copied().find(|&child_id| {
+        let child = match tree.get_instance(child_id) {
+            Some(child) => child,
+            None => return false,
+        };
+
+        match &child.metadata().instigating_source {
+            Some(InstigatingSource::ProjectNode(_, node_name, _)) => node_name == name,
+            _ => false,
+        }
+    })
+}
+
+fn metadata_update(id: RbxId, metadata: InstanceMetadata) -> PatchUpdate {
+    PatchUpdate {
+        id,
+        changed_name: None,
+        changed_class_name: None,
+        changed_properties: HashMap::new(),
+        changed_metadata: Some(metadata),
+    }
+}
+
 pub fn snapshot_project_node(
     context: &InstanceContext,
     project_folder: &Path,
@@ -169,6 +607,31 @@ pub fn snapshot_project_node(
         }
     }
 
+    // Children from $path were already checked when they were snapshotted, so
+    // we only need to look again if the project added children of its own.
+    if !node.children.is_empty() {
+        let node_path = match &node.path {
+            Some(path) => project_folder.join(path),
+            None => project_folder.to_path_buf(),
+        };
+
+        warn_sibling_collisions(
+            &node_path,
+            format_args!("Project node \"{}\"", instance_name),
+            &children,
+        );
+    }
+
+    if let Some(order) = &node.order {
+        for name in apply_child_order(&mut children, order) {
+            log::warn!(
+                "$order for {} lists a child named {}, which does not exist.",
+                instance_name,
+                name
+            );
+        }
+    }
+
     for (key, value) in &node.properties {
         let resolved_value = try_resolve_value(&class_name, key, value)
             .expect("TODO: Properly handle value resolution errors");
@@ -7,6 +7,7 @@ use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
 
 use super::{
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     util::match_file_name,
 };
 
@@ -39,8 +40,9 @@ impl SnapshotMiddleware for SnapshotRbxm {
         let children = root_instance.get_children_ids();
 
         if children.len() == 1 {
-            let snapshot = InstanceSnapshot::from_tree(&temp_tree, children[0])
-                .name(instance_name)
+            let child_id = children[0];
+            let snapshot = InstanceSnapshot::take_from_tree(&mut temp_tree, child_id)
+                .name(unescape_file_name(instance_name).into_owned())
                 .metadata(
                     InstanceMetadata::new()
                         .instigating_source(path)
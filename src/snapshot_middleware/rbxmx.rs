@@ -6,6 +6,7 @@ use crate::snapshot::{InstanceContext, InstanceMetadata, InstanceSnapshot};
 
 use super::{
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     util::match_file_name,
 };
 
@@ -27,15 +28,16 @@ impl SnapshotMiddleware for SnapshotRbxmx {
         let options = rbx_xml::DecodeOptions::new()
             .property_behavior(rbx_xml::DecodePropertyBehavior::ReadUnknown);
 
-        let temp_tree = rbx_xml::from_reader(vfs.read(path)?.as_slice(), options)
+        let mut temp_tree = rbx_xml::from_reader(vfs.read(path)?.as_slice(), options)
             .expect("TODO: Handle rbx_xml errors");
 
         let root_instance = temp_tree.get_instance(temp_tree.get_root_id()).unwrap();
         let children = root_instance.get_children_ids();
 
         if children.len() == 1 {
-            let snapshot = InstanceSnapshot::from_tree(&temp_tree, children[0])
-                .name(instance_name)
+            let child_id = children[0];
+            let snapshot = InstanceSnapshot::take_from_tree(&mut temp_tree, child_id)
+                .name(unescape_file_name(instance_name).into_owned())
                 .metadata(
                     InstanceMetadata::new()
                         .instigating_source(path)
@@ -10,6 +10,7 @@ use super::{
     error::SnapshotError,
     meta_file::AdjacentMetadata,
     middleware::{SnapshotInstanceResult, SnapshotMiddleware},
+    name_escape::unescape_file_name,
     util::match_file_name,
 };
 
@@ -42,7 +43,7 @@ impl SnapshotMiddleware for SnapshotTxt {
         let meta_path = path.with_file_name(format!("{}.meta.json", instance_name));
 
         let mut snapshot = InstanceSnapshot::new()
-            .name(instance_name)
+            .name(unescape_file_name(instance_name).into_owned())
             .class_name("StringValue")
             .properties(properties)
             .metadata(
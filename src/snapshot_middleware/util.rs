@@ -1,5 +1,7 @@
 use std::path::Path;
 
+use crate::snapshot::InstanceSnapshot;
+
 /// If the given string ends up with the given suffix, returns the portion of
 /// the string before the suffix.
 pub fn match_trailing<'a>(input: &'a str, suffix: &str) -> Option<&'a str> {
@@ -18,3 +20,40 @@ pub fn match_file_name<'a>(path: &'a Path, suffix: &str) -> Option<&'a str> {
 
     match_trailing(&file_name, suffix)
 }
+
+/// Moves the children named in `order` to the front of `children`, in the
+/// order they're listed. Children that aren't listed keep their existing
+/// relative order and come after all of the listed ones.
+///
+/// Returns the names from `order` that didn't match any child.
+pub fn apply_child_order<'a>(
+    children: &mut Vec<InstanceSnapshot>,
+    order: &'a [String],
+) -> Vec<&'a str> {
+    let mut remaining: Vec<Option<InstanceSnapshot>> = children.drain(..).map(Some).collect();
+    let mut unmatched = Vec::new();
+
+    for name in order {
+        let mut found = false;
+
+        for slot in remaining.iter_mut() {
+            let matches = match slot {
+                Some(child) => child.name == name.as_str(),
+                None => false,
+            };
+
+            if matches {
+                children.extend(slot.take());
+                found = true;
+            }
+        }
+
+        if !found {
+            unmatched.push(name.as_str());
+        }
+    }
+
+    children.extend(remaining.into_iter().flatten());
+
+    unmatched
+}
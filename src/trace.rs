@@ -0,0 +1,204 @@
+//! Records how long Rojo spends on each part of building or serving a project
+//! when `--trace` is passed, in the Chrome Trace Event format. Traces can be
+//! opened as a flame chart in `chrome://tracing` or https://ui.perfetto.dev.
+//!
+//! Events are written to the file as spans finish, instead of being kept until
+//! Rojo exits, since `rojo serve` usually exits by being interrupted. The trace
+//! format allows the closing `]` to be missing for the same reason.
+
+use std::{
+    cell::Cell,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+/// Checked before doing anything else, so that spans cost next to nothing when
+/// tracing isn't turned on.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+lazy_static! {
+    static ref TRACE: Mutex<Option<TraceFile>> = Mutex::new(None);
+}
+
+thread_local! {
+    /// Chrome traces need a number for each thread, but Rust doesn't expose
+    /// one.
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+
+    /// How many spans are open on this thread. Events are flushed to the file
+    /// whenever the outermost one finishes.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+struct TraceFile {
+    output: BufWriter<File>,
+    start: Instant,
+    wrote_event: bool,
+}
+
+/// A "complete" event, which has both a start time and a duration.
+#[derive(Serialize)]
+struct Event<'a> {
+    name: &'a str,
+    ph: &'static str,
+
+    /// Start time and duration of the event, in microseconds.
+    ts: f64,
+    dur: f64,
+
+    pid: u32,
+    tid: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<EventArgs<'a>>,
+}
+
+#[derive(Serialize)]
+struct EventArgs<'a> {
+    path: &'a Path,
+}
+
+/// Starts writing a trace to the given file. Spans that start before this is
+/// called aren't recorded.
+pub fn start(path: &Path) -> io::Result<()> {
+    let mut output = BufWriter::new(File::create(path)?);
+    output.write_all(b"[\n")?;
+    output.flush()?;
+
+    *TRACE.lock().unwrap() = Some(TraceFile {
+        output,
+        start: Instant::now(),
+        wrote_event: false,
+    });
+
+    ENABLED.store(true, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/// Stops tracing and finishes writing the trace file.
+pub fn finish() -> io::Result<()> {
+    ENABLED.store(false, Ordering::SeqCst);
+
+    match TRACE.lock().unwrap().take() {
+        Some(mut trace) => {
+            trace.output.write_all(b"\n]\n")?;
+            trace.output.flush()
+        }
+        None => Ok(()),
+    }
+}
+
+/// Starts a span with the given name, which is recorded once the returned
+/// `Span` is dropped.
+pub fn span(name: &'static str) -> Span {
+    Span::new(name, None)
+}
+
+/// Starts a span about the file or folder at the given path.
+pub fn span_with_path(name: &'static str, path: &Path) -> Span {
+    Span::new(name, Some(path))
+}
+
+pub struct Span {
+    /// `None` if tracing wasn't turned on when the span started.
+    data: Option<SpanData>,
+}
+
+struct SpanData {
+    name: &'static str,
+    path: Option<PathBuf>,
+    start: Instant,
+}
+
+impl Span {
+    fn new(name: &'static str, path: Option<&Path>) -> Self {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return Span { data: None };
+        }
+
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+
+        Span {
+            data: Some(SpanData {
+                name,
+                path: path.map(Path::to_path_buf),
+                start: Instant::now(),
+            }),
+        }
+    }
+
+    /// Ends the span without recording it.
+    pub fn discard(mut self) {
+        if self.data.take().is_some() {
+            DEPTH.with(|depth| depth.set(depth.get() - 1));
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let data = match self.data.take() {
+            Some(data) => data,
+            None => return,
+        };
+
+        let duration = data.start.elapsed();
+        let outermost = DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            depth.get() == 0
+        });
+
+        let mut trace = TRACE.lock().unwrap();
+        let trace = match trace.as_mut() {
+            Some(trace) => trace,
+            None => return,
+        };
+
+        let event = Event {
+            name: data.name,
+            ph: "X",
+            ts: data
+                .start
+                .checked_duration_since(trace.start)
+                .unwrap_or_default()
+                .as_secs_f64()
+                * 1_000_000.0,
+            dur: duration.as_secs_f64() * 1_000_000.0,
+            pid: std::process::id(),
+            tid: THREAD_ID.with(|id| *id),
+            args: data.path.as_ref().map(|path| EventArgs { path }),
+        };
+
+        let result = (|| {
+            if trace.wrote_event {
+                trace.output.write_all(b",\n")?;
+            }
+
+            serde_json::to_writer(&mut trace.output, &event)?;
+            trace.wrote_event = true;
+
+            if outermost {
+                trace.output.flush()?;
+            }
+
+            Ok::<_, io::Error>(())
+        })();
+
+        if let Err(err) = result {
+            log::error!("Could not write to trace file: {}", err);
+            ENABLED.store(false, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Decides which requests are allowed to reach the server.
+//!
+//! Browsers will happily send requests to Rojo from any website the user has
+//! open. Websites can't read the responses thanks to the same-origin policy,
+//! unless they use DNS rebinding to make the browser believe that Rojo is part
+//! of their own origin. Those requests always name the attacker's domain in
+//! their Host header, so requests are only accepted if they refer to the
+//! server by IP address or as `localhost`.
+//!
+//! Projects can also list the places they're allowed to be synced into, which
+//! is checked against the place ID that clients send with each request.
+
+use std::collections::HashSet;
+
+use hyper::{
+    header::{AUTHORIZATION, COOKIE, HOST, ORIGIN},
+    Request,
+};
+
+use super::interface::PLACE_ID_HEADER;
+
+/// Checks that the Host and Origin headers of the request, if present, refer
+/// to this server by IP address or as `localhost`.
+pub fn check_host_and_origin<B>(request: &Request<B>) -> Result<(), String> {
+    if let Some(host) = request.headers().get(HOST) {
+        let host = host.to_str().unwrap_or("");
+
+        if !is_allowed_host(host) {
+            return Err(format!("Requests for host {:?} are not allowed", host));
+        }
+    }
+
+    if let Some(origin) = request.headers().get(ORIGIN) {
+        let origin = origin.to_str().unwrap_or("");
+
+        // Origins look like `scheme://host:port`.
+        let host = match origin.find("://") {
+            Some(index) => &origin[index + "://".len()..],
+            None => origin,
+        };
+
+        if !is_allowed_host(host) {
+            return Err(format!("Requests from origin {:?} are not allowed", origin));
+        }
+    }
+
+    Ok(())
+}
+
+/// Tells whether the request has an `Authorization` header with the given
+/// bearer token.
+pub fn has_bearer_token<B>(request: &Request<B>, token: &str) -> bool {
+    let header = match request.headers().get(AUTHORIZATION) {
+        Some(header) => header.as_bytes(),
+        None => return false,
+    };
+
+    let prefix = b"Bearer ";
+
+    if !header.starts_with(prefix) {
+        return false;
+    }
+
+    constant_time_eq(&header[prefix.len()..], token.as_bytes())
+}
+
+/// The cookie that browsers keep the authentication token in, since they
+/// can't send an `Authorization` header when navigating to a page.
+pub const TOKEN_COOKIE: &str = "rojo_token";
+
+/// Tells whether the request carries the given token in the `Authorization`
+/// header or in the token cookie.
+pub fn has_token<B>(request: &Request<B>, token: &str) -> bool {
+    has_bearer_token(request, token) || has_token_cookie(request, token)
+}
+
+/// Tells whether the request has the token cookie set to the given token.
+pub fn has_token_cookie<B>(request: &Request<B>, token: &str) -> bool {
+    request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|cookie| {
+            let mut parts = cookie.trim().splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(TOKEN_COOKIE), Some(value)) => Some(value),
+                _ => None,
+            }
+        })
+        .any(|value| constant_time_eq(value.as_bytes(), token.as_bytes()))
+}
+
+/// Finds the `token` parameter in the request's query string, which is how a
+/// browser is first given the token.
+pub fn query_token<B>(request: &Request<B>) -> Option<&str> {
+    request.uri().query()?.split('&').find_map(|pair| {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("token"), Some(value)) => Some(value),
+            _ => None,
+        }
+    })
+}
+
+/// Checks that the request comes from one of the given places, going by the
+/// place ID in its `Rojo-Place-Id` header. Requests without one are rejected,
+/// since there's no telling where they came from.
+pub fn check_place_id<B>(request: &Request<B>, allowed: &HashSet<u64>) -> Result<(), String> {
+    let header = match request.headers().get(PLACE_ID_HEADER) {
+        Some(header) => header,
+        None => {
+            return Err(format!(
+                "This project is limited to certain places, but the request has no {} header",
+                PLACE_ID_HEADER
+            ));
+        }
+    };
+
+    let place_id: u64 = header
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| format!("Malformed {} header", PLACE_ID_HEADER))?;
+
+    if !allowed.contains(&place_id) {
+        return Err(format!(
+            "Place {} is not listed in this project's servePlaceIds",
+            place_id
+        ));
+    }
+
+    Ok(())
+}
+
+/// Tells whether a Host header value, which is a host name with an optional
+/// port, refers to an IP address or `localhost`.
+fn is_allowed_host(host: &str) -> bool {
+    // IPv6 addresses are wrapped in brackets so that they can be told apart
+    // from the port.
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(end) => host[1..end].parse::<std::net::Ipv6Addr>().is_ok(),
+            None => false,
+        };
+    }
+
+    let name = match host.rfind(':') {
+        Some(index) => &host[..index],
+        None => host,
+    };
+
+    name.eq_ignore_ascii_case("localhost") || name.parse::<std::net::Ipv4Addr>().is_ok()
+}
+
+/// Compares two byte strings in a way that takes the same amount of time no
+/// matter where they differ, so that the time it takes to reject a token
+/// doesn't reveal how much of it was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter()
+        .zip(b)
+        .fold(0, |difference, (x, y)| difference | (x ^ y))
+        == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut builder = Request::builder();
+
+        for (name, value) in headers {
+            builder.header(*name, *value);
+        }
+
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn hosts() {
+        let allowed = [
+            "localhost:34872",
+            "LOCALHOST",
+            "127.0.0.1:34872",
+            "192.168.1.20:34872",
+            "[::1]:34872",
+        ];
+
+        for host in &allowed {
+            assert_eq!(check_host_and_origin(&request(&[("Host", host)])), Ok(()));
+        }
+
+        let rejected = [
+            "evil.example.com:34872",
+            "localhost.evil.example.com",
+            "[::1",
+        ];
+
+        for host in &rejected {
+            assert!(check_host_and_origin(&request(&[("Host", host)])).is_err());
+        }
+
+        assert!(check_host_and_origin(&request(&[
+            ("Host", "localhost:34872"),
+            ("Origin", "https://evil.example.com")
+        ]))
+        .is_err());
+
+        assert_eq!(
+            check_host_and_origin(&request(&[
+                ("Host", "localhost:34872"),
+                ("Origin", "http://localhost:34872")
+            ])),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn bearer_token() {
+        let token = "abc123";
+
+        assert!(has_bearer_token(
+            &request(&[("Authorization", "Bearer abc123")]),
+            token
+        ));
+
+        assert!(!has_bearer_token(&request(&[]), token));
+        assert!(!has_bearer_token(
+            &request(&[("Authorization", "Bearer abc124")]),
+            token
+        ));
+        assert!(!has_bearer_token(
+            &request(&[("Authorization", "Basic abc123")]),
+            token
+        ));
+    }
+
+    #[test]
+    fn token_cookie() {
+        let token = "abc123";
+
+        assert!(has_token(
+            &request(&[("Cookie", "theme=dark; rojo_token=abc123")]),
+            token
+        ));
+        assert!(has_token(
+            &request(&[("Authorization", "Bearer abc123")]),
+            token
+        ));
+
+        assert!(!has_token(&request(&[]), token));
+        assert!(!has_token(
+            &request(&[("Cookie", "rojo_token=abc124")]),
+            token
+        ));
+        assert!(!has_token(
+            &request(&[("Cookie", "other_rojo_token=abc123")]),
+            token
+        ));
+    }
+
+    #[test]
+    fn token_query() {
+        fn get(uri: &str) -> Request<()> {
+            Request::builder().uri(uri).body(()).unwrap()
+        }
+
+        assert_eq!(query_token(&get("/?token=abc123")), Some("abc123"));
+        assert_eq!(
+            query_token(&get("/api/rojo?a=b&token=abc123")),
+            Some("abc123")
+        );
+        assert_eq!(query_token(&get("/?tokens=abc123")), None);
+        assert_eq!(query_token(&get("/")), None);
+    }
+
+    #[test]
+    fn place_ids() {
+        let allowed: HashSet<u64> = [123, 456].iter().cloned().collect();
+
+        assert_eq!(
+            check_place_id(&request(&[("Rojo-Place-Id", "456")]), &allowed),
+            Ok(())
+        );
+
+        assert!(check_place_id(&request(&[]), &allowed).is_err());
+        assert!(check_place_id(&request(&[("Rojo-Place-Id", "789")]), &allowed).is_err());
+        assert!(check_place_id(&request(&[("Rojo-Place-Id", "0x7b")]), &allowed).is_err());
+    }
+}
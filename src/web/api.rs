@@ -1,28 +1,41 @@
 //! Defines Rojo's HTTP API, all under /api. These endpoints generally return
-//! JSON.
+//! JSON, or MessagePack for clients that ask for it.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
 
-use futures::{Future, Stream};
+use futures::{future::Either, sync::oneshot, Future, Stream};
 
 use hyper::{service::Service, Body, Method, Request, StatusCode};
-use rbx_dom_weak::RbxId;
+use rbx_dom_weak::{RbxId, RbxValue};
 
 use crate::{
+    change_processor::{SyncCommand, SyncControl, TreeMutation, TreeMutationError},
+    metrics,
     serve_session::ServeSession,
-    snapshot::{PatchSet, PatchUpdate},
+    snapshot::{AppliedPatchSet, PatchSet, PatchUpdate, RojoTree},
     web::{
+        access,
+        clients::{ClientKey, Clients},
         interface::{
-            ErrorResponse, Instance, InstanceMetadata as WebInstanceMetadata, InstanceUpdate,
-            ReadResponse, ServerInfoResponse, SubscribeMessage, SubscribeResponse, WriteRequest,
-            WriteResponse, PROTOCOL_VERSION, SERVER_VERSION,
+            DiagnosticList, DiagnosticsResponse, ErrorResponse, Instance,
+            InstanceMetadata as WebInstanceMetadata, InstanceReorder, InstanceUpdate, ReadResponse,
+            ServerInfoResponse, SubscribeMessage, SubscribeResponse, SyncStatusResponse, TextPatch,
+            WriteConflict, WriteRequest, WriteResponse, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+            SERVER_VERSION,
         },
-        util::{json, json_ok},
+        protocol::ProtocolVersion,
+        read_page::{read_page, Continuation},
+        text_patch::text_patch,
+        util::{json, Encoding, CONTENT_CODINGS, RESPONSE_FORMATS},
     },
 };
 
 pub struct ApiService {
     serve_session: Arc<ServeSession>,
+    clients: Arc<Clients>,
+
+    /// The address of the client on the other end of this connection.
+    remote_address: Option<IpAddr>,
 }
 
 impl Service for ApiService {
@@ -33,47 +46,118 @@ impl Service for ApiService {
         Box<dyn Future<Item = hyper::Response<Self::ReqBody>, Error = Self::Error> + Send>;
 
     fn call(&mut self, request: hyper::Request<Self::ReqBody>) -> Self::Future {
+        let timer = metrics::REQUEST_DURATION.start_timer(route_name(request.uri().path()));
+
+        Box::new(self.route(request).then(move |result| {
+            drop(timer);
+            result
+        }))
+    }
+}
+
+impl ApiService {
+    pub fn new(
+        serve_session: Arc<ServeSession>,
+        clients: Arc<Clients>,
+        remote_address: Option<IpAddr>,
+    ) -> Self {
+        ApiService {
+            serve_session,
+            clients,
+            remote_address,
+        }
+    }
+
+    fn route(&mut self, request: Request<Body>) -> <Self as Service>::Future {
+        let protocol = match ProtocolVersion::negotiate(&request) {
+            Ok(protocol) => protocol,
+            Err(err) => return json(err, StatusCode::BAD_REQUEST),
+        };
+
+        // Clients need to be able to read the list of allowed places from
+        // /api/rojo, but nothing else.
+        if request.uri().path() != "/api/rojo" {
+            if let Some(place_ids) = self.serve_session.serve_place_ids() {
+                if let Err(message) = access::check_place_id(&request, place_ids) {
+                    log::warn!(
+                        "Rejected {} {}: {}",
+                        request.method(),
+                        request.uri().path(),
+                        message
+                    );
+                    return json(ErrorResponse::forbidden(message), StatusCode::FORBIDDEN);
+                }
+            }
+        }
+
+        let client = self
+            .clients
+            .record_request(&request, self.remote_address, protocol.number());
+
+        let encoding = Encoding::negotiate(&request);
+
         match (request.method(), request.uri().path()) {
-            (&Method::GET, "/api/rojo") => self.handle_api_rojo(),
-            (&Method::GET, path) if path.starts_with("/api/read/") => self.handle_api_read(request),
+            (&Method::GET, "/api/rojo") => self.handle_api_rojo(protocol, encoding),
+            (&Method::GET, path) if path.starts_with("/api/read/") => {
+                self.handle_api_read(request, protocol, encoding)
+            }
             (&Method::GET, path) if path.starts_with("/api/subscribe/") => {
-                self.handle_api_subscribe(request)
+                self.handle_api_subscribe(request, protocol, encoding, client)
             }
+            (&Method::GET, "/api/diagnostics") => self.handle_api_diagnostics(encoding),
 
             (&Method::POST, "/api/write") if cfg!(feature = "unstable_two_way_sync") => {
-                self.handle_api_write(request)
+                self.handle_api_write(request, encoding)
             }
 
+            (&Method::POST, "/api/pause") => self.handle_api_sync(SyncCommand::Pause, encoding),
+            (&Method::POST, "/api/resume") => self.handle_api_sync(SyncCommand::Resume, encoding),
+            (&Method::POST, "/api/resync") => self.handle_api_sync(SyncCommand::Resync, encoding),
+
             (_method, path) => json(
                 ErrorResponse::not_found(format!("Route not found: {}", path)),
                 StatusCode::NOT_FOUND,
             ),
         }
     }
-}
-
-impl ApiService {
-    pub fn new(serve_session: Arc<ServeSession>) -> Self {
-        ApiService { serve_session }
-    }
 
     /// Get a summary of information about the server
-    fn handle_api_rojo(&self) -> <Self as Service>::Future {
+    fn handle_api_rojo(
+        &self,
+        protocol: ProtocolVersion,
+        encoding: Encoding,
+    ) -> <Self as Service>::Future {
         let tree = self.serve_session.tree();
         let root_instance_id = tree.get_root_id();
 
-        json_ok(&ServerInfoResponse {
+        encoding.respond_ok(&ServerInfoResponse {
             server_version: SERVER_VERSION.to_owned(),
-            protocol_version: PROTOCOL_VERSION,
+            protocol_version: protocol.number(),
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            max_protocol_version: PROTOCOL_VERSION,
             session_id: self.serve_session.session_id(),
             expected_place_ids: self.serve_session.serve_place_ids().cloned(),
             root_instance_id,
+            response_formats: RESPONSE_FORMATS
+                .iter()
+                .map(|&format| format.to_owned())
+                .collect(),
+            content_encodings: CONTENT_CODINGS
+                .iter()
+                .map(|&coding| coding.to_owned())
+                .collect(),
         })
     }
 
     /// Retrieve any messages past the given cursor index, and if
     /// there weren't any, subscribe to receive any new messages.
-    fn handle_api_subscribe(&self, request: Request<Body>) -> <Self as Service>::Future {
+    fn handle_api_subscribe(
+        &self,
+        request: Request<Body>,
+        protocol: ProtocolVersion,
+        encoding: Encoding,
+        client: ClientKey,
+    ) -> <Self as Service>::Future {
         let argument = &request.uri().path()["/api/subscribe/".len()..];
         let input_cursor: u32 = match argument.parse() {
             Ok(v) => v,
@@ -85,62 +169,72 @@ impl ApiService {
             }
         };
 
+        let mut diagnostics_cursor = None;
+
+        for (name, value) in query_pairs(request.uri().query().unwrap_or("")) {
+            if name == "diagnostics" {
+                match value.parse::<u32>() {
+                    Ok(v) => diagnostics_cursor = Some(v),
+                    Err(err) => {
+                        return json(
+                            ErrorResponse::bad_request(format!(
+                                "Malformed diagnostics cursor: {}",
+                                err
+                            )),
+                            StatusCode::BAD_REQUEST,
+                        );
+                    }
+                }
+            }
+        }
+
         let session_id = self.serve_session.session_id();
 
         let receiver = self.serve_session.message_queue().subscribe(input_cursor);
 
+        // Clients that ask about diagnostics are also woken up when those
+        // change, in which case there aren't any new messages for them.
+        let receiver: Box<dyn Future<Item = _, Error = _> + Send> = match diagnostics_cursor {
+            Some(cursor) => Box::new(
+                receiver
+                    .select2(self.serve_session.diagnostics().subscribe(cursor))
+                    .then(move |result| match result {
+                        Ok(Either::A((messages, _))) => Ok(messages),
+                        Ok(Either::B(_)) => Ok((input_cursor, Vec::new())),
+                        Err(Either::A((err, _))) | Err(Either::B((err, _))) => Err(err),
+                    }),
+            ),
+            None => Box::new(receiver),
+        };
+
         let tree_handle = self.serve_session.tree_handle();
+        let diagnostics = self.serve_session.diagnostics_handle();
+
+        // The client counts as waiting until the response is ready, or until
+        // it hangs up and the response is dropped.
+        let waiting = self.clients.wait_for_messages(client);
 
         Box::new(receiver.then(move |result| match result {
             Ok((message_cursor, messages)) => {
+                drop(waiting);
+
                 let tree = tree_handle.lock().unwrap();
 
                 let api_messages = messages
                     .into_iter()
-                    .map(|message| {
-                        let removed = message.removed;
-
-                        let mut added = HashMap::new();
-                        for id in message.added {
-                            let instance = tree.get_instance(id).unwrap();
-                            added.insert(id, Instance::from_rojo_instance(instance));
-
-                            for instance in tree.descendants(id) {
-                                added.insert(instance.id(), Instance::from_rojo_instance(instance));
-                            }
-                        }
-
-                        let updated = message
-                            .updated
-                            .into_iter()
-                            .map(|update| {
-                                let changed_metadata = update
-                                    .changed_metadata
-                                    .as_ref()
-                                    .map(WebInstanceMetadata::from_rojo_metadata);
-
-                                InstanceUpdate {
-                                    id: update.id,
-                                    changed_name: update.changed_name,
-                                    changed_class_name: update.changed_class_name,
-                                    changed_properties: update.changed_properties,
-                                    changed_metadata,
-                                }
-                            })
-                            .collect();
-
-                        SubscribeMessage {
-                            removed,
-                            added,
-                            updated,
-                        }
-                    })
+                    .map(|message| subscribe_message(&tree, message, protocol))
                     .collect();
 
-                json_ok(SubscribeResponse {
+                let diagnostics = diagnostics_cursor.map(|_| {
+                    let (cursor, current) = diagnostics.current();
+                    DiagnosticList::from_rojo_diagnostics(cursor, &current)
+                });
+
+                encoding.respond_ok(SubscribeResponse {
                     session_id,
                     message_cursor,
                     messages: api_messages,
+                    diagnostics,
                 })
             }
             Err(_) => json(
@@ -150,7 +244,22 @@ impl ApiService {
         }))
     }
 
-    fn handle_api_write(&self, request: Request<Body>) -> <Self as Service>::Future {
+    /// Get every current error and warning from snapshotting the project's
+    /// files.
+    fn handle_api_diagnostics(&self, encoding: Encoding) -> <Self as Service>::Future {
+        let (cursor, current) = self.serve_session.diagnostics().current();
+
+        encoding.respond_ok(DiagnosticsResponse {
+            session_id: self.serve_session.session_id(),
+            diagnostics: DiagnosticList::from_rojo_diagnostics(cursor, &current),
+        })
+    }
+
+    fn handle_api_write(
+        &self,
+        request: Request<Body>,
+        encoding: Encoding,
+    ) -> <Self as Service>::Future {
         let session_id = self.serve_session.session_id();
         let tree_mutation_sender = self.serve_session.tree_mutation_sender();
 
@@ -172,31 +281,113 @@ impl ApiService {
                 );
             }
 
+            let mut previous_properties = HashMap::new();
+
             let updated_instances = request
                 .updated
                 .into_iter()
-                .map(|update| PatchUpdate {
-                    id: update.id,
-                    changed_class_name: update.changed_class_name,
-                    changed_name: update.changed_name,
-                    changed_properties: update.changed_properties,
-                    changed_metadata: None,
+                .map(|update| {
+                    if !update.previous_properties.is_empty() {
+                        previous_properties.insert(update.id, update.previous_properties);
+                    }
+
+                    PatchUpdate {
+                        id: update.id,
+                        changed_class_name: update.changed_class_name,
+                        changed_name: update.changed_name,
+                        changed_properties: update.changed_properties,
+                        changed_metadata: None,
+                    }
                 })
                 .collect();
 
+            let (response_sender, response_receiver) = oneshot::channel();
+
             tree_mutation_sender
-                .send(PatchSet {
-                    removed_instances: Vec::new(),
-                    added_instances: Vec::new(),
-                    updated_instances,
+                .send(TreeMutation {
+                    patch_set: PatchSet {
+                        removed_instances: Vec::new(),
+                        added_instances: Vec::new(),
+                        updated_instances,
+                        reordered_children: Vec::new(),
+                    },
+                    previous_properties,
+                    response_sender: Some(response_sender),
                 })
                 .unwrap();
 
-            json_ok(&WriteResponse { session_id })
+            Box::new(response_receiver.then(move |result| match result {
+                Ok(Ok(conflicts)) => {
+                    let conflicts = conflicts
+                        .into_iter()
+                        .map(|conflict| WriteConflict {
+                            id: conflict.id,
+                            property: conflict.property,
+                            expected_value: conflict.expected_value,
+                            current_value: conflict.current_value,
+                        })
+                        .collect();
+
+                    encoding.respond_ok(&WriteResponse {
+                        session_id,
+                        conflicts,
+                    })
+                }
+                Ok(Err(err @ TreeMutationError::SyncPaused)) => json(
+                    ErrorResponse::conflict(err.to_string()),
+                    StatusCode::CONFLICT,
+                ),
+                Ok(Err(TreeMutationError::InvalidPatch { source })) => json(
+                    ErrorResponse::bad_request(format!("Invalid patch: {}", source)),
+                    StatusCode::BAD_REQUEST,
+                ),
+                Err(_) => json(
+                    ErrorResponse::internal_error("Change processor dropped the write request"),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ),
+            }))
         }))
     }
 
-    fn handle_api_read(&self, request: Request<Body>) -> <Self as Service>::Future {
+    /// Pauses, resumes, or redoes syncing from the filesystem, responding once
+    /// the change processor has done it.
+    fn handle_api_sync(
+        &self,
+        command: SyncCommand,
+        encoding: Encoding,
+    ) -> <Self as Service>::Future {
+        let (response_sender, response_receiver) = oneshot::channel();
+
+        self.serve_session
+            .sync_control_sender()
+            .send(SyncControl {
+                command,
+                response_sender: Some(response_sender),
+            })
+            .unwrap();
+
+        let serve_session = Arc::clone(&self.serve_session);
+
+        Box::new(response_receiver.then(move |result| match result {
+            Ok(status) => encoding.respond_ok(SyncStatusResponse {
+                session_id: serve_session.session_id(),
+                paused: status.paused,
+                buffered_paths: status.buffered_paths,
+                message_cursor: serve_session.message_queue().cursor(),
+            }),
+            Err(_) => json(
+                ErrorResponse::internal_error("Change processor dropped the sync request"),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        }))
+    }
+
+    fn handle_api_read(
+        &self,
+        request: Request<Body>,
+        protocol: ProtocolVersion,
+        encoding: Encoding,
+    ) -> <Self as Service>::Future {
         let argument = &request.uri().path()["/api/read/".len()..];
         let requested_ids: Option<Vec<RbxId>> = argument.split(',').map(RbxId::parse_str).collect();
 
@@ -210,27 +401,229 @@ impl ApiService {
             }
         };
 
-        let message_queue = self.serve_session.message_queue();
-        let message_cursor = message_queue.cursor();
+        let mut limit = usize::MAX;
+        let mut continuation = None;
+
+        for (name, value) in query_pairs(request.uri().query().unwrap_or("")) {
+            match name {
+                "limit" => match value.parse() {
+                    Ok(0) | Err(_) => {
+                        return json(
+                            ErrorResponse::bad_request("Limit must be a positive integer"),
+                            StatusCode::BAD_REQUEST,
+                        );
+                    }
+                    Ok(v) => limit = v,
+                },
+                "continuation" => match Continuation::parse(value) {
+                    Some(v) => continuation = Some(v),
+                    None => {
+                        return json(
+                            ErrorResponse::bad_request("Malformed continuation token"),
+                            StatusCode::BAD_REQUEST,
+                        );
+                    }
+                },
+                _ => {}
+            }
+        }
 
-        let tree = self.serve_session.tree();
+        if !protocol.supports_paged_reads() && (limit != usize::MAX || continuation.is_some()) {
+            return json(
+                ErrorResponse::bad_request(format!(
+                    "Paged reads need protocol version 4 or newer, but the client uses version {}",
+                    protocol.number()
+                )),
+                StatusCode::BAD_REQUEST,
+            );
+        }
 
-        let mut instances = HashMap::new();
+        // Instances are copied out of the tree so that it can be unlocked
+        // before the response is encoded, which is slow for large reads.
+        let (message_cursor, instances, next_id) = {
+            let tree = self.serve_session.tree();
+            let message_cursor = self.serve_session.message_queue().cursor();
 
-        for id in requested_ids {
-            if let Some(instance) = tree.get_instance(id) {
-                instances.insert(id, Instance::from_rojo_instance(instance));
+            if let Some(continuation) = &continuation {
+                if continuation.message_cursor != message_cursor {
+                    return json(
+                        ErrorResponse::conflict(
+                            "The tree changed since reading began, start reading again",
+                        ),
+                        StatusCode::CONFLICT,
+                    );
+                }
+            }
+
+            let start = continuation.map(|continuation| continuation.next_id);
 
-                for descendant in tree.descendants(id) {
-                    instances.insert(descendant.id(), Instance::from_rojo_instance(descendant));
+            match read_page(&tree, &requested_ids, start, limit) {
+                Some((instances, next_id)) => (message_cursor, instances, next_id),
+                None => {
+                    return json(
+                        ErrorResponse::bad_request(
+                            "Continuation token does not belong to the requested IDs",
+                        ),
+                        StatusCode::BAD_REQUEST,
+                    );
                 }
             }
-        }
+        };
+
+        let continuation = next_id.map(|next_id| {
+            Continuation {
+                message_cursor,
+                next_id,
+            }
+            .to_token()
+        });
 
-        json_ok(ReadResponse {
+        encoding.respond_ok(ReadResponse {
             session_id: self.serve_session.session_id(),
             message_cursor,
             instances,
+            continuation,
+        })
+    }
+}
+
+/// Names the route that a request path belongs to, for grouping metrics.
+/// Paths with IDs or cursors in them are grouped together.
+fn route_name(path: &str) -> &'static str {
+    const ROUTES: &[&str] = &[
+        "/api/rojo",
+        "/api/read",
+        "/api/subscribe",
+        "/api/diagnostics",
+        "/api/write",
+        "/api/pause",
+        "/api/resume",
+        "/api/resync",
+    ];
+
+    ROUTES
+        .iter()
+        .copied()
+        .find(|&route| path == route || path.starts_with(&format!("{}/", route)))
+        .unwrap_or("other")
+}
+
+/// Splits a URL query string into its names and values. Nothing that Rojo
+/// reads from queries needs percent-decoding.
+fn query_pairs(query: &str) -> impl Iterator<Item = (&str, &str)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
+        })
+}
+
+/// Converts a message from the message queue into the form that clients using
+/// the given protocol version expect.
+fn subscribe_message<'a>(
+    tree: &'a RojoTree,
+    message: AppliedPatchSet,
+    protocol: ProtocolVersion,
+) -> SubscribeMessage<'a> {
+    let mut added = HashMap::new();
+    for id in message.added {
+        let instance = tree.get_instance(id).unwrap();
+        added.insert(id, Instance::from_rojo_instance(instance));
+
+        for instance in tree.descendants(id) {
+            added.insert(instance.id(), Instance::from_rojo_instance(instance));
+        }
+    }
+
+    let updated = message
+        .updated
+        .into_iter()
+        .map(|update| {
+            let changed_metadata = update
+                .changed_metadata
+                .as_ref()
+                .map(WebInstanceMetadata::from_rojo_metadata);
+
+            let (changed_properties, patched_properties) = if protocol.supports_text_patches() {
+                patch_string_properties(update.changed_properties, &update.previous_properties)
+            } else {
+                let changed_properties = update
+                    .changed_properties
+                    .into_iter()
+                    .map(|(key, value)| (key, value.map(unshare)))
+                    .collect();
+
+                (changed_properties, HashMap::new())
+            };
+
+            InstanceUpdate {
+                id: update.id,
+                changed_name: update.changed_name,
+                changed_class_name: update.changed_class_name,
+                changed_properties,
+                changed_metadata,
+                patched_properties,
+                previous_properties: HashMap::new(),
+            }
         })
+        .collect();
+
+    let reordered = message
+        .reordered
+        .into_iter()
+        .map(|reorder| InstanceReorder {
+            parent_id: reorder.parent_id,
+            children: reorder.children,
+        })
+        .collect();
+
+    SubscribeMessage {
+        removed: message.removed,
+        added,
+        updated,
+        reordered,
     }
 }
+
+/// Splits changed properties into those sent in full and string properties
+/// that are cheaper to send as a patch against their previous value.
+fn patch_string_properties(
+    changed_properties: HashMap<String, Option<Arc<RbxValue>>>,
+    previous_properties: &HashMap<String, Option<Arc<RbxValue>>>,
+) -> (
+    HashMap<String, Option<RbxValue>>,
+    HashMap<String, TextPatch>,
+) {
+    let mut full = HashMap::new();
+    let mut patched = HashMap::new();
+
+    for (key, value) in changed_properties {
+        let previous_value = previous_properties.get(&key).and_then(Option::as_deref);
+
+        let patch = match (value.as_deref(), previous_value) {
+            (Some(RbxValue::String { value: new }), Some(RbxValue::String { value: old })) => {
+                text_patch(old, new)
+            }
+            _ => None,
+        };
+
+        match patch {
+            Some(patch) => {
+                patched.insert(key, patch);
+            }
+            None => {
+                full.insert(key, value.map(unshare));
+            }
+        }
+    }
+
+    (full, patched)
+}
+
+/// Takes a property value out of an applied patch to send it, which only
+/// copies it if it's shared with other patches.
+fn unshare(value: Arc<RbxValue>) -> RbxValue {
+    Arc::try_unwrap(value).unwrap_or_else(|value| (*value).clone())
+}
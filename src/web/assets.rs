@@ -23,6 +23,7 @@ macro_rules! declare_asset {
 }
 
 declare_asset!(css, "../../assets/index.css");
+declare_asset!(dashboard_js, "../../assets/dashboard.js");
 
 pub fn logo() -> &'static [u8] {
     static LOGO: &[u8] = include_bytes!("../../assets/logo-512.png");
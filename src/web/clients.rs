@@ -0,0 +1,237 @@
+//! Keeps track of the clients using the live-sync API, like Studio sessions
+//! running the Rojo plugin, so that the dashboard can show who's connected.
+//!
+//! HTTP doesn't have a notion of a client beyond a single connection, so
+//! clients are told apart by their IP address and the place ID they send.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::sync::oneshot;
+use hyper::Request;
+use serde::Serialize;
+
+use crate::message_queue::MessageQueue;
+
+use super::interface::PLACE_ID_HEADER;
+
+/// Clients that haven't made a request in this long and aren't waiting on
+/// one are considered to have gone away.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientKey {
+    address: Option<IpAddr>,
+    place_id: Option<u64>,
+}
+
+#[derive(Debug)]
+struct ClientState {
+    protocol_version: u64,
+    first_seen: Instant,
+    last_seen: Instant,
+
+    /// How many subscribe requests from this client are waiting for messages.
+    /// Plugins always have one open while they're connected.
+    waiting_subscriptions: usize,
+}
+
+/// What the dashboard shows about each client.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientInfo {
+    pub address: Option<String>,
+    pub place_id: Option<u64>,
+    pub protocol_version: u64,
+    pub connected_seconds: u64,
+    pub idle_seconds: u64,
+    pub subscribed: bool,
+}
+
+pub struct Clients {
+    clients: Mutex<HashMap<ClientKey, ClientState>>,
+
+    /// Gets an empty message whenever a client shows up or starts or stops
+    /// waiting for messages.
+    changes: MessageQueue<()>,
+}
+
+impl Clients {
+    pub fn new() -> Self {
+        Clients {
+            clients: Mutex::new(HashMap::new()),
+            changes: MessageQueue::new(),
+        }
+    }
+
+    /// Records a request from the client at the given address.
+    pub fn record_request<B>(
+        &self,
+        request: &Request<B>,
+        address: Option<IpAddr>,
+        protocol_version: u64,
+    ) -> ClientKey {
+        let place_id = request
+            .headers()
+            .get(PLACE_ID_HEADER)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|value| value.trim().parse().ok());
+
+        let key = ClientKey { address, place_id };
+        let now = Instant::now();
+
+        let mut clients = self.clients.lock().unwrap();
+
+        match clients.get_mut(&key) {
+            Some(client) => {
+                client.protocol_version = protocol_version;
+                client.last_seen = now;
+            }
+            None => {
+                clients.insert(
+                    key,
+                    ClientState {
+                        protocol_version,
+                        first_seen: now,
+                        last_seen: now,
+                        waiting_subscriptions: 0,
+                    },
+                );
+
+                self.changes.push_messages(&[()]);
+            }
+        }
+
+        key
+    }
+
+    /// Marks the client as waiting for messages until the returned guard is
+    /// dropped, which happens when its subscribe request finishes or the
+    /// connection it was made on closes.
+    pub fn wait_for_messages(self: &Arc<Self>, key: ClientKey) -> WaitingGuard {
+        self.update_waiting(key, |waiting| *waiting += 1);
+
+        WaitingGuard {
+            clients: Arc::clone(self),
+            key,
+        }
+    }
+
+    fn update_waiting(&self, key: ClientKey, update: impl FnOnce(&mut usize)) {
+        let mut clients = self.clients.lock().unwrap();
+
+        if let Some(client) = clients.get_mut(&key) {
+            let was_waiting = client.waiting_subscriptions > 0;
+            update(&mut client.waiting_subscriptions);
+            client.last_seen = Instant::now();
+
+            if was_waiting != (client.waiting_subscriptions > 0) {
+                self.changes.push_messages(&[()]);
+            }
+        }
+    }
+
+    /// Returns the clients that are still around, along with a cursor that
+    /// can be passed to `subscribe` to wait for them to change.
+    pub fn list(&self) -> (u32, Vec<ClientInfo>) {
+        let mut clients = self.clients.lock().unwrap();
+        let now = Instant::now();
+
+        clients.retain(|_, client| {
+            client.waiting_subscriptions > 0 || now - client.last_seen < CLIENT_TIMEOUT
+        });
+
+        let mut list: Vec<_> = clients
+            .iter()
+            .map(|(key, client)| ClientInfo {
+                address: key.address.map(|address| address.to_string()),
+                place_id: key.place_id,
+                protocol_version: client.protocol_version,
+                connected_seconds: (now - client.first_seen).as_secs(),
+                idle_seconds: (now - client.last_seen).as_secs(),
+                subscribed: client.waiting_subscriptions > 0,
+            })
+            .collect();
+
+        list.sort_by_key(|client| std::cmp::Reverse(client.connected_seconds));
+
+        (self.changes.cursor(), list)
+    }
+
+    /// Counts the subscribe requests that are waiting for messages, across
+    /// every client.
+    pub fn waiting_subscriptions(&self) -> usize {
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .map(|client| client.waiting_subscriptions)
+            .sum()
+    }
+
+    /// Resolves once clients have changed since the given cursor.
+    pub fn subscribe(&self, cursor: u32) -> oneshot::Receiver<(u32, Vec<()>)> {
+        self.changes.subscribe(cursor)
+    }
+}
+
+pub struct WaitingGuard {
+    clients: Arc<Clients>,
+    key: ClientKey,
+}
+
+impl Drop for WaitingGuard {
+    fn drop(&mut self) {
+        self.clients
+            .update_waiting(self.key, |waiting| *waiting = waiting.saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(place_id: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder();
+
+        if let Some(place_id) = place_id {
+            builder.header(PLACE_ID_HEADER, place_id);
+        }
+
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn track_waiting_clients() {
+        let clients = Arc::new(Clients::new());
+        let address = Some("127.0.0.1".parse().unwrap());
+
+        let studio = clients.record_request(&request(Some("123")), address, 4);
+        clients.record_request(&request(Some("123")), address, 4);
+        clients.record_request(&request(None), address, 3);
+
+        let (cursor, list) = clients.list();
+        assert_eq!(cursor, 2);
+        assert_eq!(list.len(), 2);
+
+        let guard = clients.wait_for_messages(studio);
+        let (cursor, list) = clients.list();
+        assert_eq!(cursor, 3);
+        assert_eq!(
+            list.iter()
+                .filter(|client| client.subscribed)
+                .map(|client| client.place_id)
+                .collect::<Vec<_>>(),
+            vec![Some(123)]
+        );
+
+        drop(guard);
+        let (cursor, list) = clients.list();
+        assert_eq!(cursor, 4);
+        assert!(list.iter().all(|client| !client.subscribed));
+    }
+}
@@ -0,0 +1,448 @@
+//! The data behind the live dashboard: a stream of server-sent events that
+//! tells the page what changed, and JSON endpoints for the instance tree.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures::{future, stream, Future, Stream};
+use hyper::{header, Body, Response, StatusCode};
+use rbx_dom_weak::{RbxId, RbxValue};
+use serde::Serialize;
+use tokio::timer::Delay;
+
+use crate::{
+    serve_session::ServeSession,
+    snapshot::{AppliedPatchSet, InstigatingSource, RojoTree},
+};
+
+use super::{
+    clients::{ClientInfo, Clients},
+    interface::{DiagnosticList, ErrorResponse},
+    util::json,
+};
+
+/// How often the event stream sends something even if nothing happened. This
+/// keeps proxies from closing the connection and refreshes how long clients
+/// have been idle.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+type ResponseFuture = Box<dyn Future<Item = Response<Body>, Error = hyper::Error> + Send>;
+
+type EventFuture = Box<dyn Future<Item = Wakeup, Error = io::Error> + Send>;
+
+/// Why the event stream woke up.
+enum Wakeup {
+    Patches(u32, Vec<AppliedPatchSet>),
+    Diagnostics,
+    Clients,
+    Keepalive,
+}
+
+struct EventSource {
+    serve_session: Arc<ServeSession>,
+    clients: Arc<Clients>,
+
+    /// What the stream is waiting on. Each source of events always has
+    /// exactly one future in here, which is replaced once it fires.
+    waiting: Vec<EventFuture>,
+}
+
+impl EventSource {
+    fn wait_for_patches(&self, cursor: u32) -> EventFuture {
+        Box::new(
+            self.serve_session
+                .message_queue()
+                .subscribe(cursor)
+                .map(|(cursor, patches)| Wakeup::Patches(cursor, patches))
+                .map_err(|_| disconnected()),
+        )
+    }
+
+    fn wait_for_diagnostics(&self, cursor: u32) -> EventFuture {
+        Box::new(
+            self.serve_session
+                .diagnostics()
+                .subscribe(cursor)
+                .map(|_| Wakeup::Diagnostics)
+                .map_err(|_| disconnected()),
+        )
+    }
+
+    fn wait_for_clients(&self, cursor: u32) -> EventFuture {
+        Box::new(
+            self.clients
+                .subscribe(cursor)
+                .map(|_| Wakeup::Clients)
+                .map_err(|_| disconnected()),
+        )
+    }
+
+    fn wait_for_keepalive(&self) -> EventFuture {
+        Box::new(
+            Delay::new(Instant::now() + KEEPALIVE_INTERVAL)
+                .map(|_| Wakeup::Keepalive)
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err)),
+        )
+    }
+
+    fn diagnostics_event(&self) -> (u32, String) {
+        let (cursor, current) = self.serve_session.diagnostics().current();
+        let list = DiagnosticList::from_rojo_diagnostics(cursor, &current);
+
+        (cursor, event("diagnostics", &list))
+    }
+
+    fn clients_event(&self) -> (u32, String) {
+        let (cursor, clients) = self.clients.list();
+
+        (cursor, event("clients", &ClientsEvent { clients }))
+    }
+
+    /// Waits for the next thing to happen, and returns the events to send
+    /// for it.
+    fn next(mut self) -> impl Future<Item = (String, Self), Error = io::Error> {
+        let waiting = std::mem::take(&mut self.waiting);
+
+        future::select_all(waiting)
+            .map_err(|(err, _, _)| err)
+            .map(move |(wakeup, _, rest)| {
+                self.waiting = rest;
+
+                let output = match wakeup {
+                    Wakeup::Patches(cursor, patches) => {
+                        let output = {
+                            let tree = self.serve_session.tree();
+
+                            patches
+                                .iter()
+                                .map(|patch| event("patch", &PatchEvent::new(&tree, patch)))
+                                .collect()
+                        };
+
+                        let future = self.wait_for_patches(cursor);
+                        self.waiting.push(future);
+                        output
+                    }
+                    Wakeup::Diagnostics => {
+                        let (cursor, output) = self.diagnostics_event();
+                        let future = self.wait_for_diagnostics(cursor);
+                        self.waiting.push(future);
+                        output
+                    }
+                    Wakeup::Clients => {
+                        let (cursor, output) = self.clients_event();
+                        let future = self.wait_for_clients(cursor);
+                        self.waiting.push(future);
+                        output
+                    }
+                    Wakeup::Keepalive => {
+                        // Clients that time out don't trigger a change, so the
+                        // list is sent again to keep the page up to date.
+                        let (_, output) = self.clients_event();
+                        let future = self.wait_for_keepalive();
+                        self.waiting.push(future);
+                        output
+                    }
+                };
+
+                (output, self)
+            })
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "Message queue disconnected sender",
+    )
+}
+
+/// Formats a server-sent event with the given type and JSON data.
+fn event<T: Serialize>(name: &str, data: &T) -> String {
+    // serde_json never writes a raw newline, so the data always fits on the
+    // single line that an event's data needs to.
+    format!(
+        "event: {}\ndata: {}\n\n",
+        name,
+        serde_json::to_string(data).unwrap()
+    )
+}
+
+/// Streams server-sent events for as long as the page is open.
+///
+/// The stream starts with the current diagnostics and clients, and then sends
+/// an event for each patch applied to the tree, and whenever the diagnostics
+/// or clients change. Pages should load the tree after the stream opens, since
+/// any patch applied after it opens will be sent.
+pub fn events(serve_session: Arc<ServeSession>, clients: Arc<Clients>) -> Response<Body> {
+    let message_cursor = serve_session.message_queue().cursor();
+
+    let mut source = EventSource {
+        serve_session,
+        clients,
+        waiting: Vec::new(),
+    };
+
+    let (diagnostics_cursor, diagnostics_event) = source.diagnostics_event();
+    let (clients_cursor, clients_event) = source.clients_event();
+
+    source.waiting = vec![
+        source.wait_for_patches(message_cursor),
+        source.wait_for_diagnostics(diagnostics_cursor),
+        source.wait_for_clients(clients_cursor),
+        source.wait_for_keepalive(),
+    ];
+
+    let initial = stream::once(Ok(diagnostics_event + &clients_event));
+    let updates = stream::unfold(source, |source| Some(source.next()));
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::wrap_stream(initial.chain(updates)))
+        .unwrap()
+}
+
+#[derive(Serialize)]
+struct ClientsEvent {
+    clients: Vec<ClientInfo>,
+}
+
+/// A summary of a patch for the dashboard's feed. It only says what changed,
+/// not what it changed to, since the inspector can load the instance itself.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PatchEvent {
+    added: Vec<InstanceSummary>,
+    removed: Vec<RbxId>,
+    updated: Vec<UpdateSummary>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceSummary {
+    id: RbxId,
+    name: String,
+    class_name: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateSummary {
+    id: RbxId,
+    name: Option<String>,
+    changed_name: bool,
+    changed_class_name: bool,
+    changed_properties: Vec<String>,
+}
+
+impl PatchEvent {
+    fn new(tree: &RojoTree, patch: &AppliedPatchSet) -> Self {
+        // Instances added by this patch might have been removed by a later
+        // one, in which case there's nothing to say about them anymore.
+        let added = patch
+            .added
+            .iter()
+            .filter_map(|&id| tree.get_instance(id))
+            .map(|instance| InstanceSummary {
+                id: instance.id(),
+                name: instance.name().to_owned(),
+                class_name: instance.class_name().to_owned(),
+            })
+            .collect();
+
+        let updated = patch
+            .updated
+            .iter()
+            .map(|update| {
+                let mut changed_properties: Vec<_> =
+                    update.changed_properties.keys().cloned().collect();
+                changed_properties.sort();
+
+                UpdateSummary {
+                    id: update.id,
+                    name: tree
+                        .get_instance(update.id)
+                        .map(|instance| instance.name().to_owned()),
+                    changed_name: update.changed_name.is_some(),
+                    changed_class_name: update.changed_class_name.is_some(),
+                    changed_properties,
+                }
+            })
+            .collect();
+
+        PatchEvent {
+            added,
+            removed: patch.removed.clone(),
+            updated,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TreeResponse {
+    root_id: RbxId,
+    instances: HashMap<RbxId, TreeInstance>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TreeInstance {
+    name: String,
+    class_name: String,
+    parent: Option<RbxId>,
+    children: Vec<RbxId>,
+}
+
+/// Lists every instance in the tree with just enough information to draw it
+/// and search through it.
+pub fn tree(serve_session: &ServeSession) -> ResponseFuture {
+    let tree = serve_session.tree();
+    let root_id = tree.get_root_id();
+
+    // `descendants` doesn't include the instance it starts from.
+    let instances = tree
+        .get_instance(root_id)
+        .into_iter()
+        .chain(tree.descendants(root_id))
+        .map(|instance| {
+            let data = TreeInstance {
+                name: instance.name().to_owned(),
+                class_name: instance.class_name().to_owned(),
+                parent: instance.parent(),
+                children: instance.children().to_vec(),
+            };
+
+            (instance.id(), data)
+        })
+        .collect();
+
+    json(TreeResponse { root_id, instances }, StatusCode::OK)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceResponse {
+    id: RbxId,
+    name: String,
+    class_name: String,
+    properties: Vec<PropertySummary>,
+    source: Option<String>,
+
+    /// Set when the source belongs to an ancestor, like for instances that
+    /// come from a model file.
+    source_instance: Option<RbxId>,
+    relevant_paths: Vec<String>,
+    ignore_unknown_instances: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PropertySummary {
+    name: String,
+    ty: String,
+    value: String,
+}
+
+/// Describes a single instance for the property inspector.
+pub fn instance(serve_session: &ServeSession, argument: &str) -> ResponseFuture {
+    let id = match RbxId::parse_str(argument) {
+        Some(id) => id,
+        None => {
+            return json(
+                ErrorResponse::bad_request(format!("Malformed instance ID: {}", argument)),
+                StatusCode::BAD_REQUEST,
+            )
+        }
+    };
+
+    let tree = serve_session.tree();
+
+    let instance = match tree.get_instance(id) {
+        Some(instance) => instance,
+        None => {
+            return json(
+                ErrorResponse::not_found(format!("No instance with ID {}", id)),
+                StatusCode::NOT_FOUND,
+            )
+        }
+    };
+
+    let mut properties: Vec<_> = instance
+        .properties()
+        .iter()
+        .map(|(name, value)| PropertySummary {
+            name: name.clone(),
+            ty: format!("{:?}", value.get_type()),
+            value: display_value(value),
+        })
+        .collect();
+    properties.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let (source, source_instance) = match find_source(&tree, id) {
+        Some((source_id, source)) => (
+            Some(source),
+            if source_id == id {
+                None
+            } else {
+                Some(source_id)
+            },
+        ),
+        None => (None, None),
+    };
+
+    let metadata = instance.metadata();
+
+    let response = InstanceResponse {
+        id,
+        name: instance.name().to_owned(),
+        class_name: instance.class_name().to_owned(),
+        properties,
+        source,
+        source_instance,
+        relevant_paths: metadata
+            .relevant_paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+        ignore_unknown_instances: metadata.ignore_unknown_instances,
+    };
+
+    json(response, StatusCode::OK)
+}
+
+/// Finds where an instance came from, looking through its ancestors if it
+/// doesn't have a source of its own.
+fn find_source(tree: &RojoTree, id: RbxId) -> Option<(RbxId, String)> {
+    let mut current = tree.get_instance(id)?;
+
+    loop {
+        match &current.metadata().instigating_source {
+            Some(InstigatingSource::Path(path)) => {
+                return Some((current.id(), path.display().to_string()))
+            }
+            Some(InstigatingSource::ProjectNode(project_folder, name, node)) => {
+                let source = match &node.path {
+                    Some(path) => project_folder.join(path).display().to_string(),
+                    None => format!("project node \"{}\" in {}", name, project_folder.display()),
+                };
+
+                return Some((current.id(), source));
+            }
+            None => current = tree.get_instance(current.parent()?)?,
+        }
+    }
+}
+
+fn display_value(value: &RbxValue) -> String {
+    match value {
+        RbxValue::String { value } => value.clone(),
+        RbxValue::Bool { value } => value.to_string(),
+        _ => format!("{:?}", value),
+    }
+}
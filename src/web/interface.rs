@@ -9,6 +9,7 @@ use rbx_dom_weak::{RbxId, RbxValue};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    diagnostics::{Diagnostic as RojoDiagnostic, Severity as RojoSeverity},
     session_id::SessionId,
     snapshot::{InstanceMetadata as RojoInstanceMetadata, InstanceWithMeta},
 };
@@ -16,8 +17,24 @@ use crate::{
 /// Server version to report over the API, not exposed outside this crate.
 pub(crate) const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-/// Current protocol version, which is required to match.
-pub const PROTOCOL_VERSION: u64 = 3;
+/// Newest protocol version that the server supports.
+pub const PROTOCOL_VERSION: u64 = 4;
+
+/// Oldest protocol version that the server still supports.
+pub const MIN_PROTOCOL_VERSION: u64 = 3;
+
+/// Protocol version used by clients that don't send `PROTOCOL_VERSION_HEADER`,
+/// which is the version every client spoke before versions were negotiated.
+pub const LEGACY_PROTOCOL_VERSION: u64 = 3;
+
+/// Header that clients send with each request to pick the protocol version
+/// that the server should use when responding to it.
+pub const PROTOCOL_VERSION_HEADER: &str = "Rojo-Protocol-Version";
+
+/// Header that clients send with each request to say which place they're
+/// syncing into. Projects that set `servePlaceIds` only accept requests with
+/// one of those places.
+pub const PLACE_ID_HEADER: &str = "Rojo-Place-Id";
 
 /// Message returned by Rojo API when a change has occurred.
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,6 +43,17 @@ pub struct SubscribeMessage<'a> {
     pub removed: Vec<RbxId>,
     pub added: HashMap<RbxId, Instance<'a>>,
     pub updated: Vec<InstanceUpdate>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reordered: Vec<InstanceReorder>,
+}
+
+/// Describes the new order of an instance's children.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceReorder {
+    pub parent_id: RbxId,
+    pub children: Vec<RbxId>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,6 +68,43 @@ pub struct InstanceUpdate {
     #[serde(default)]
     pub changed_properties: HashMap<String, Option<RbxValue>>,
     pub changed_metadata: Option<InstanceMetadata>,
+
+    /// String properties that changed, described as edits to their previous
+    /// value instead of in full. Properties are in either this map or
+    /// `changed_properties`, never both.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub patched_properties: HashMap<String, TextPatch>,
+
+    /// In write requests, the values that the client last saw for the
+    /// properties it's changing. Used to detect conflicting changes.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub previous_properties: HashMap<String, Option<RbxValue>>,
+}
+
+/// A change to a string property, as a list of edits to apply to its previous
+/// value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextPatch {
+    /// The length in bytes of the value that the edits apply to, which clients
+    /// can check to make sure they have the same previous value as the server.
+    pub base_length: usize,
+
+    /// The 32-bit FNV-1a hash of the value that the edits apply to. Values with
+    /// the same length can still differ, so clients check this too.
+    pub base_hash: u32,
+
+    /// Edits to apply, sorted by where they start and never overlapping.
+    /// Offsets are all relative to the previous value.
+    pub edits: Vec<TextEdit>,
+}
+
+/// Replaces `length` bytes of a string starting at byte `start` with `text`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextEdit {
+    pub start: usize,
+    pub length: usize,
+    pub text: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -80,6 +145,20 @@ impl<'a> Instance<'a> {
             metadata: Some(InstanceMetadata::from_rojo_metadata(source.metadata())),
         }
     }
+
+    /// Copies any borrowed data so that the instance can outlive the tree it
+    /// came from.
+    pub(crate) fn into_owned(self) -> Instance<'static> {
+        Instance {
+            id: self.id,
+            parent: self.parent,
+            name: Cow::Owned(self.name.into_owned()),
+            class_name: Cow::Owned(self.class_name.into_owned()),
+            properties: Cow::Owned(self.properties.into_owned()),
+            children: Cow::Owned(self.children.into_owned()),
+            metadata: self.metadata,
+        }
+    }
 }
 
 /// Response body from /api/rojo
@@ -88,9 +167,23 @@ impl<'a> Instance<'a> {
 pub struct ServerInfoResponse {
     pub session_id: SessionId,
     pub server_version: String,
+    /// The protocol version that the server used for this request.
     pub protocol_version: u64,
+
+    /// The oldest and newest protocol versions that the server supports.
+    pub min_protocol_version: u64,
+    pub max_protocol_version: u64,
+
     pub expected_place_ids: Option<HashSet<u64>>,
     pub root_instance_id: RbxId,
+
+    /// Media types that clients can ask for in their `Accept` header, which
+    /// responses are encoded as instead of JSON.
+    pub response_formats: Vec<String>,
+
+    /// Content codings that clients can ask for in their `Accept-Encoding`
+    /// header, which responses are compressed with.
+    pub content_encodings: Vec<String>,
 }
 
 /// Response body from /api/read/{id}
@@ -100,6 +193,11 @@ pub struct ReadResponse<'a> {
     pub session_id: SessionId,
     pub message_cursor: u32,
     pub instances: HashMap<RbxId, Instance<'a>>,
+
+    /// Set when the read was limited and there are more instances to read,
+    /// which can be requested by passing this back as `continuation`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continuation: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -117,6 +215,20 @@ pub struct WriteRequest {
 #[serde(rename_all = "camelCase")]
 pub struct WriteResponse {
     pub session_id: SessionId,
+
+    /// Changes that weren't applied because the property had been changed by
+    /// someone else since the client last saw it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<WriteConflict>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WriteConflict {
+    pub id: RbxId,
+    pub property: String,
+    pub expected_value: Option<RbxValue>,
+    pub current_value: Option<RbxValue>,
 }
 
 /// Response body from /api/subscribe/{cursor}
@@ -126,6 +238,79 @@ pub struct SubscribeResponse<'a> {
     pub session_id: SessionId,
     pub message_cursor: u32,
     pub messages: Vec<SubscribeMessage<'a>>,
+
+    /// The current diagnostics, included if the client asked to be told
+    /// about them by passing a `diagnostics` cursor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<DiagnosticList>,
+}
+
+/// Response body from /api/diagnostics
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsResponse {
+    pub session_id: SessionId,
+
+    #[serde(flatten)]
+    pub diagnostics: DiagnosticList,
+}
+
+/// Response body from /api/pause, /api/resume, and /api/resync
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncStatusResponse {
+    pub session_id: SessionId,
+    pub paused: bool,
+
+    /// How many changed paths will be synced once syncing resumes.
+    pub buffered_paths: usize,
+
+    /// The message cursor once the command was done, which includes any patch
+    /// it produced.
+    pub message_cursor: u32,
+}
+
+/// Every current problem with the project's files, along with a cursor that
+/// can be passed to /api/subscribe to wait for them to change.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticList {
+    pub cursor: u32,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticList {
+    pub(crate) fn from_rojo_diagnostics(cursor: u32, diagnostics: &[RojoDiagnostic]) -> Self {
+        Self {
+            cursor,
+            diagnostics: diagnostics
+                .iter()
+                .map(|diagnostic| Diagnostic {
+                    severity: match diagnostic.severity {
+                        RojoSeverity::Error => DiagnosticSeverity::Error,
+                        RojoSeverity::Warning => DiagnosticSeverity::Warning,
+                    },
+                    path: diagnostic.path.display().to_string(),
+                    message: diagnostic.message.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An error or warning from snapshotting a file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub severity: DiagnosticSeverity,
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
 }
 
 /// General response type returned from all Rojo routes
@@ -157,6 +342,34 @@ impl ErrorResponse {
             details: details.into(),
         }
     }
+
+    pub fn unauthorized<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::Unauthorized,
+            details: details.into(),
+        }
+    }
+
+    pub fn forbidden<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::Forbidden,
+            details: details.into(),
+        }
+    }
+
+    pub fn conflict<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::Conflict,
+            details: details.into(),
+        }
+    }
+
+    pub fn unsupported_protocol_version<S: Into<String>>(details: S) -> Self {
+        Self {
+            kind: ErrorResponseKind::UnsupportedProtocolVersion,
+            details: details.into(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -164,4 +377,8 @@ pub enum ErrorResponseKind {
     NotFound,
     BadRequest,
     InternalError,
+    Unauthorized,
+    Forbidden,
+    Conflict,
+    UnsupportedProtocolVersion,
 }
@@ -1,25 +1,53 @@
+mod access;
 mod api;
 mod assets;
+mod clients;
+mod dashboard;
 pub mod interface;
+mod protocol;
+mod read_page;
+mod text_patch;
+#[cfg(feature = "tls")]
+mod tls;
 mod ui;
 mod util;
 
-use std::sync::Arc;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use futures::{
     future::{self, FutureResult},
     Future,
 };
-use hyper::{service::Service, Body, Request, Response, Server};
+use hyper::{
+    header,
+    server::conn::AddrStream,
+    service::{make_service_fn, Service},
+    Body, Method, Request, Response, Server, StatusCode,
+};
 use log::trace;
 
-use crate::serve_session::ServeSession;
+use crate::{metrics, serve_session::ServeSession};
 
-use self::{api::ApiService, ui::UiService};
+use self::{
+    api::ApiService, clients::Clients, interface::ErrorResponse, ui::UiService, util::json,
+};
+
+#[cfg(feature = "tls")]
+pub use self::tls::{TlsConfig, TlsError};
 
 pub struct RootService {
     api: ApiService,
     ui: UiService,
+    serve_session: Arc<ServeSession>,
+
+    /// If set, every request needs to include this token, either as a bearer
+    /// token or in the cookie that browsers are given with `?token=`.
+    auth_token: Option<Arc<str>>,
+
+    clients: Arc<Clients>,
 }
 
 impl Service for RootService {
@@ -31,7 +59,29 @@ impl Service for RootService {
     fn call(&mut self, request: Request<Self::ReqBody>) -> Self::Future {
         trace!("{} {}", request.method(), request.uri().path());
 
-        if request.uri().path().starts_with("/api") {
+        if let Err(message) = access::check_host_and_origin(&request) {
+            log::warn!("Rejected request: {}", message);
+            return json(ErrorResponse::forbidden(message), StatusCode::FORBIDDEN);
+        }
+
+        if let Some(token) = &self.auth_token {
+            if !access::has_token(&request, token) {
+                if request.method() == Method::GET
+                    && access::query_token(&request) == Some(&**token)
+                {
+                    return Box::new(future::ok(set_token_cookie(&request, token)));
+                }
+
+                return json(
+                    ErrorResponse::unauthorized("Missing or incorrect authentication token"),
+                    StatusCode::UNAUTHORIZED,
+                );
+            }
+        }
+
+        if request.uri().path() == "/metrics" {
+            Box::new(future::ok(self.handle_metrics()))
+        } else if request.uri().path().starts_with("/api") {
             self.api.call(request)
         } else {
             self.ui.call(request)
@@ -40,34 +90,269 @@ impl Service for RootService {
 }
 
 impl RootService {
-    pub fn new(serve_session: Arc<ServeSession>) -> Self {
+    pub fn new(
+        serve_session: Arc<ServeSession>,
+        auth_token: Option<Arc<str>>,
+        clients: Arc<Clients>,
+        remote_address: Option<IpAddr>,
+    ) -> Self {
         RootService {
-            api: ApiService::new(Arc::clone(&serve_session)),
-            ui: UiService::new(Arc::clone(&serve_session)),
+            api: ApiService::new(
+                Arc::clone(&serve_session),
+                Arc::clone(&clients),
+                remote_address,
+            ),
+            ui: UiService::new(Arc::clone(&serve_session), Arc::clone(&clients)),
+            serve_session,
+            auth_token,
+            clients,
         }
     }
+
+    /// Serves metrics in Prometheus' text format.
+    fn handle_metrics(&self) -> Response<Body> {
+        let mut output = String::new();
+        metrics::render(&mut output);
+
+        metrics::render_gauge(
+            &mut output,
+            "rojo_message_queue_length",
+            "Patches pushed to the message queue since the session started.",
+            u64::from(self.serve_session.message_queue().cursor()),
+        );
+
+        metrics::render_gauge(
+            &mut output,
+            "rojo_active_subscribers",
+            "Subscribe requests waiting for messages.",
+            self.clients.waiting_subscriptions() as u64,
+        );
+
+        Response::builder()
+            .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(output))
+            .unwrap()
+    }
+}
+
+/// Stores the token from the query string in a cookie and sends the browser
+/// back to the same page without it, so that the token doesn't stay in the
+/// address bar or the history and the page's own requests carry it too.
+fn set_token_cookie<B>(request: &Request<B>, token: &str) -> Response<Body> {
+    let query: Vec<_> = request
+        .uri()
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with("token="))
+        .collect();
+
+    let mut location = request.uri().path().to_owned();
+    if !query.is_empty() {
+        location.push('?');
+        location.push_str(&query.join("&"));
+    }
+
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(header::LOCATION, location)
+        .header(
+            header::SET_COOKIE,
+            format!(
+                "{}={}; HttpOnly; SameSite=Strict; Path=/",
+                access::TOKEN_COOKIE,
+                token
+            ),
+        )
+        .body(Body::empty())
+        .unwrap()
 }
 
 pub struct LiveServer {
     serve_session: Arc<ServeSession>,
+    auth_token: Option<Arc<str>>,
+
+    #[cfg(feature = "tls")]
+    tls: Option<TlsConfig>,
+
+    /// Shared by every connection, since clients usually make more than one.
+    clients: Arc<Clients>,
 }
 
 impl LiveServer {
     pub fn new(serve_session: Arc<ServeSession>) -> Self {
-        LiveServer { serve_session }
+        LiveServer {
+            serve_session,
+            auth_token: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            clients: Arc::new(Clients::new()),
+        }
     }
 
-    pub fn start(self, port: u16) {
-        let address = ([127, 0, 0, 1], port).into();
+    /// Requires every request to include the given token, either as a bearer
+    /// token in its `Authorization` header or, for browsers, in a cookie that
+    /// is set by visiting any page with `?token=<token>`.
+    pub fn auth_token(self, auth_token: Option<String>) -> Self {
+        LiveServer {
+            auth_token: auth_token.map(Into::into),
+            ..self
+        }
+    }
+
+    /// Serves HTTPS using the given certificate instead of plain HTTP.
+    #[cfg(feature = "tls")]
+    pub fn tls(self, tls: Option<TlsConfig>) -> Self {
+        LiveServer { tls, ..self }
+    }
+
+    pub fn start(self, address: SocketAddr) {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(tls) = self.tls.clone() {
+                self.start_tls(address, tls);
+                return;
+            }
+        }
 
         let server = Server::bind(&address)
-            .serve(move || {
-                let service: FutureResult<_, hyper::Error> =
-                    future::ok(RootService::new(Arc::clone(&self.serve_session)));
+            .serve(make_service_fn(move |socket: &AddrStream| {
+                let service: FutureResult<_, hyper::Error> = future::ok(RootService::new(
+                    Arc::clone(&self.serve_session),
+                    self.auth_token.clone(),
+                    Arc::clone(&self.clients),
+                    Some(socket.remote_addr().ip()),
+                ));
                 service
-            })
+            }))
             .map_err(|e| eprintln!("Server error: {}", e));
 
         hyper::rt::run(server);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, Vfs, VfsSnapshot};
+
+    fn root_service(auth_token: Option<&str>) -> (RootService, Arc<ServeSession>) {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/foo", VfsSnapshot::empty_dir())
+            .unwrap();
+
+        let serve_session = Arc::new(ServeSession::new(Vfs::new(imfs), "/foo"));
+        let service = RootService::new(
+            Arc::clone(&serve_session),
+            auth_token.map(Into::into),
+            Arc::new(Clients::new()),
+            None,
+        );
+
+        (service, serve_session)
+    }
+
+    fn get(service: &mut RootService, path: &str, token: Option<&str>) -> StatusCode {
+        let mut builder = Request::builder();
+        builder.uri(path).header(header::HOST, "localhost:34872");
+
+        if let Some(token) = token {
+            builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        let request = builder.body(Body::empty()).unwrap();
+        service.call(request).wait().unwrap().status()
+    }
+
+    /// Makes a request the way a browser would, with whatever cookies it has.
+    fn browse(service: &mut RootService, path: &str, cookie: Option<&str>) -> Response<Body> {
+        let mut builder = Request::builder();
+        builder.uri(path).header(header::HOST, "localhost:34872");
+
+        if let Some(cookie) = cookie {
+            builder.header(header::COOKIE, cookie);
+        }
+
+        let request = builder.body(Body::empty()).unwrap();
+        service.call(request).wait().unwrap()
+    }
+
+    #[test]
+    fn dashboard_needs_token() {
+        let (mut service, serve_session) = root_service(Some("secret"));
+        let root_id = serve_session.tree().get_root_id();
+        let path = format!("/dashboard/instance/{}", root_id);
+
+        assert_eq!(get(&mut service, &path, None), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            get(&mut service, &path, Some("wrong")),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(get(&mut service, &path, Some("secret")), StatusCode::OK);
+    }
+
+    #[test]
+    fn browser_gets_token_cookie() {
+        let (mut service, _serve_session) = root_service(Some("secret"));
+
+        let response = browse(&mut service, "/?token=wrong", None);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = browse(&mut service, "/?token=secret", None);
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/");
+
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.contains("HttpOnly"));
+        assert!(set_cookie.contains("SameSite=Strict"));
+
+        // Browsers send back only the name and value of the cookie.
+        let cookie = set_cookie.split(';').next().unwrap();
+
+        for path in &["/", "/dashboard", "/dashboard/events"] {
+            assert_eq!(
+                browse(&mut service, path, None).status(),
+                StatusCode::UNAUTHORIZED
+            );
+            assert_eq!(
+                browse(&mut service, path, Some(cookie)).status(),
+                StatusCode::OK
+            );
+        }
+
+        let response = browse(&mut service, "/dashboard?token=secret&tab=patches", None);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/dashboard?tab=patches"
+        );
+
+        assert_eq!(
+            browse(&mut service, "/", Some("rojo_token=wrong")).status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[test]
+    fn metrics_needs_token() {
+        let (mut service, _serve_session) = root_service(Some("secret"));
+
+        assert_eq!(
+            get(&mut service, "/metrics", None),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get(&mut service, "/metrics", Some("secret")),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn no_token_configured() {
+        let (mut service, serve_session) = root_service(None);
+        let root_id = serve_session.tree().get_root_id();
+        let path = format!("/dashboard/instance/{}", root_id);
+
+        assert_eq!(get(&mut service, &path, None), StatusCode::OK);
+    }
+}
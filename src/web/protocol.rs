@@ -0,0 +1,106 @@
+//! Works out which version of the protocol each request uses, so that the
+//! server can keep talking to plugins that are a version behind or ahead of it.
+
+use hyper::Request;
+
+use super::interface::{
+    ErrorResponse, LEGACY_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    PROTOCOL_VERSION_HEADER, SERVER_VERSION,
+};
+
+/// A protocol version that the server supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtocolVersion(u64);
+
+impl ProtocolVersion {
+    /// Reads the protocol version from the request's headers. Clients that
+    /// were released before the version was negotiated don't send it, and all
+    /// speak `LEGACY_PROTOCOL_VERSION`.
+    pub fn negotiate<B>(request: &Request<B>) -> Result<Self, ErrorResponse> {
+        let header = match request.headers().get(PROTOCOL_VERSION_HEADER) {
+            Some(header) => header,
+            None => return Ok(ProtocolVersion(LEGACY_PROTOCOL_VERSION)),
+        };
+
+        let version: u64 = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .ok_or_else(|| {
+                ErrorResponse::bad_request(format!("Malformed {} header", PROTOCOL_VERSION_HEADER))
+            })?;
+
+        if version < MIN_PROTOCOL_VERSION {
+            return Err(ErrorResponse::unsupported_protocol_version(format!(
+                "The Rojo plugin uses protocol version {}, which is older than this server \
+                 supports. Update the plugin to use it with Rojo {}, which supports protocol \
+                 versions {} through {}.",
+                version, SERVER_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            )));
+        }
+
+        if version > PROTOCOL_VERSION {
+            return Err(ErrorResponse::unsupported_protocol_version(format!(
+                "The Rojo plugin uses protocol version {}, which is newer than this server \
+                 supports. Rojo {} supports protocol versions {} through {}; update Rojo to \
+                 use it with this plugin.",
+                version, SERVER_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            )));
+        }
+
+        Ok(ProtocolVersion(version))
+    }
+
+    pub fn number(self) -> u64 {
+        self.0
+    }
+
+    /// Whether changed string properties can be sent as a `TextPatch`.
+    pub fn supports_text_patches(self) -> bool {
+        self.0 >= 4
+    }
+
+    /// Whether reads can be split into pages with `limit` and `continuation`.
+    pub fn supports_paged_reads(self) -> bool {
+        self.0 >= 4
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(version: Option<&str>) -> Request<()> {
+        let mut builder = Request::builder();
+
+        if let Some(version) = version {
+            builder.header(PROTOCOL_VERSION_HEADER, version);
+        }
+
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn negotiate() {
+        let negotiate = |version: Option<&str>| ProtocolVersion::negotiate(&request(version)).ok();
+
+        assert_eq!(
+            negotiate(None),
+            Some(ProtocolVersion(LEGACY_PROTOCOL_VERSION))
+        );
+
+        for version in MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION {
+            assert_eq!(
+                negotiate(Some(&version.to_string())),
+                Some(ProtocolVersion(version))
+            );
+        }
+
+        assert_eq!(
+            negotiate(Some(&(MIN_PROTOCOL_VERSION - 1).to_string())),
+            None
+        );
+        assert_eq!(negotiate(Some(&(PROTOCOL_VERSION + 1).to_string())), None);
+        assert_eq!(negotiate(Some("four")), None);
+    }
+}
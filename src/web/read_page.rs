@@ -0,0 +1,190 @@
+//! Splits reads of large trees into pages, so that clients can load them a
+//! piece at a time and the tree isn't locked while a huge response is encoded.
+
+use std::collections::HashMap;
+
+use rbx_dom_weak::RbxId;
+
+use crate::snapshot::RojoTree;
+
+use super::interface::Instance;
+
+/// Where a paged read left off. Tokens are only valid while the tree stays the
+/// same, which is checked using the message cursor from when reading began.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Continuation {
+    pub message_cursor: u32,
+    pub next_id: RbxId,
+}
+
+impl Continuation {
+    pub fn parse(token: &str) -> Option<Self> {
+        let mut parts = token.splitn(2, '-');
+        let message_cursor = parts.next()?.parse().ok()?;
+        let next_id = RbxId::parse_str(parts.next()?)?;
+
+        Some(Continuation {
+            message_cursor,
+            next_id,
+        })
+    }
+
+    pub fn to_token(self) -> String {
+        format!("{}-{}", self.message_cursor, self.next_id)
+    }
+}
+
+/// Copies up to `limit` instances from the given roots and their descendants,
+/// in depth-first order, starting from `start` if it's given.
+///
+/// Returns the instances along with the ID of the next instance to read if
+/// there are any left, or `None` if `start` isn't below any of the roots.
+pub fn read_page(
+    tree: &RojoTree,
+    roots: &[RbxId],
+    start: Option<RbxId>,
+    limit: usize,
+) -> Option<(HashMap<RbxId, Instance<'static>>, Option<RbxId>)> {
+    // Each entry is a list of siblings and the index of the next one to visit.
+    let mut stack: Vec<(&[RbxId], usize)> = match start {
+        Some(start) => resume_stack(tree, roots, start)?,
+        None => vec![(roots, 0)],
+    };
+
+    let mut instances = HashMap::new();
+
+    while let Some((siblings, index)) = stack.last_mut() {
+        let id = match siblings.get(*index) {
+            Some(&id) => id,
+            None => {
+                stack.pop();
+                continue;
+            }
+        };
+
+        if instances.len() >= limit {
+            return Some((instances, Some(id)));
+        }
+
+        *index += 1;
+
+        if let Some(instance) = tree.get_instance(id) {
+            instances.insert(id, Instance::from_rojo_instance(instance).into_owned());
+            stack.push((instance.children(), 0));
+        }
+    }
+
+    Some((instances, None))
+}
+
+/// Rebuilds the stack that `read_page` had when it reached `start`, by walking
+/// up from it to the root that it's under.
+fn resume_stack<'a>(
+    tree: &'a RojoTree,
+    roots: &'a [RbxId],
+    start: RbxId,
+) -> Option<Vec<(&'a [RbxId], usize)>> {
+    let mut stack = Vec::new();
+    let mut current = start;
+
+    // `start` is the next instance to visit, but its ancestors have already
+    // been visited, so their entries need to point at the sibling after them.
+    let mut skip = 0;
+
+    loop {
+        if let Some(index) = roots.iter().position(|&root| root == current) {
+            stack.push((roots, index + skip));
+            break;
+        }
+
+        let parent_id = tree.get_instance(current)?.parent()?;
+        let siblings = tree.get_instance(parent_id)?.children();
+        let index = siblings.iter().position(|&id| id == current)?;
+
+        stack.push((siblings, index + skip));
+        current = parent_id;
+        skip = 1;
+    }
+
+    stack.reverse();
+    Some(stack)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rbx_dom_weak::RbxInstanceProperties;
+
+    use crate::snapshot::InstancePropertiesWithMeta;
+
+    fn folder(name: &str) -> InstancePropertiesWithMeta {
+        InstancePropertiesWithMeta {
+            properties: RbxInstanceProperties {
+                name: name.to_owned(),
+                class_name: "Folder".to_owned(),
+                properties: HashMap::new(),
+            },
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn pages_cover_tree_once() {
+        let mut tree = RojoTree::new(folder("Root"));
+        let root_id = tree.get_root_id();
+
+        for i in 0..3 {
+            let child_id = tree.insert_instance(folder(&format!("Child{}", i)), root_id);
+
+            for j in 0..4 {
+                tree.insert_instance(folder(&format!("Grandchild{}{}", i, j)), child_id);
+            }
+        }
+
+        let everything = read_page(&tree, &[root_id], None, usize::MAX).unwrap().0;
+        assert_eq!(everything.len(), 16);
+
+        let mut names = Vec::new();
+        let mut start = None;
+        let mut pages = 0;
+
+        loop {
+            let (instances, next) = read_page(&tree, &[root_id], start, 5).unwrap();
+            assert!(instances.len() <= 5);
+
+            names.extend(instances.values().map(|instance| instance.name.to_string()));
+            pages += 1;
+
+            match next {
+                Some(next) => start = Some(next),
+                None => break,
+            }
+        }
+
+        assert_eq!(pages, 4);
+
+        let mut expected: Vec<_> = everything
+            .values()
+            .map(|instance| instance.name.to_string())
+            .collect();
+        expected.sort();
+        names.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn continuation_tokens() {
+        let continuation = Continuation {
+            message_cursor: 12,
+            next_id: RbxId::new(),
+        };
+
+        assert_eq!(
+            Continuation::parse(&continuation.to_token()),
+            Some(continuation)
+        );
+        assert_eq!(Continuation::parse("12"), None);
+        assert_eq!(Continuation::parse("x-y"), None);
+    }
+}
@@ -0,0 +1,330 @@
+//! Computes the edits that turn one version of a string property into another,
+//! so that small changes to large scripts don't need the whole script sent to
+//! clients again.
+
+use super::interface::{TextEdit, TextPatch};
+
+/// Strings shorter than this are always sent in full.
+const MIN_PATCHED_LENGTH: usize = 1024;
+
+/// How many lines can be inserted or removed before giving up on finding a
+/// patch. The diff takes time and memory quadratic in this number, and patches
+/// with this many changes rarely end up much smaller than the new value.
+const MAX_CHANGED_LINES: usize = 1000;
+
+/// Bytes that each edit costs over the text it inserts, roughly matching how
+/// much space its offsets take up once encoded.
+const EDIT_OVERHEAD: usize = 32;
+
+/// Computes a patch from `old` to `new`, if there's one that's worth sending
+/// instead of the whole new value.
+pub fn text_patch(old: &str, new: &str) -> Option<TextPatch> {
+    if new.len() < MIN_PATCHED_LENGTH {
+        return None;
+    }
+
+    let edits = diff_lines(old, new)?;
+
+    let patch_size: usize = edits
+        .iter()
+        .map(|edit| edit.text.len() + EDIT_OVERHEAD)
+        .sum();
+
+    if patch_size * 2 > new.len() {
+        return None;
+    }
+
+    Some(TextPatch {
+        base_length: old.len(),
+        base_hash: fnv1a(old),
+        edits,
+    })
+}
+
+/// Hashes a string with 32-bit FNV-1a, which is simple enough for clients to
+/// compute too.
+fn fnv1a(value: &str) -> u32 {
+    value.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+impl TextEdit {
+    fn new(start: usize) -> Self {
+        TextEdit {
+            start,
+            length: 0,
+            text: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Finds the smallest set of whole lines to remove from `old` and insert into
+/// it to produce `new`, returned as byte ranges of `old` to replace.
+fn diff_lines(old: &str, new: &str) -> Option<Vec<TextEdit>> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+
+    // Most changes only touch a few lines in the middle of a file, so the
+    // lines that are the same at each end are skipped before diffing.
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old_lines[prefix..old_lines.len() - suffix];
+    let new_middle = &new_lines[prefix..new_lines.len() - suffix];
+
+    let ops = shortest_edit(old_middle, new_middle)?;
+
+    let mut edits = Vec::new();
+    let mut current: Option<TextEdit> = None;
+    let mut offset: usize = old_lines[..prefix].iter().map(|line| line.len()).sum();
+    let mut old_index = 0;
+    let mut new_index = 0;
+
+    for op in ops {
+        match op {
+            Op::Equal => {
+                edits.extend(current.take());
+                offset += old_middle[old_index].len();
+                old_index += 1;
+                new_index += 1;
+            }
+            Op::Delete => {
+                let edit = current.get_or_insert_with(|| TextEdit::new(offset));
+                edit.length += old_middle[old_index].len();
+                offset += old_middle[old_index].len();
+                old_index += 1;
+            }
+            Op::Insert => {
+                let edit = current.get_or_insert_with(|| TextEdit::new(offset));
+                edit.text.push_str(new_middle[new_index]);
+                new_index += 1;
+            }
+        }
+    }
+
+    edits.extend(current);
+
+    Some(edits)
+}
+
+/// Splits a string into lines, keeping the newline at the end of each one so
+/// that the lines add up to the whole string.
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+
+    for (index, _) in text.match_indices('\n') {
+        lines.push(&text[start..=index]);
+        start = index + 1;
+    }
+
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+
+    lines
+}
+
+/// Finds the shortest sequence of operations that turns `a` into `b` using
+/// Myers' diff algorithm, or `None` if it needs more than `MAX_CHANGED_LINES`
+/// insertions and deletions.
+fn shortest_edit(a: &[&str], b: &[&str]) -> Option<Vec<Op>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let limit = MAX_CHANGED_LINES.min(a.len() + b.len()) as isize;
+
+    // `v[k + offset]` is the furthest index into `a` reached on diagonal `k`.
+    let offset = limit + 1;
+    let mut v = vec![0; 2 * offset as usize + 1];
+
+    // The part of `v` that each step used, kept to retrace the path that
+    // reached the end.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    for d in 0..=limit {
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[index] = x;
+
+            if x >= n && y >= m {
+                trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+    }
+
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let mut x = n;
+    let mut y = m;
+
+    for d in (1..trace.len() as isize).rev() {
+        let previous = &trace[(d - 1) as usize];
+        let furthest = |k: isize| previous[(k + d - 1) as usize];
+
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+
+        let previous_x = furthest(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+
+        ops.push(if x == previous_x {
+            Op::Insert
+        } else {
+            Op::Delete
+        });
+
+        x = previous_x;
+        y = previous_y;
+    }
+
+    for _ in 0..x {
+        ops.push(Op::Equal);
+    }
+
+    ops.reverse();
+    ops
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn apply(old: &str, edits: &[TextEdit]) -> String {
+        let mut output = String::new();
+        let mut position = 0;
+
+        for edit in edits {
+            output.push_str(&old[position..edit.start]);
+            output.push_str(&edit.text);
+            position = edit.start + edit.length;
+        }
+
+        output.push_str(&old[position..]);
+        output
+    }
+
+    fn numbered_lines(count: usize) -> Vec<String> {
+        (0..count)
+            .map(|index| format!("local value{} = {}\n", index, index))
+            .collect()
+    }
+
+    #[test]
+    fn diff_round_trips() {
+        let cases = [
+            ("", "a\nb\n"),
+            ("a\nb\n", ""),
+            ("a\nb\nc\n", "a\nc\n"),
+            ("a\nb\nc", "a\nB\nc"),
+            ("a\nb\nc\nd\ne\n", "x\nb\nc\nd\ny\n"),
+            ("a\nb\n", "b\na\n"),
+            ("no newline", "no newline at all"),
+        ];
+
+        for (old, new) in &cases {
+            let edits = diff_lines(old, new).unwrap();
+            assert_eq!(apply(old, &edits), *new, "diffing {:?} to {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn one_changed_line() {
+        let mut lines = numbered_lines(5000);
+        let old = lines.concat();
+
+        lines[2500] = "local changed = true\n".to_owned();
+        lines.insert(4000, "print(\"hello\")\n".to_owned());
+        let new = lines.concat();
+
+        let patch = text_patch(&old, &new).unwrap();
+        assert_eq!(patch.base_length, old.len());
+        assert_eq!(patch.base_hash, fnv1a(&old));
+        assert_eq!(patch.edits.len(), 2);
+        assert_eq!(patch.edits[0].text, "local changed = true\n");
+        assert_eq!(patch.edits[1].text, "print(\"hello\")\n");
+        assert_eq!(apply(&old, &patch.edits), new);
+    }
+
+    #[test]
+    fn not_worth_patching() {
+        assert!(text_patch("short", "shorter").is_none());
+
+        let old = numbered_lines(200).concat();
+        let new = old.replace("local", "global");
+        assert!(text_patch(&old, &new).is_none());
+    }
+
+    #[test]
+    fn hashes() {
+        // Clients compute the same hashes, so these shouldn't change.
+        assert_eq!(fnv1a(""), 0x811c_9dc5);
+        assert_eq!(fnv1a("a"), 0xe40c_292c);
+        assert_eq!(fnv1a("foobar"), 0xbf9c_f968);
+    }
+
+    #[test]
+    fn stale_base_with_same_length() {
+        let mut lines = numbered_lines(100);
+        let old = lines.concat();
+
+        // A client that changed a value without changing its length can't
+        // tell that it's stale from the length alone.
+        let stale = old.replacen("value1 = 1", "value1 = 7", 1);
+        assert_eq!(stale.len(), old.len());
+
+        lines[50] = "local changed = true\n".to_owned();
+        let new = lines.concat();
+
+        let patch = text_patch(&old, &new).unwrap();
+        assert_eq!(patch.base_length, stale.len());
+        assert_ne!(patch.base_hash, fnv1a(&stale));
+        assert_eq!(patch.base_hash, fnv1a(&old));
+    }
+}
@@ -1,18 +1,17 @@
 //! Defines the HTTP-based UI. These endpoints generally return HTML and SVG.
 
-use std::{borrow::Cow, sync::Arc, time::Duration};
+use std::{sync::Arc, time::Duration};
 
 use futures::{future, Future};
 use hyper::{header, service::Service, Body, Method, Request, Response, StatusCode};
-use maplit::hashmap;
-use rbx_dom_weak::{RbxId, RbxValue};
-use ritz::{html, Fragment, HtmlContent, HtmlSelfClosingTag};
+use ritz::{html, HtmlContent};
 
 use crate::{
     serve_session::ServeSession,
-    snapshot::RojoTree,
     web::{
         assets,
+        clients::Clients,
+        dashboard,
         interface::{ErrorResponse, SERVER_VERSION},
         util::json,
     },
@@ -20,6 +19,7 @@ use crate::{
 
 pub struct UiService {
     serve_session: Arc<ServeSession>,
+    clients: Arc<Clients>,
 }
 
 impl Service for UiService {
@@ -33,6 +33,17 @@ impl Service for UiService {
             (&Method::GET, "/") => self.handle_home(),
             (&Method::GET, "/logo.png") => self.handle_logo(),
             (&Method::GET, "/icon.png") => self.handle_icon(),
+            (&Method::GET, "/dashboard") => self.handle_dashboard(),
+            (&Method::GET, "/dashboard/events") => {
+                dashboard::events(Arc::clone(&self.serve_session), Arc::clone(&self.clients))
+            }
+            (&Method::GET, "/dashboard/tree") => return dashboard::tree(&self.serve_session),
+            (&Method::GET, path) if path.starts_with("/dashboard/instance/") => {
+                return dashboard::instance(
+                    &self.serve_session,
+                    &path["/dashboard/instance/".len()..],
+                )
+            }
             (&Method::GET, "/show-instances") => self.handle_show_instances(),
             (_method, path) => {
                 return json(
@@ -47,8 +58,11 @@ impl Service for UiService {
 }
 
 impl UiService {
-    pub fn new(serve_session: Arc<ServeSession>) -> Self {
-        UiService { serve_session }
+    pub fn new(serve_session: Arc<ServeSession>, clients: Arc<Clients>) -> Self {
+        UiService {
+            serve_session,
+            clients,
+        }
     }
 
     fn handle_logo(&self) -> Response<Body> {
@@ -69,7 +83,7 @@ impl UiService {
         let page = self.normal_page(html! {
             <div class="button-list">
                 { Self::button("Rojo Documentation", "https://rojo.space/docs") }
-                { Self::button("View instance tree state", "/show-instances") }
+                { Self::button("Open live dashboard", "/dashboard") }
             </div>
         });
 
@@ -79,12 +93,38 @@ impl UiService {
             .unwrap()
     }
 
-    fn handle_show_instances(&self) -> Response<Body> {
-        let tree = self.serve_session.tree();
-        let root_id = tree.get_root_id();
-
+    fn handle_dashboard(&self) -> Response<Body> {
         let page = self.normal_page(html! {
-            { Self::instance(&tree, root_id) }
+            <>
+                <div class="dashboard">
+                    <section class="dashboard-section dashboard-tree">
+                        <h1 class="section-title">"Instances"</h1>
+                        <input id="instance-search" class="search" type="search" placeholder="Search by name or class" />
+                        <div id="instance-tree" class="instance-tree"></div>
+                    </section>
+                    <section class="dashboard-section dashboard-inspector">
+                        <h1 class="section-title">"Inspector"</h1>
+                        <div id="inspector" class="inspector">
+                            <p class="placeholder">"Select an instance to see its properties."</p>
+                        </div>
+                    </section>
+                    <section class="dashboard-section dashboard-wide">
+                        <h1 class="section-title">"Errors and Warnings"</h1>
+                        <ul id="diagnostics" class="diagnostics"></ul>
+                    </section>
+                    <section class="dashboard-section dashboard-wide">
+                        <h1 class="section-title">"Connected Clients"</h1>
+                        <ul id="clients" class="clients"></ul>
+                    </section>
+                    <section class="dashboard-section dashboard-wide">
+                        <h1 class="section-title">"Recent Patches"</h1>
+                        <ul id="patches" class="patches"></ul>
+                    </section>
+                </div>
+                <script>
+                    { ritz::UnescapedText::new(assets::dashboard_js()) }
+                </script>
+            </>
         });
 
         Response::builder()
@@ -93,128 +133,14 @@ impl UiService {
             .unwrap()
     }
 
-    fn instance(tree: &RojoTree, id: RbxId) -> HtmlContent<'_> {
-        let instance = tree.get_instance(id).unwrap();
-        let children_list: Vec<_> = instance
-            .children()
-            .iter()
-            .copied()
-            .map(|id| Self::instance(tree, id))
-            .collect();
-
-        let children_container = if children_list.is_empty() {
-            HtmlContent::None
-        } else {
-            let section = ExpandableSection {
-                title: "Children",
-                class_name: "instance-children",
-                id,
-                expanded: true,
-                content: html! {
-                    { Fragment::new(children_list) }
-                },
-            };
-
-            section.render()
-        };
-
-        let mut properties: Vec<_> = instance.properties().iter().collect();
-        properties.sort_by_key(|pair| pair.0);
-
-        let property_list: Vec<_> = properties
-            .into_iter()
-            .map(|(key, value)| {
-                html! {
-                    <div class="instance-property" title={ Self::display_value(value) }>
-                        { key.clone() } ": " { format!("{:?}", value.get_type()) }
-                    </div>
-                }
-            })
-            .collect();
-
-        let property_container = if property_list.is_empty() {
-            HtmlContent::None
-        } else {
-            let section = ExpandableSection {
-                title: "Properties",
-                class_name: "instance-properties",
-                id,
-                expanded: false,
-                content: html! {
-                    { Fragment::new(property_list) }
-                },
-            };
-
-            section.render()
-        };
-
-        let metadata_container = {
-            let metadata = instance.metadata();
-
-            let relevant_paths = if metadata.relevant_paths.is_empty() {
-                HtmlContent::None
-            } else {
-                let list = metadata
-                    .relevant_paths
-                    .iter()
-                    .map(|path| html! { <li>{ format!("{}", path.display()) }</li> });
-
-                html! {
-                    <div>
-                        "relevant_paths: "
-                        <ul class="path-list">{ Fragment::new(list) }</ul>
-                    </div>
-                }
-            };
-
-            let content = html! {
-                <>
-                    <div>"ignore_unknown_instances: " { metadata.ignore_unknown_instances.to_string() }</div>
-                    <div>"instigating source: " { format!("{:?}", metadata.instigating_source) }</div>
-                    { relevant_paths }
-                </>
-            };
-
-            let section = ExpandableSection {
-                title: "Metadata",
-                class_name: "instance-metadata",
-                id,
-                expanded: false,
-                content,
-            };
-
-            section.render()
-        };
-
-        let class_name_specifier = if instance.name() == instance.class_name() {
-            HtmlContent::None
-        } else {
-            html! {
-                <span>
-                    " (" { instance.class_name().to_owned() } ")"
-                </span>
-            }
-        };
-
-        html! {
-            <div class="instance">
-                <label class="instance-title" for={ format!("instance-{}", id) }>
-                    { instance.name().to_owned() }
-                    { class_name_specifier }
-                </label>
-                { metadata_container }
-                { property_container }
-                { children_container }
-            </div>
-        }
-    }
-
-    fn display_value(value: &RbxValue) -> String {
-        match value {
-            RbxValue::String { value } => value.clone(),
-            RbxValue::Bool { value } => value.to_string(),
-            _ => format!("{:?}", value),
-        }
+    /// The dashboard replaced the static instance page, but links to the old
+    /// page should keep working.
+    fn handle_show_instances(&self) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::MOVED_PERMANENTLY)
+            .header(header::LOCATION, "/dashboard")
+            .body(Body::empty())
+            .unwrap()
     }
 
     fn stat_item<S: Into<String>>(name: &str, value: S) -> HtmlContent<'_> {
@@ -284,50 +210,3 @@ impl UiService {
         }
     }
 }
-
-struct ExpandableSection<'a> {
-    title: &'a str,
-    class_name: &'a str,
-    id: RbxId,
-    expanded: bool,
-    content: HtmlContent<'a>,
-}
-
-impl<'a> ExpandableSection<'a> {
-    fn render(self) -> HtmlContent<'a> {
-        let input_id = format!("{}-{}", self.class_name, self.id);
-
-        // We need to specify this input manually because Ritz doesn't have
-        // support for conditional attributes like `checked`.
-        let mut input = HtmlSelfClosingTag {
-            name: Cow::Borrowed("input"),
-            attributes: hashmap! {
-                Cow::Borrowed("class") => Cow::Borrowed("expandable-input"),
-                Cow::Borrowed("id") => Cow::Owned(input_id.clone()),
-                Cow::Borrowed("type") => Cow::Borrowed("checkbox"),
-            },
-        };
-
-        if self.expanded {
-            input
-                .attributes
-                .insert(Cow::Borrowed("checked"), Cow::Borrowed("checked"));
-        }
-
-        html! {
-            <section class="expandable-section">
-                { input }
-
-                <h1 class="expandable-label">
-                    <label for={ input_id.clone() }>
-                        <span class="expandable-visualizer"></span>
-                        { self.title }
-                    </label>
-                </h1>
-                <div class={ format!("expandable-items {}", self.class_name) }>
-                    { self.content }
-                </div>
-            </section>
-        }
-    }
-}
@@ -1,17 +1,187 @@
+use std::io::Write;
+
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
 use futures::{future, Future};
-use hyper::{header::CONTENT_TYPE, Body, Response, StatusCode};
+use hyper::{
+    header::{HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY},
+    Body, Request, Response, StatusCode,
+};
 use serde::Serialize;
 
+/// The media types that API responses can be encoded as, in order of
+/// preference.
+pub const RESPONSE_FORMATS: &[&str] = &["application/msgpack", "application/json"];
+
+/// The content codings that API responses can be compressed with, in order of
+/// preference.
+pub const CONTENT_CODINGS: &[&str] = &["gzip", "deflate"];
+
+/// Bodies smaller than this aren't worth compressing.
+const MIN_COMPRESSED_SIZE: usize = 1024;
+
+/// How a response body should be encoded, based on the `Accept` and
+/// `Accept-Encoding` headers of the request it's responding to.
+///
+/// Clients that don't ask for anything else get uncompressed JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encoding {
+    format: Format,
+    coding: Option<Coding>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Json,
+    MessagePack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Coding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn negotiate<B>(request: &Request<B>) -> Self {
+        let headers = request.headers();
+
+        let accepted = |name, value: &str| {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|header| header.to_str().ok())
+                .any(|header| lists_token(header, value))
+        };
+
+        let format = if accepted(ACCEPT, "application/msgpack")
+            || accepted(ACCEPT, "application/x-msgpack")
+        {
+            Format::MessagePack
+        } else {
+            Format::Json
+        };
+
+        let coding = if accepted(ACCEPT_ENCODING, "gzip") {
+            Some(Coding::Gzip)
+        } else if accepted(ACCEPT_ENCODING, "deflate") {
+            Some(Coding::Deflate)
+        } else {
+            None
+        };
+
+        Encoding { format, coding }
+    }
+
+    pub fn respond<T: Serialize>(
+        self,
+        value: T,
+        code: StatusCode,
+    ) -> Box<dyn Future<Item = hyper::Response<hyper::Body>, Error = hyper::Error> + Send> {
+        Box::new(future::ok(self.response(value, code)))
+    }
+
+    pub fn respond_ok<T: Serialize>(
+        self,
+        value: T,
+    ) -> Box<dyn Future<Item = hyper::Response<hyper::Body>, Error = hyper::Error> + Send> {
+        self.respond(value, StatusCode::OK)
+    }
+
+    fn response<T: Serialize>(self, value: T, code: StatusCode) -> Response<Body> {
+        let (content_type, serialized) = match self.format {
+            Format::Json => (
+                "application/json",
+                serde_json::to_vec(&value).map_err(|err| err.to_string()),
+            ),
+            // Structs are written as maps keyed by field name, the same way
+            // they are in JSON, so that clients can decode either format into
+            // the same shapes.
+            Format::MessagePack => (
+                "application/msgpack",
+                rmp_serde::to_vec_named(&value).map_err(|err| err.to_string()),
+            ),
+        };
+
+        let serialized = match serialized {
+            Ok(v) => v,
+            Err(err) => return error_response(err),
+        };
+
+        let mut response = Response::builder();
+        response
+            .status(code)
+            .header(CONTENT_TYPE, content_type)
+            .header(VARY, HeaderValue::from_static("Accept, Accept-Encoding"));
+
+        let body = match self.coding {
+            Some(coding) if serialized.len() >= MIN_COMPRESSED_SIZE => {
+                let (name, compressed) = match coding {
+                    Coding::Gzip => ("gzip", gzip(&serialized)),
+                    Coding::Deflate => ("deflate", deflate(&serialized)),
+                };
+
+                response.header(CONTENT_ENCODING, name);
+                compressed
+            }
+            _ => serialized,
+        };
+
+        response.body(Body::from(body)).unwrap()
+    }
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// HTTP's `deflate` coding is the zlib format, not raw deflate.
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Tells whether a header value like `Accept` or `Accept-Encoding` lists the
+/// given token without ruling it out with `q=0`.
+fn lists_token(header: &str, token: &str) -> bool {
+    header.split(',').any(|item| {
+        let mut parts = item.split(';').map(str::trim);
+
+        if !parts.next().unwrap_or("").eq_ignore_ascii_case(token) {
+            return false;
+        }
+
+        parts
+            .filter_map(|param| {
+                let mut pair = param.splitn(2, '=');
+                match (pair.next(), pair.next()) {
+                    (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("q") => {
+                        value.trim().parse::<f32>().ok()
+                    }
+                    _ => None,
+                }
+            })
+            .all(|quality| quality > 0.0)
+    })
+}
+
+fn error_response(message: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(message))
+        .unwrap()
+}
+
 fn response_json<T: Serialize>(value: T, code: StatusCode) -> Response<Body> {
     let serialized = match serde_json::to_string(&value) {
         Ok(v) => v,
-        Err(err) => {
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .header(CONTENT_TYPE, "text/plain")
-                .body(Body::from(err.to_string()))
-                .unwrap();
-        }
+        Err(err) => return error_response(err.to_string()),
     };
 
     Response::builder()
@@ -28,8 +198,118 @@ pub fn json<T: Serialize>(
     Box::new(future::ok(response_json(value, code)))
 }
 
-pub fn json_ok<T: Serialize>(
-    value: T,
-) -> Box<dyn Future<Item = hyper::Response<hyper::Body>, Error = hyper::Error> + Send> {
-    json(value, StatusCode::OK)
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::{collections::HashMap, io::Read};
+
+    use flate2::read::GzDecoder;
+    use futures::Stream;
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut builder = Request::builder();
+
+        for (name, value) in headers {
+            builder.header(*name, *value);
+        }
+
+        builder.body(()).unwrap()
+    }
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(
+            Encoding::negotiate(&request(&[])),
+            Encoding {
+                format: Format::Json,
+                coding: None,
+            }
+        );
+
+        assert_eq!(
+            Encoding::negotiate(&request(&[
+                ("Accept", "application/msgpack, application/json;q=0.5"),
+                ("Accept-Encoding", "deflate, gzip;q=0.8"),
+            ])),
+            Encoding {
+                format: Format::MessagePack,
+                coding: Some(Coding::Gzip),
+            }
+        );
+
+        assert_eq!(
+            Encoding::negotiate(&request(&[
+                ("Accept", "application/msgpack;q=0"),
+                ("Accept-Encoding", "gzip;q=0, deflate"),
+            ])),
+            Encoding {
+                format: Format::Json,
+                coding: Some(Coding::Deflate),
+            }
+        );
+    }
+
+    #[test]
+    fn compress_large_bodies() {
+        let encoding = Encoding::negotiate(&request(&[("Accept-Encoding", "gzip")]));
+
+        let small = encoding.response("small", StatusCode::OK);
+        assert!(small.headers().get(CONTENT_ENCODING).is_none());
+
+        let value = "a".repeat(MIN_COMPRESSED_SIZE);
+        let large = encoding.response(&value, StatusCode::OK);
+        assert_eq!(large.headers()[CONTENT_ENCODING], "gzip");
+
+        let body = large.into_body().concat2().wait().unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+
+        assert_eq!(decompressed, serde_json::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn msgpack_matches_json() {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Example {
+            some_list: Vec<u8>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            skipped: Option<u8>,
+            unit: Kind,
+            newtype: Kind,
+            #[serde(flatten)]
+            rest: HashMap<String, f64>,
+        }
+
+        #[derive(Serialize)]
+        enum Kind {
+            Unit,
+            Newtype(bool),
+        }
+
+        let mut rest = HashMap::new();
+        for index in 0..20 {
+            rest.insert(format!("k{:02}", index), f64::from(index) / 2.0);
+        }
+
+        let value = Example {
+            some_list: vec![1, 2],
+            skipped: None,
+            unit: Kind::Unit,
+            newtype: Kind::Newtype(false),
+            rest,
+        };
+
+        let encoding = Encoding::negotiate(&request(&[("Accept", "application/msgpack")]));
+        let response = encoding.response(&value, StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/msgpack");
+
+        let body = response.into_body().concat2().wait().unwrap();
+        let decoded: serde_json::Value = rmp_serde::from_slice(&body).unwrap();
+
+        assert_eq!(decoded, serde_json::to_value(&value).unwrap());
+    }
 }
@@ -0,0 +1,383 @@
+//! Guards the changes that `rojo serve` makes to the filesystem on behalf of
+//! clients like the Rojo Studio plugin.
+
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use memofs::{IoResultExt, Vfs};
+use snafu::{ResultExt, Snafu};
+
+use crate::project::Project;
+
+/// How many backups are kept before the oldest ones are deleted.
+const MAX_BACKUPS: usize = 200;
+
+#[derive(Debug, Snafu)]
+pub enum WriteError {
+    #[snafu(display("Refusing to change {}, it is outside of the project", path.display()))]
+    OutsideProject { path: PathBuf },
+
+    #[snafu(display("Refusing to change {}, it is a project file", path.display()))]
+    ProjectFile { path: PathBuf },
+
+    #[snafu(display("Could not back up {}: {}", path.display(), source))]
+    Backup { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Could not change {}: {}", path.display(), source))]
+    Io { path: PathBuf, source: io::Error },
+}
+
+/// Restricts the files that can be written or removed to the files and folders
+/// that make up a project, and keeps a copy of each file before it's changed so that
+/// changes made by a misbehaving client can be undone.
+///
+/// Backups are stored in a folder per change, named by when the change was
+/// made, and only the most recent `MAX_BACKUPS` are kept.
+#[derive(Debug)]
+pub struct WriteSandbox {
+    roots: Vec<PathBuf>,
+    backup_dir: Option<PathBuf>,
+    backup_count: AtomicUsize,
+}
+
+impl WriteSandbox {
+    /// Creates a sandbox that allows changes to the given files and to any file
+    /// under the given folders, except for project files, without keeping
+    /// backups.
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self {
+            roots,
+            backup_dir: None,
+            backup_count: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn backup_dir(self, backup_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            backup_dir: Some(backup_dir.into()),
+            ..self
+        }
+    }
+
+    pub fn write(&self, vfs: &Vfs, path: &Path, contents: &[u8]) -> Result<(), WriteError> {
+        let relative_path = self.check_path(vfs, path)?;
+        self.back_up(vfs, path, &relative_path)?;
+
+        vfs.write(path, contents).context(Io { path })
+    }
+
+    pub fn remove_file(&self, vfs: &Vfs, path: &Path) -> Result<(), WriteError> {
+        let relative_path = self.check_path(vfs, path)?;
+        self.back_up(vfs, path, &relative_path)?;
+
+        vfs.remove_file(path).context(Io { path })
+    }
+
+    /// Checks that the given path is one of the sandbox's roots or inside one
+    /// of them, returning the path relative to that root.
+    fn check_path(&self, vfs: &Vfs, path: &Path) -> Result<PathBuf, WriteError> {
+        // Paths that go up a folder could end up anywhere, so they're never
+        // allowed, even if they start inside one of the roots.
+        let goes_up = path
+            .components()
+            .any(|component| component == Component::ParentDir);
+
+        if goes_up {
+            return OutsideProject { path }.fail();
+        }
+
+        // Symlinks inside of a root can point anywhere, so both the path and
+        // the roots are compared with their symlinks resolved.
+        let resolved_path = resolve_symlinks(path);
+
+        if Project::is_project_file(path) || Project::is_project_file(&resolved_path) {
+            return ProjectFile { path }.fail();
+        }
+
+        let relative_path = self.roots.iter().find_map(|root| {
+            let relative_path = resolved_path.strip_prefix(resolve_symlinks(root)).ok()?;
+
+            if relative_path.components().next().is_some() {
+                return Some(relative_path.to_path_buf());
+            }
+
+            // A root can also be a single file, which can then be changed
+            // itself.
+            let is_file = vfs
+                .metadata(path)
+                .map(|meta| meta.is_file())
+                .unwrap_or(false);
+
+            if is_file {
+                path.file_name().map(PathBuf::from)
+            } else {
+                None
+            }
+        });
+
+        relative_path.ok_or_else(|| WriteError::OutsideProject {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn back_up(&self, vfs: &Vfs, path: &Path, relative_path: &Path) -> Result<(), WriteError> {
+        let backup_dir = match &self.backup_dir {
+            Some(backup_dir) => backup_dir,
+            None => return Ok(()),
+        };
+
+        let contents = match vfs.read(path).with_not_found().context(Backup { path })? {
+            Some(contents) => contents,
+            None => return Ok(()),
+        };
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or(0);
+        let index = self.backup_count.fetch_add(1, Ordering::SeqCst);
+
+        // Both parts are padded so that sorting the names of the backups sorts
+        // them from oldest to newest.
+        let backup_path = backup_dir
+            .join(format!("{:020}-{:06}", millis, index))
+            .join(relative_path);
+
+        if let Some(parent) = backup_path.parent() {
+            fs::create_dir_all(parent).context(Backup { path })?;
+        }
+
+        fs::write(&backup_path, contents.as_slice()).context(Backup { path })?;
+
+        log::debug!("Backed up {} to {}", path.display(), backup_path.display());
+
+        prune_backups(backup_dir).context(Backup { path })
+    }
+}
+
+/// The folder, relative to the folder a project is in, that Rojo keeps its own
+/// files for that project in.
+pub const ROJO_FOLDER_NAME: &str = ".rojo";
+
+/// Picks the folder to keep backups for the project in the given folder in.
+pub fn default_backup_dir(project_folder: &Path) -> PathBuf {
+    project_folder.join(ROJO_FOLDER_NAME).join("backups")
+}
+
+/// Resolves the symlinks in the longest part of the given path that exists,
+/// keeping the rest of the path as it is.
+fn resolve_symlinks(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut missing = Vec::new();
+
+    loop {
+        if let Ok(resolved) = fs::canonicalize(existing) {
+            return missing
+                .iter()
+                .rev()
+                .fold(resolved, |resolved, name| resolved.join(name));
+        }
+
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name);
+                existing = parent;
+            }
+            _ => return path.to_path_buf(),
+        }
+    }
+}
+
+/// Deletes the oldest backups in the given folder until at most `MAX_BACKUPS`
+/// are left.
+fn prune_backups(backup_dir: &Path) -> io::Result<()> {
+    let mut backups = fs::read_dir(backup_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    if backups.len() <= MAX_BACKUPS {
+        return Ok(());
+    }
+
+    backups.sort();
+
+    for backup in &backups[..backups.len() - MAX_BACKUPS] {
+        fs::remove_dir_all(backup)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use memofs::{InMemoryFs, StdBackend, VfsSnapshot};
+    use tempfile::tempdir;
+
+    #[test]
+    fn outside_project() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/project/foo.lua", VfsSnapshot::file("foo"))
+            .unwrap();
+        imfs.load_snapshot("/elsewhere/bar.lua", VfsSnapshot::file("bar"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let sandbox = WriteSandbox::new(vec![PathBuf::from("/project")]);
+
+        let paths = [
+            "/elsewhere/bar.lua",
+            "/project/../elsewhere/bar.lua",
+            "/project",
+        ];
+
+        for path in &paths {
+            match sandbox.remove_file(&vfs, Path::new(path)) {
+                Err(WriteError::OutsideProject { .. }) => {}
+                other => panic!("expected {} to be outside project, got {:?}", path, other),
+            }
+        }
+
+        sandbox
+            .write(&vfs, Path::new("/project/foo.lua"), b"changed")
+            .unwrap();
+        assert_eq!(vfs.read("/project/foo.lua").unwrap().as_slice(), b"changed");
+        assert_eq!(vfs.read("/elsewhere/bar.lua").unwrap().as_slice(), b"bar");
+    }
+
+    #[test]
+    fn project_files() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/project/default.project.json", VfsSnapshot::file("{}"))
+            .unwrap();
+        imfs.load_snapshot("/project/nested.project.json", VfsSnapshot::file("{}"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let sandbox = WriteSandbox::new(vec![PathBuf::from("/project")]);
+
+        let paths = [
+            "/project/default.project.json",
+            "/project/nested.project.json",
+        ];
+
+        for path in &paths {
+            match sandbox.write(&vfs, Path::new(path), b"changed") {
+                Err(WriteError::ProjectFile { .. }) => {}
+                other => panic!("expected {} to be refused, got {:?}", path, other),
+            }
+        }
+
+        assert_eq!(
+            vfs.read("/project/default.project.json")
+                .unwrap()
+                .as_slice(),
+            b"{}"
+        );
+    }
+
+    #[test]
+    fn file_roots() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/project/foo.lua", VfsSnapshot::file("foo"))
+            .unwrap();
+        imfs.load_snapshot("/project/bar.lua", VfsSnapshot::file("bar"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let sandbox = WriteSandbox::new(vec![PathBuf::from("/project/foo.lua")]);
+
+        sandbox
+            .write(&vfs, Path::new("/project/foo.lua"), b"changed")
+            .unwrap();
+
+        match sandbox.write(&vfs, Path::new("/project/bar.lua"), b"changed") {
+            Err(WriteError::OutsideProject { .. }) => {}
+            other => panic!("expected bar.lua to be outside project, got {:?}", other),
+        }
+
+        assert_eq!(vfs.read("/project/foo.lua").unwrap().as_slice(), b"changed");
+        assert_eq!(vfs.read("/project/bar.lua").unwrap().as_slice(), b"bar");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_project() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempdir().unwrap();
+        let project = dir.path().join("project");
+        let elsewhere = dir.path().join("elsewhere");
+
+        fs::create_dir_all(project.join("src")).unwrap();
+        fs::create_dir(&elsewhere).unwrap();
+        fs::write(elsewhere.join("bar.lua"), "bar").unwrap();
+        symlink(&elsewhere, project.join("src/link")).unwrap();
+
+        let vfs = Vfs::new(StdBackend::new());
+        let sandbox = WriteSandbox::new(vec![project.join("src")]);
+
+        let paths = [
+            project.join("src/link/bar.lua"),
+            project.join("src/link/new.lua"),
+        ];
+
+        for path in &paths {
+            match sandbox.write(&vfs, path, b"changed") {
+                Err(WriteError::OutsideProject { .. }) => {}
+                other => panic!(
+                    "expected {} to be outside project, got {:?}",
+                    path.display(),
+                    other
+                ),
+            }
+        }
+
+        sandbox
+            .write(&vfs, &project.join("src/foo.lua"), b"foo")
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(elsewhere.join("bar.lua")).unwrap(),
+            "bar"
+        );
+        assert!(!elsewhere.join("new.lua").exists());
+    }
+
+    #[test]
+    fn backups() {
+        let mut imfs = InMemoryFs::new();
+        imfs.load_snapshot("/project/src/foo.lua", VfsSnapshot::file("first"))
+            .unwrap();
+
+        let vfs = Vfs::new(imfs);
+        let backup_dir = tempdir().unwrap();
+        let sandbox =
+            WriteSandbox::new(vec![PathBuf::from("/project")]).backup_dir(backup_dir.path());
+
+        let path = Path::new("/project/src/foo.lua");
+        sandbox.write(&vfs, path, b"second").unwrap();
+        sandbox.remove_file(&vfs, path).unwrap();
+
+        // Creating a file has nothing to back up.
+        sandbox.write(&vfs, path, b"third").unwrap();
+
+        let mut backups: Vec<_> = fs::read_dir(backup_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        backups.sort();
+
+        let contents: Vec<_> = backups
+            .iter()
+            .map(|backup| fs::read_to_string(backup.join("src/foo.lua")).unwrap())
+            .collect();
+
+        assert_eq!(contents, vec!["first", "second"]);
+    }
+}